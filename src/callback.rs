@@ -0,0 +1,177 @@
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::events::{
+    OnChangedEventArgs, OnCreatedEventArgs, OnDeletedEventArgs, OnRenamedEventArgs,
+};
+use crate::search_dir::{ChangedFile, File, RenamedFileEntry};
+use crate::{WatchSink, OPERATION};
+
+type BoxedCallback<T> = Box<dyn FnMut(T) + Send>;
+
+// a user callback that can be cloned into the watcher threads and called from any of them
+pub struct Callback<T>(Arc<Mutex<BoxedCallback<T>>>);
+
+impl<T> Callback<T> {
+    pub fn new(callback: BoxedCallback<T>) -> Self {
+        Self(Arc::new(Mutex::new(callback)))
+    }
+
+    pub fn call(&self, arg: T) {
+        (self.0.lock().unwrap())(arg)
+    }
+}
+
+impl<T> Clone for Callback<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<T> Debug for Callback<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Callback")
+    }
+}
+
+type BoxedSink = Box<dyn WatchSink + Send>;
+
+// a user sink shared with the events thread, files are handed over sorted by name like the
+// event args do
+#[derive(Clone)]
+pub struct Sink(Arc<Mutex<BoxedSink>>);
+
+impl Sink {
+    pub fn new(sink: BoxedSink) -> Self {
+        Self(Arc::new(Mutex::new(sink)))
+    }
+
+    // a batch is handed over as its non empty parts
+    pub fn send(&self, op: &OPERATION) {
+        let mut sink = self.0.lock().unwrap();
+
+        match op {
+            OPERATION::CREATE(files) => Self::send_created(&mut sink, files),
+            OPERATION::CHANGE(files) => Self::send_changed(&mut sink, files),
+            OPERATION::DELETE(files) => Self::send_deleted(&mut sink, files),
+            OPERATION::RENAME(files) => Self::send_renamed(&mut sink, files),
+            OPERATION::BATCH {
+                created,
+                changed,
+                deleted,
+                renamed,
+            } => {
+                Self::send_created(&mut sink, created);
+                Self::send_changed(&mut sink, changed);
+                Self::send_deleted(&mut sink, deleted);
+                Self::send_renamed(&mut sink, renamed);
+            }
+            OPERATION::ERROR(error) => sink.on_error(error),
+        }
+    }
+
+    fn send_created(sink: &mut BoxedSink, files: &HashSet<File>) {
+        if !files.is_empty() {
+            sink.on_created(OnCreatedEventArgs::new(files.clone()).files());
+        }
+    }
+
+    fn send_changed(sink: &mut BoxedSink, files: &HashSet<ChangedFile>) {
+        if !files.is_empty() {
+            sink.on_changed(OnChangedEventArgs::new(files.clone()).files());
+        }
+    }
+
+    fn send_deleted(sink: &mut BoxedSink, files: &HashSet<File>) {
+        if !files.is_empty() {
+            sink.on_deleted(OnDeletedEventArgs::new(files.clone()).files());
+        }
+    }
+
+    fn send_renamed(sink: &mut BoxedSink, files: &HashSet<RenamedFileEntry>) {
+        if !files.is_empty() {
+            sink.on_renamed(OnRenamedEventArgs::new(files.clone()).files());
+        }
+    }
+}
+
+impl Debug for Sink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sink")
+    }
+}
+
+pub type ChangeDetectorFn = Box<dyn Fn(&File, &File) -> bool + Send + Sync>;
+
+// decides whether a file changed between two snapshots, given the old and the new one
+#[derive(Clone)]
+pub struct ChangeDetector(Arc<ChangeDetectorFn>);
+
+impl ChangeDetector {
+    pub fn new(detector: ChangeDetectorFn) -> Self {
+        Self(Arc::new(detector))
+    }
+
+    pub fn is_changed(&self, old: &File, new: &File) -> bool {
+        (self.0)(old, new)
+    }
+}
+
+impl Debug for ChangeDetector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ChangeDetector")
+    }
+}
+
+pub type SpawnFn = Box<dyn Fn(Box<dyn FnOnce() + Send>) + Send + Sync>;
+
+// runs the poll and events work of a watcher, a thread of its own for each when not given
+#[derive(Clone, Default)]
+pub struct Spawner(Option<Arc<SpawnFn>>);
+
+impl Spawner {
+    pub fn new(spawn: SpawnFn) -> Self {
+        Self(Some(Arc::new(spawn)))
+    }
+
+    pub fn spawn<T: Send + 'static>(&self, work: impl FnOnce() -> T + Send + 'static) -> Worker<T> {
+        match &self.0 {
+            Some(spawn) => {
+                let (sender, receiver) = channel::<T>();
+                spawn(Box::new(move || {
+                    let _ = sender.send(work());
+                }));
+
+                Worker::External(Mutex::new(receiver))
+            }
+            None => Worker::Thread(thread::spawn(work)),
+        }
+    }
+}
+
+impl Debug for Spawner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Spawner")
+    }
+}
+
+// work handed to a Spawner, joined by waiting for its result
+#[derive(Debug)]
+pub enum Worker<T> {
+    Thread(JoinHandle<T>),
+    //behind a mutex for the watcher to stay Sync
+    External(Mutex<Receiver<T>>),
+}
+
+impl<T> Worker<T> {
+    // None when the work panicked
+    pub fn join(self) -> Option<T> {
+        match self {
+            Worker::Thread(handle) => handle.join().ok(),
+            Worker::External(receiver) => receiver.lock().unwrap().recv().ok(),
+        }
+    }
+}
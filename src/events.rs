@@ -1,70 +1,142 @@
 use std::collections::HashSet;
 use std::fmt::Debug;
 
-use crate::search_dir::{File, RenamedFileEntry};
+use crate::search_dir::{ChangedFile, File, RenamedFileEntry};
+
+// the files of an event are ordered by name, so callbacks see them in the same order every run
+pub(crate) trait Named {
+    fn name(&self) -> &str;
+}
+
+impl Named for File {
+    fn name(&self) -> &str {
+        self.name()
+    }
+}
+
+impl Named for ChangedFile {
+    fn name(&self) -> &str {
+        self.name()
+    }
+}
+
+impl Named for RenamedFileEntry {
+    fn name(&self) -> &str {
+        self.name()
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct OnCreatedEventArgs {
     args: BaseEventArgs<File>,
+    seq: u64,
 }
 
 impl OnCreatedEventArgs {
     pub fn new(files: HashSet<File>) -> Self {
         Self {
-            args: BaseEventArgs { files },
+            args: BaseEventArgs::new(files),
+            seq: 0,
         }
     }
 
-    pub fn files(&self) -> &HashSet<File> {
-        &self.args.files
+    pub(crate) fn with_seq(mut self, seq: u64) -> Self {
+        self.seq = seq;
+
+        self
+    }
+
+    // the events a watcher delivers are numbered from 1 across all its callbacks, in the order
+    // they are delivered; 0 for arguments that did not come from a watcher
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn files(&self) -> &[File] {
+        self.args.files()
     }
 }
 
 #[derive(Debug, Clone)]
-struct BaseEventArgs<T: Clone + Debug> {
-    files: HashSet<T>,
+struct BaseEventArgs<T: Clone + Debug + Named> {
+    files: Vec<T>,
 }
 
-impl<T: Clone + Debug> BaseEventArgs<T> {
+impl<T: Clone + Debug + Named> BaseEventArgs<T> {
     fn new(files: HashSet<T>) -> Self {
+        let mut files: Vec<T> = files.into_iter().collect();
+        files.sort_by(|a, b| a.name().cmp(b.name()));
+
         Self { files }
     }
 
-    fn files(&self) -> &HashSet<T> {
+    fn files(&self) -> &[T] {
         &self.files
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct OnChangedEventArgs {
-    args: BaseEventArgs<File>,
+    args: BaseEventArgs<ChangedFile>,
+    seq: u64,
 }
 
 impl OnChangedEventArgs {
-    pub fn new(files: HashSet<File>) -> Self {
+    pub fn new(files: HashSet<ChangedFile>) -> Self {
         Self {
             args: BaseEventArgs::new(files),
+            seq: 0,
         }
     }
 
-    pub fn files(&self) -> &HashSet<File> {
+    pub(crate) fn with_seq(mut self, seq: u64) -> Self {
+        self.seq = seq;
+
+        self
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn files(&self) -> &[ChangedFile] {
         self.args.files()
     }
+
+    // the file modified last, as of the new state of the files
+    pub fn most_recent(&self) -> Option<&File> {
+        self.files()
+            .iter()
+            .map(ChangedFile::file)
+            .max_by(|a, b| a.cmp_by_modified(b))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct OnDeletedEventArgs {
     args: BaseEventArgs<File>,
+    seq: u64,
 }
 
 impl OnDeletedEventArgs {
     pub fn new(files: HashSet<File>) -> Self {
         Self {
             args: BaseEventArgs::new(files),
+            seq: 0,
         }
     }
 
-    pub fn files(&self) -> &HashSet<File> {
+    pub(crate) fn with_seq(mut self, seq: u64) -> Self {
+        self.seq = seq;
+
+        self
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn files(&self) -> &[File] {
         self.args.files()
     }
 }
@@ -72,16 +144,80 @@ impl OnDeletedEventArgs {
 #[derive(Debug, Clone)]
 pub struct OnRenamedEventArgs {
     args: BaseEventArgs<RenamedFileEntry>,
+    seq: u64,
 }
 
 impl OnRenamedEventArgs {
     pub fn new(files: HashSet<RenamedFileEntry>) -> Self {
         Self {
             args: BaseEventArgs::new(files),
+            seq: 0,
         }
     }
 
-    pub fn files(&self) -> &HashSet<RenamedFileEntry> {
+    pub(crate) fn with_seq(mut self, seq: u64) -> Self {
+        self.seq = seq;
+
+        self
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn files(&self) -> &[RenamedFileEntry] {
         self.args.files()
     }
 }
+
+#[derive(Debug, Clone)]
+pub struct OnBatchEventArgs {
+    created: BaseEventArgs<File>,
+    changed: BaseEventArgs<ChangedFile>,
+    deleted: BaseEventArgs<File>,
+    renamed: BaseEventArgs<RenamedFileEntry>,
+    seq: u64,
+}
+
+impl OnBatchEventArgs {
+    pub fn new(
+        created: HashSet<File>,
+        changed: HashSet<ChangedFile>,
+        deleted: HashSet<File>,
+        renamed: HashSet<RenamedFileEntry>,
+    ) -> Self {
+        Self {
+            created: BaseEventArgs::new(created),
+            changed: BaseEventArgs::new(changed),
+            deleted: BaseEventArgs::new(deleted),
+            renamed: BaseEventArgs::new(renamed),
+            seq: 0,
+        }
+    }
+
+    pub(crate) fn with_seq(mut self, seq: u64) -> Self {
+        self.seq = seq;
+
+        self
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn created(&self) -> &[File] {
+        self.created.files()
+    }
+
+    pub fn changed(&self) -> &[ChangedFile] {
+        self.changed.files()
+    }
+
+    pub fn deleted(&self) -> &[File] {
+        self.deleted.files()
+    }
+
+    pub fn renamed(&self) -> &[RenamedFileEntry] {
+        self.renamed.files()
+    }
+}
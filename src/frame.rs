@@ -0,0 +1,231 @@
+use std::collections::HashSet;
+use std::fmt::Error;
+use std::io;
+
+use bincode::config;
+
+use crate::search_dir::{ChangedFile, File, FileFields, RenamedFileEntry};
+use crate::OPERATION;
+
+// bumped whenever the payload layout changes, frames of another version are rejected
+const FRAME_VERSION: u8 = 1;
+// the version byte followed by the payload length as a little endian u32
+const FRAME_HEADER_LEN: usize = 5;
+
+const CREATE: u8 = 0;
+const CHANGE: u8 = 1;
+const DELETE: u8 = 2;
+const RENAME: u8 = 3;
+const BATCH: u8 = 4;
+const ERROR: u8 = 5;
+
+type ChangedFields = (FileFields, FileFields, Option<Vec<u8>>);
+
+// every operation is framed with the same fields, the kind telling which of them are used:
+// kind, created, changed, deleted and renamed. An error is its kind alone, fmt::Error has no
+// message to carry
+type Payload = (
+    u8,
+    Vec<FileFields>,
+    Vec<ChangedFields>,
+    Vec<FileFields>,
+    Vec<(String, String)>,
+);
+
+impl OPERATION {
+    // encodes the operation as a frame: a version byte, the payload length as a little endian
+    // u32 and the bincode payload. Frames can be written back to back to a pipe or a socket
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let files = |files: &HashSet<File>| files.iter().map(File::to_fields).collect();
+        let changed = |files: &HashSet<ChangedFile>| {
+            files
+                .iter()
+                .map(|f| {
+                    let contents = f.contents().map(|contents| contents.to_vec());
+                    (f.file().to_fields(), f.previous().to_fields(), contents)
+                })
+                .collect()
+        };
+        let renamed = |files: &HashSet<RenamedFileEntry>| {
+            files
+                .iter()
+                .map(|f| (f.name().to_string(), f.old_name().to_string()))
+                .collect()
+        };
+
+        let payload: Payload = match self {
+            OPERATION::CREATE(created) => (CREATE, files(created), vec![], vec![], vec![]),
+            OPERATION::CHANGE(data) => (CHANGE, vec![], changed(data), vec![], vec![]),
+            OPERATION::DELETE(deleted) => (DELETE, vec![], vec![], files(deleted), vec![]),
+            OPERATION::RENAME(data) => (RENAME, vec![], vec![], vec![], renamed(data)),
+            OPERATION::BATCH {
+                created,
+                changed: changed_files,
+                deleted,
+                renamed: renamed_files,
+            } => (
+                BATCH,
+                files(created),
+                changed(changed_files),
+                files(deleted),
+                renamed(renamed_files),
+            ),
+            OPERATION::ERROR(_) => (ERROR, vec![], vec![], vec![], vec![]),
+        };
+
+        //plain strings, numbers and collections always encode
+        let payload = bincode::encode_to_vec(payload, config::standard()).unwrap();
+
+        let mut result = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+        result.push(FRAME_VERSION);
+        result.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        result.extend_from_slice(&payload);
+
+        result
+    }
+
+    // decodes the frame at the start of bytes, returning the operation and the length of the
+    // frame. An incomplete frame is an UnexpectedEof error, a frame of another version or a
+    // malformed one InvalidData
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<(Self, usize)> {
+        let header = bytes
+            .get(..FRAME_HEADER_LEN)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "incomplete frame"))?;
+
+        if header[0] != FRAME_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported frame version {}", header[0]),
+            ));
+        }
+
+        let len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        let payload = bytes
+            .get(FRAME_HEADER_LEN..FRAME_HEADER_LEN + len)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "incomplete frame"))?;
+
+        let ((kind, created, changed, deleted, renamed), _): (Payload, usize) =
+            bincode::decode_from_slice(payload, config::standard())
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+        let files = |files: Vec<FileFields>| files.into_iter().map(File::from_fields).collect();
+        let changed = changed
+            .into_iter()
+            .map(|(file, previous, contents)| {
+                ChangedFile::new(File::from_fields(file), File::from_fields(previous))
+                    .with_contents(contents)
+            })
+            .collect();
+        let renamed = renamed
+            .iter()
+            .map(|(name, old_name)| RenamedFileEntry::new(name, old_name))
+            .collect();
+
+        let operation = match kind {
+            CREATE => OPERATION::CREATE(files(created)),
+            CHANGE => OPERATION::CHANGE(changed),
+            DELETE => OPERATION::DELETE(files(deleted)),
+            RENAME => OPERATION::RENAME(renamed),
+            BATCH => OPERATION::BATCH {
+                created: files(created),
+                changed,
+                deleted: files(deleted),
+                renamed,
+            },
+            ERROR => OPERATION::ERROR(Error),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown operation kind {}", kind),
+                ))
+            }
+        };
+
+        Ok((operation, FRAME_HEADER_LEN + len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+
+    fn files(names: &[&str]) -> HashSet<File> {
+        let modified = SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 42);
+
+        names
+            .iter()
+            .map(|name| File::from_manifest(Path::new(name), modified, name.len() as u64))
+            .collect()
+    }
+
+    fn changed(names: &[&str]) -> HashSet<ChangedFile> {
+        files(names)
+            .into_iter()
+            .map(|f| ChangedFile::new(f.clone(), f).with_contents(Some(b"contents".to_vec())))
+            .collect()
+    }
+
+    fn renamed() -> HashSet<RenamedFileEntry> {
+        HashSet::from([RenamedFileEntry::new("/new.txt", "/old.txt")])
+    }
+
+    #[test]
+    fn every_operation_survives_a_round_trip() {
+        let operations = [
+            OPERATION::CREATE(files(&["/a.txt", "/b.txt"])),
+            OPERATION::CHANGE(changed(&["/c.txt"])),
+            OPERATION::DELETE(files(&["/d.txt"])),
+            OPERATION::RENAME(renamed()),
+            OPERATION::BATCH {
+                created: files(&["/a.txt"]),
+                changed: changed(&["/c.txt"]),
+                deleted: files(&["/d.txt"]),
+                renamed: renamed(),
+            },
+            //nothing but the kind, fmt::Error has no message
+            OPERATION::ERROR(Error),
+        ];
+
+        //frames written back to back are read back one at a time
+        let bytes: Vec<u8> = operations.iter().flat_map(OPERATION::to_bytes).collect();
+        let mut offset = 0;
+        for operation in operations.iter() {
+            let (decoded, len) = OPERATION::from_bytes(&bytes[offset..]).unwrap();
+            assert_eq!(&decoded, operation);
+            offset += len;
+        }
+        assert_eq!(offset, bytes.len());
+
+        //files compare by name, the other fields are checked apart
+        let (decoded, _) = OPERATION::from_bytes(&operations[1].to_bytes()).unwrap();
+        let (expected, decoded) = match (&operations[1], decoded) {
+            (OPERATION::CHANGE(expected), OPERATION::CHANGE(decoded)) => (
+                expected.iter().next().unwrap().clone(),
+                decoded.into_iter().next().unwrap(),
+            ),
+            _ => panic!("not a change"),
+        };
+        assert_eq!(decoded.file().size(), expected.file().size());
+        assert_eq!(
+            decoded.file().last_modified(),
+            expected.file().last_modified()
+        );
+        assert_eq!(decoded.contents(), Some(&b"contents"[..]));
+    }
+
+    #[test]
+    fn incomplete_and_unknown_frames_are_rejected() {
+        let bytes = OPERATION::DELETE(files(&["/d.txt"])).to_bytes();
+
+        let incomplete = OPERATION::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert_eq!(incomplete.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut newer = bytes.clone();
+        newer[0] = FRAME_VERSION + 1;
+        let unsupported = OPERATION::from_bytes(&newer).unwrap_err();
+        assert_eq!(unsupported.kind(), io::ErrorKind::InvalidData);
+    }
+}
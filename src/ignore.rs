@@ -0,0 +1,138 @@
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub const DEFAULT_IGNORE_FILE: &str = ".watchignore";
+const COMMENT_CHAR: char = '#';
+const PATH_SEPARATOR: char = '/';
+
+#[derive(Debug, Clone)]
+struct IgnorePattern {
+    regex: Regex,
+    dir_only: bool,
+}
+
+impl IgnorePattern {
+    fn parse(line: &str) -> Option<Self> {
+        let mut glob = line.trim();
+        if glob.is_empty() || glob.starts_with(COMMENT_CHAR) {
+            return None;
+        }
+
+        let dir_only = glob.ends_with(PATH_SEPARATOR);
+        glob = glob.trim_end_matches(PATH_SEPARATOR);
+
+        // patterns containing a separator are relative to the root, the others match at any depth
+        let anchored = glob.contains(PATH_SEPARATOR);
+        glob = glob.trim_start_matches(PATH_SEPARATOR);
+        if glob.is_empty() {
+            return None;
+        }
+
+        let mut pattern = String::from(if anchored { "^" } else { "^(?:.*/)?" });
+        let mut chars = glob.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&PATH_SEPARATOR) {
+                        chars.next();
+                        pattern.push_str("(?:.*/)?");
+                    } else {
+                        pattern.push_str(".*");
+                    }
+                }
+                '*' => pattern.push_str("[^/]*"),
+                '?' => pattern.push_str("[^/]"),
+                _ => pattern.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        pattern.push('$');
+
+        Regex::new(&pattern)
+            .ok()
+            .map(|regex| Self { regex, dir_only })
+    }
+
+    fn is_match(&self, relative_path: &str, is_dir: bool) -> bool {
+        (is_dir || !self.dir_only) && self.regex.is_match(relative_path)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IgnoreFile {
+    root: PathBuf,
+    path: PathBuf,
+    last_modified: Option<SystemTime>,
+    patterns: Vec<IgnorePattern>,
+}
+
+impl IgnoreFile {
+    pub fn load(root: &Path, path: PathBuf) -> Self {
+        let mut result = Self {
+            root: root.to_path_buf(),
+            path,
+            last_modified: None,
+            patterns: vec![],
+        };
+        result.reload();
+
+        result
+    }
+
+    // re-reads the patterns if the ignore file was modified, created or removed since the last load
+    pub fn reload_if_changed(&mut self) -> bool {
+        if Self::modified(&self.path) == self.last_modified {
+            return false;
+        }
+
+        self.reload();
+
+        true
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let relative = match path.strip_prefix(&self.root) {
+            Ok(relative) => relative,
+            Err(_) => return false,
+        };
+
+        if self.patterns.is_empty() || relative.as_os_str().is_empty() {
+            return false;
+        }
+
+        let components: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+
+        // the entry itself, then every parent directory below the root
+        for len in (1..=components.len()).rev() {
+            let candidate = components[..len].join("/");
+            let candidate_is_dir = is_dir || len < components.len();
+
+            if self
+                .patterns
+                .iter()
+                .any(|p| p.is_match(&candidate, candidate_is_dir))
+            {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn reload(&mut self) {
+        self.last_modified = Self::modified(&self.path);
+        self.patterns = match fs::read_to_string(&self.path) {
+            Ok(content) => content.lines().filter_map(IgnorePattern::parse).collect(),
+            Err(_) => vec![],
+        };
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|meta| meta.modified()).ok()
+    }
+}
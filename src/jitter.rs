@@ -0,0 +1,73 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// tells apart watchers created within the same clock tick
+static WATCHERS: AtomicU64 = AtomicU64::new(0);
+
+// a random offset uniform in [0, max) added to each sleep of the poll loop, so watchers sharing
+// a refresh rate do not scan at the same time. A xorshift generator is plenty for this.
+#[derive(Debug)]
+pub struct Jitter {
+    max: Duration,
+    state: u64,
+}
+
+impl Jitter {
+    pub fn new(max: Duration) -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos() as u64);
+        let watcher = WATCHERS.fetch_add(1, Ordering::Relaxed);
+
+        Self {
+            max,
+            state: Self::mix(nanos ^ watcher.wrapping_mul(0x9E37_79B9_7F4A_7C15)),
+        }
+    }
+
+    pub fn next_offset(&mut self) -> Duration {
+        let max = self.max.as_nanos() as u64;
+        if max == 0 {
+            return Duration::ZERO;
+        }
+
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+
+        Duration::from_nanos(self.state % max)
+    }
+
+    // splitmix64 finalizer, spreads close seeds apart and never gives the stuck xorshift state 0
+    fn mix(seed: u64) -> u64 {
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+        (z ^ (z >> 31)).max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn offsets_vary_within_the_jitter_bound() {
+        let max = Duration::from_millis(50);
+        let mut jitter = Jitter::new(max);
+
+        let offsets: Vec<Duration> = (0..100).map(|_| jitter.next_offset()).collect();
+        assert!(offsets.iter().all(|offset| *offset < max));
+        assert!(offsets.iter().collect::<HashSet<_>>().len() > 50);
+
+        let mut other = Jitter::new(max);
+        assert_ne!(
+            offsets,
+            (0..100).map(|_| other.next_offset()).collect::<Vec<_>>()
+        );
+        assert_eq!(Jitter::new(Duration::ZERO).next_offset(), Duration::ZERO);
+    }
+}
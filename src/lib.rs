@@ -1,49 +1,409 @@
+#![forbid(unsafe_code)]
+
+mod callback;
 mod events;
+#[cfg(feature = "binary")]
+mod frame;
+mod ignore;
+mod jitter;
+mod poller;
+mod probe;
+mod roots;
+#[cfg(feature = "schedule")]
+mod schedule;
 pub mod search_dir;
+pub mod stats;
+mod suppress;
+mod throttle;
+mod tick;
+mod trace;
+#[cfg(feature = "webhook")]
+mod webhook;
 
-use std::collections::HashSet;
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashSet};
 use std::fmt::{Debug, Display, Error};
-use std::fs::Metadata;
-use std::path::PathBuf;
-use std::sync::mpsc::{channel, Sender};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::{
+    channel, sync_channel, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError,
+};
 use std::sync::{Arc, Mutex};
-use std::thread::{self, JoinHandle};
-use std::time::{Duration, SystemTime};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-use search_dir::RenamedFileEntry;
+use search_dir::{ChangedFile, RenamedFileEntry};
 
+use crate::callback::{Callback, ChangeDetector, ChangeDetectorFn, Sink, SpawnFn, Spawner, Worker};
 use crate::events::{
-    OnChangedEventArgs, OnCreatedEventArgs, OnDeletedEventArgs, OnRenamedEventArgs,
+    OnBatchEventArgs, OnChangedEventArgs, OnCreatedEventArgs, OnDeletedEventArgs,
+    OnRenamedEventArgs,
 };
-use crate::search_dir::{File, SearchDir};
+use crate::ignore::DEFAULT_IGNORE_FILE;
+use crate::jitter::Jitter;
+use crate::poller::Poller;
+use crate::probe::EphemeralProbe;
+use crate::roots::Roots;
+#[cfg(feature = "schedule")]
+use crate::schedule::ActiveWindow;
+use crate::search_dir::{File, SearchDir, ALL_FILES_FILTER, FILTER_SEPARATORS};
+use crate::stats::WatcherStats;
+use crate::suppress::Suppressions;
+use crate::throttle::EventThrottle;
+use crate::tick::EventTick;
+use crate::trace::PollSpan;
+#[cfg(feature = "webhook")]
+use crate::webhook::{Webhook, WebhookUrl};
 
-#[derive(Debug, Clone)]
+pub use crate::suppress::SuppressionGuard;
+
+// the recommended refresh rate leaves the watcher scanning at most a tenth of the time
+const REFRESH_HINT_SCAN_MULTIPLE: u32 = 10;
+const REFRESH_HINT_MIN: Duration = Duration::from_millis(10);
+const REFRESH_HINT_MAX: Duration = Duration::from_secs(60);
+
+// the defaults of FileWatcher::watch
+const WATCH_REFRESH_RATE: u64 = 250;
+const WATCH_BUFFER: usize = 1024;
+
+// outside its active window the watcher checks the clock at least this often, so a stop() is
+// not held up until the window opens
+#[cfg(feature = "schedule")]
+const DORMANT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The changes detected by a single poll are dispatched in a fixed order: CREATE, CHANGE,
+/// DELETE and then RENAME, each at most once per poll. With combined events they are sent as a
+/// single BATCH per poll instead.
+///
+/// Operations compare equal when they are of the same kind with the same files, files being
+/// compared by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum OPERATION {
     CREATE(HashSet<File>),
-    CHANGE(HashSet<File>),
+    CHANGE(HashSet<ChangedFile>),
     DELETE(HashSet<File>),
     RENAME(HashSet<RenamedFileEntry>),
+    BATCH {
+        created: HashSet<File>,
+        changed: HashSet<ChangedFile>,
+        deleted: HashSet<File>,
+        renamed: HashSet<RenamedFileEntry>,
+    },
     ERROR(Error),
 }
 
+// what an operation is, without the files it carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Created,
+    Changed,
+    Deleted,
+    Renamed,
+    Batch,
+    Error,
+}
+
+impl EventKind {
+    // the label used for the operation in logs and traces
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Created => "created",
+            EventKind::Changed => "changed",
+            EventKind::Deleted => "deleted",
+            EventKind::Renamed => "renamed",
+            EventKind::Batch => "batch",
+            EventKind::Error => "error",
+        }
+    }
+}
+
+impl Display for EventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl OPERATION {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            OPERATION::CREATE(_) => EventKind::Created,
+            OPERATION::CHANGE(_) => EventKind::Changed,
+            OPERATION::DELETE(_) => EventKind::Deleted,
+            OPERATION::RENAME(_) => EventKind::Renamed,
+            OPERATION::BATCH { .. } => EventKind::Batch,
+            OPERATION::ERROR(_) => EventKind::Error,
+        }
+    }
+
+    // every path an operation is about, both names of a rename
+    fn paths(&self) -> HashSet<&str> {
+        fn renamed(files: &HashSet<RenamedFileEntry>) -> impl Iterator<Item = &str> {
+            files.iter().flat_map(|f| [f.name(), f.old_name()])
+        }
+
+        match self {
+            OPERATION::CREATE(files) | OPERATION::DELETE(files) => {
+                files.iter().map(|f| f.name()).collect()
+            }
+            OPERATION::CHANGE(files) => files.iter().map(|f| f.name()).collect(),
+            OPERATION::RENAME(files) => renamed(files).collect(),
+            OPERATION::BATCH {
+                created,
+                changed,
+                deleted,
+                renamed: renames,
+            } => created
+                .iter()
+                .chain(deleted)
+                .map(|f| f.name())
+                .chain(changed.iter().map(|f| f.name()))
+                .chain(renamed(renames))
+                .collect(),
+            OPERATION::ERROR(_) => HashSet::new(),
+        }
+    }
+
+    // merges the operations of one poll into a single BATCH, errors are kept apart
+    fn combine(operations: Vec<Self>) -> Vec<Self> {
+        let mut result: Vec<Self> = vec![];
+        let (mut created, mut changed, mut deleted, mut renamed) = (
+            HashSet::new(),
+            HashSet::new(),
+            HashSet::new(),
+            HashSet::new(),
+        );
+
+        for operation in operations {
+            match operation {
+                OPERATION::CREATE(files) => created.extend(files),
+                OPERATION::CHANGE(files) => changed.extend(files),
+                OPERATION::DELETE(files) => deleted.extend(files),
+                OPERATION::RENAME(files) => renamed.extend(files),
+                other => result.push(other),
+            }
+        }
+
+        if !created.is_empty() || !changed.is_empty() || !deleted.is_empty() || !renamed.is_empty()
+        {
+            result.insert(
+                0,
+                OPERATION::BATCH {
+                    created,
+                    changed,
+                    deleted,
+                    renamed,
+                },
+            );
+        }
+
+        result
+    }
+}
+
+// a single file of an operation, as delivered by FileWatcher::events_rx
+#[derive(Debug, Clone)]
+pub enum WatchEvent {
+    Created(File),
+    Changed(ChangedFile),
+    Deleted(File),
+    Renamed(RenamedFileEntry),
+}
+
+impl WatchEvent {
+    fn from_operation(op: &OPERATION) -> Vec<Self> {
+        match op {
+            OPERATION::CREATE(files) => files.iter().cloned().map(Self::Created).collect(),
+            OPERATION::CHANGE(files) => files.iter().cloned().map(Self::Changed).collect(),
+            OPERATION::DELETE(files) => files.iter().cloned().map(Self::Deleted).collect(),
+            OPERATION::RENAME(files) => files.iter().cloned().map(Self::Renamed).collect(),
+            OPERATION::BATCH {
+                created,
+                changed,
+                deleted,
+                renamed,
+            } => {
+                let created = created.iter().cloned().map(Self::Created);
+                let changed = changed.iter().cloned().map(Self::Changed);
+                let deleted = deleted.iter().cloned().map(Self::Deleted);
+                let renamed = renamed.iter().cloned().map(Self::Renamed);
+
+                created
+                    .chain(changed)
+                    .chain(deleted)
+                    .chain(renamed)
+                    .collect()
+            }
+            OPERATION::ERROR(_) => vec![],
+        }
+    }
+}
+
 //enums
 #[derive(Debug, Clone)]
+#[allow(clippy::upper_case_acronyms)]
 enum ChannelOperation {
-    CONTINUE(OPERATION),
+    CONTINUE(Box<OPERATION>, PollSpan),
     EXIT,
 }
 
+// where events_rx delivers, a bounded receiver drops the events it has no room for
+#[derive(Debug)]
+enum EventSender {
+    Unbounded(Sender<WatchEvent>),
+    Bounded(SyncSender<WatchEvent>),
+}
+
+impl EventSender {
+    // the number of events dropped, None once the receiver is gone
+    fn send(&self, events: &[WatchEvent]) -> Option<u64> {
+        let mut dropped: u64 = 0;
+
+        for event in events {
+            match self {
+                EventSender::Unbounded(sender) => sender.send(event.clone()).ok()?,
+                EventSender::Bounded(sender) => match sender.try_send(event.clone()) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => dropped += 1,
+                    Err(TrySendError::Disconnected(_)) => return None,
+                },
+            }
+        }
+
+        Some(dropped)
+    }
+}
+
 bitflags::bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
     pub struct NotifyFilters : u8 {
-        const Attributes = 0;
-        const CreationTime = 1;
-        const DirectoryName = 2;
-        const FileName = 3;
-        const LastAccess = 4;
-        const LastWrite = 5;
-        const Security = 6;
-        const Size = 7;
+        const Attributes = 1 << 0;
+        const CreationTime = 1 << 1;
+        //directories produce events too, see Entries
+        const DirectoryName = 1 << 2;
+        const FileName = 1 << 3;
+        const LastAccess = 1 << 4;
+        const LastWrite = 1 << 5;
+        const Security = 1 << 6;
+        const Size = 1 << 7;
+    }
+}
+
+// the kinds of operations a watcher computes and reports
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct OperationMask : u8 {
+        const CREATE = 1 << 0;
+        const CHANGE = 1 << 1;
+        const DELETE = 1 << 2;
+        const RENAME = 1 << 3;
+    }
+}
+
+impl NotifyFilters {
+    pub const DEFAULT: Self = Self::LastWrite.union(Self::Size);
+    pub const ALL: Self = Self::all();
+    pub const TIMESTAMPS: Self = Self::CreationTime
+        .union(Self::LastWrite)
+        .union(Self::LastAccess);
+    pub const CONTENT: Self = Self::LastWrite.union(Self::Size);
+}
+
+// receives the operations of a watcher in place of separate callbacks, each method is called on
+// the events thread with the files of one operation sorted by name; on_error is also called from
+// the webhook thread, see FileWatcher::with_webhook
+pub trait WatchSink {
+    fn on_created(&mut self, _files: &[File]) {}
+
+    fn on_changed(&mut self, _files: &[ChangedFile]) {}
+
+    fn on_deleted(&mut self, _files: &[File]) {}
+
+    fn on_renamed(&mut self, _files: &[RenamedFileEntry]) {}
+
+    fn on_error(&mut self, _error: &Error) {}
+}
+
+// what identifies a created file as a deleted one that was renamed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenameMatch {
+    Modified,
+    Created,
+    Size,
+    //also reports a moved directory as a single rename of the directory
+    Inode,
+}
+
+// which renames are reported, directory renames are only detected with RenameMatch::Inode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenameScope {
+    Files,
+    Dirs,
+    #[default]
+    All,
+    None,
+}
+
+impl RenameScope {
+    pub(crate) fn includes_files(self) -> bool {
+        matches!(self, Self::Files | Self::All)
+    }
+
+    pub(crate) fn includes_dirs(self) -> bool {
+        matches!(self, Self::Dirs | Self::All)
+    }
+}
+
+// how the filters treat a path that is not valid UTF-8
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonUtf8 {
+    // never matched, the file is left out of every scan
+    #[default]
+    Skip,
+    // compared after replacing the invalid sequences with U+FFFD, which may match the wrong
+    // filter; the file is reported under the replaced name
+    Lossy,
+}
+
+// which entries produce events, scans descend into every directory within the depth either way.
+// A directory is reported as a File whose is_dir is true: created, deleted, renamed and, as its
+// modification time changes along with its entries, changed whenever an entry is added or
+// removed. Without FileWatcherOptions::with_entries, files and directories are reported when the
+// notify filters hold DirectoryName and only files otherwise; an explicit selection overrides the
+// filter, FilesOnly never reports a directory even with DirectoryName
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Entries {
+    #[default]
+    FilesOnly,
+    DirsOnly,
+    Both,
+}
+
+impl Entries {
+    // what is reported when with_entries was not called
+    pub(crate) fn for_notify_filters(filters: NotifyFilters) -> Self {
+        match filters.contains(NotifyFilters::DirectoryName) {
+            true => Self::Both,
+            false => Self::FilesOnly,
+        }
+    }
+
+    pub(crate) fn includes_files(self) -> bool {
+        matches!(self, Self::FilesOnly | Self::Both)
+    }
+
+    pub(crate) fn includes_dirs(self) -> bool {
+        matches!(self, Self::DirsOnly | Self::Both)
+    }
+}
+
+impl Default for RenameMatch {
+    // inodes survive any rename but only exist on unix
+    fn default() -> Self {
+        match cfg!(unix) {
+            true => Self::Inode,
+            false => Self::Modified,
+        }
     }
 }
 
@@ -53,32 +413,200 @@ impl Display for NotifyFilters {
     }
 }
 
+// comma separated flags or presets in any case, e.g. "LastWrite,Size" or "timestamps"
+impl FromStr for NotifyFilters {
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let presets = [
+            ("all", Self::ALL),
+            ("default", Self::DEFAULT),
+            ("timestamps", Self::TIMESTAMPS),
+            ("content", Self::CONTENT),
+        ];
+
+        let mut result = Self::empty();
+        for token in s.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            let flags = Self::all()
+                .iter_names()
+                .chain(presets)
+                .find(|(name, _)| name.eq_ignore_ascii_case(token))
+                .map(|(_, flags)| flags)
+                .ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("'{token}' is not a notify filter!"),
+                    )
+                })?;
+
+            result |= flags;
+        }
+
+        Ok(result)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FileWatcherOptions {
     dir: String,
+    extra_dirs: Vec<PathBuf>,
     filter: Option<String>,
     refresh_rate_mils: u64,
-    on_created: Option<fn(OnCreatedEventArgs)>,
-    on_deleted: Option<fn(OnDeletedEventArgs)>,
-    on_changed: Option<fn(OnChangedEventArgs)>,
-    on_renamed: Option<fn(OnRenamedEventArgs)>,
+    on_created: Option<Callback<OnCreatedEventArgs>>,
+    on_deleted: Option<Callback<OnDeletedEventArgs>>,
+    on_changed: Option<Callback<OnChangedEventArgs>>,
+    on_changed_in: Vec<(String, Callback<OnChangedEventArgs>)>,
+    on_created_for: Vec<(String, Callback<OnCreatedEventArgs>)>,
+    on_renamed: Option<Callback<OnRenamedEventArgs>>,
+    on_batch: Option<Callback<OnBatchEventArgs>>,
+    combined_events: bool,
     notify_filters: NotifyFilters,
     dir_depth: Option<u8>,
+    ignore_file: Option<String>,
+    use_ignore_file: bool,
+    scan_budget: Option<usize>,
+    on_scan_progress: Option<Callback<usize>>,
+    max_runtime: Option<Duration>,
+    on_max_runtime: Option<Callback<Duration>>,
+    overflow_threshold: Option<Duration>,
+    on_overflow: Option<Callback<Duration>>,
+    rename_match: RenameMatch,
+    rename_fallback_reporting: bool,
+    rename_scope: RenameScope,
+    change_detector: Option<ChangeDetector>,
+    wait_for_directory: bool,
+    on_watching: Option<Callback<PathBuf>>,
+    dedup_hardlinks: bool,
+    report_broken_symlinks: bool,
+    track_symlink_targets: bool,
+    non_utf8: NonUtf8,
+    entries: Option<Entries>,
+    require_filter: bool,
+    metadata_retry: (u32, Duration),
+    read_on_change: Option<u64>,
+    content_size_limit: Option<u64>,
+    discard_on_stop: bool,
+    emit_deletes_on_stop: bool,
+    max_event_rate: Option<u32>,
+    tick: Option<Duration>,
+    sink: Option<Sink>,
+    filter_separators: Option<Vec<char>>,
+    content_type_filter: Option<Vec<String>>,
+    on_started: Option<Callback<()>>,
+    on_stopped: Option<Callback<()>>,
+    on_empty: Option<Callback<()>>,
+    on_non_empty: Option<Callback<()>>,
+    ignore_atime_only: bool,
+    poll_jitter: Option<Duration>,
+    ephemeral_probe: Option<Duration>,
+    full_rescan_every: Option<u32>,
+    operations: OperationMask,
+    #[cfg(feature = "schedule")]
+    active_window: Option<ActiveWindow>,
+    on_snapshot: Option<Callback<HashSet<File>>>,
+    on_ephemeral: Option<Callback<HashSet<String>>>,
+    on_metadata_error: Option<Callback<(PathBuf, std::io::Error)>>,
 }
 
 impl FileWatcherOptions {
     pub fn new(directory: &str) -> Self {
         Self {
             dir: directory.to_string(),
+            extra_dirs: vec![],
             filter: None,
             refresh_rate_mils: 250,
             on_changed: None,
+            on_changed_in: vec![],
+            on_created_for: vec![],
             on_created: None,
             on_deleted: None,
             on_renamed: None,
+            on_batch: None,
+            combined_events: false,
             dir_depth: None,
-            notify_filters: NotifyFilters::LastWrite,
+            notify_filters: NotifyFilters::DEFAULT,
+            ignore_file: None,
+            use_ignore_file: false,
+            scan_budget: None,
+            on_scan_progress: None,
+            max_runtime: None,
+            on_max_runtime: None,
+            overflow_threshold: None,
+            on_overflow: None,
+            rename_match: RenameMatch::default(),
+            rename_fallback_reporting: false,
+            rename_scope: RenameScope::default(),
+            change_detector: None,
+            wait_for_directory: false,
+            on_watching: None,
+            dedup_hardlinks: false,
+            report_broken_symlinks: false,
+            track_symlink_targets: false,
+            non_utf8: NonUtf8::default(),
+            entries: None,
+            require_filter: false,
+            metadata_retry: (0, Duration::ZERO),
+            read_on_change: None,
+            content_size_limit: None,
+            discard_on_stop: false,
+            emit_deletes_on_stop: false,
+            max_event_rate: None,
+            tick: None,
+            sink: None,
+            filter_separators: None,
+            content_type_filter: None,
+            on_started: None,
+            on_stopped: None,
+            on_empty: None,
+            on_non_empty: None,
+            ignore_atime_only: true,
+            poll_jitter: None,
+            ephemeral_probe: None,
+            full_rescan_every: None,
+            operations: OperationMask::all(),
+            #[cfg(feature = "schedule")]
+            active_window: None,
+            on_snapshot: None,
+            on_ephemeral: None,
+            on_metadata_error: None,
+        }
+    }
+
+    // the watcher of the options, an error where the filter is invalid or missing while required,
+    // or the directory cannot be watched unless it is waited for, see with_wait_for_directory
+    pub fn build(&self) -> Result<FileWatcher, WatcherError> {
+        let dir = PathBuf::from(&self.dir);
+        if !self.wait_for_directory {
+            WatcherError::check(&dir)?;
+            for dir in self.extra_dirs.iter() {
+                WatcherError::check(dir)?;
+            }
+        }
+        WatcherError::check_filter(
+            &dir,
+            self.filter.as_deref(),
+            self.filter_separators
+                .as_deref()
+                .unwrap_or(FILTER_SEPARATORS),
+        )?;
+
+        let result = FileWatcher::from_options(self);
+        FileWatcher::check_require_filter(result.config())?;
+
+        Ok(result)
+    }
+
+    // more directories watched along with the one of new, with the same settings and callbacks;
+    // a directory given twice is watched once. Files moved between them are a delete and a
+    // create, and the ephemeral probe only covers the directory of new.
+    pub fn with_directories(&mut self, dirs: &[&str]) -> &mut Self {
+        for dir in dirs.iter().map(PathBuf::from) {
+            if dir != Path::new(&self.dir) && !self.extra_dirs.contains(&dir) {
+                self.extra_dirs.push(dir);
+            }
         }
+
+        self
     }
 
     pub fn with_filter(&mut self, filter: &str) -> &mut Self {
@@ -87,446 +615,5532 @@ impl FileWatcherOptions {
         self
     }
 
+    // what the filter is split on instead of ';' and ',', the entries are matched the same way
+    pub fn with_filter_separators(&mut self, separators: &[char]) -> &mut Self {
+        self.filter_separators = Some(separators.to_vec());
+
+        self
+    }
+
+    // only files whose leading bytes are of one of the MIME types (i.e. "image/png") are watched,
+    // whatever their name. Applied after the filter, the result is cached until the file changes.
+    #[cfg(feature = "content-type")]
+    pub fn with_content_type_filter(&mut self, content_types: &[&str]) -> &mut Self {
+        self.content_type_filter = Some(content_types.iter().map(|t| t.to_string()).collect());
+
+        self
+    }
+
+    // a file created and deleted between two polls is never seen, with_ephemeral_probe catches
+    // some of them
     pub fn with_refresh_rate(&mut self, refresh_rate_in_milliseconds: u64) -> &mut Self {
         self.refresh_rate_mils = refresh_rate_in_milliseconds;
 
         self
     }
 
-    pub fn with_on_changed(&mut self, action: fn(OnChangedEventArgs)) -> &mut Self {
-        self.on_changed = Some(action);
+    // a refresh rate at which every file living at least `lifetime` is seen by a poll, half of it
+    // is left for the scan itself
+    pub fn with_min_refresh_for_ephemeral(&mut self, lifetime: Duration) -> &mut Self {
+        self.refresh_rate_mils = (lifetime.as_millis() as u64 / 2).max(1);
 
         self
     }
 
-    pub fn with_on_created(&mut self, event: fn(OnCreatedEventArgs)) -> &mut Self {
-        self.on_created = Some(event);
+    pub fn with_on_changed(
+        &mut self,
+        action: impl FnMut(OnChangedEventArgs) + Send + 'static,
+    ) -> &mut Self {
+        self.on_changed = Some(Callback::new(Box::new(action)));
 
         self
     }
 
-    pub fn with_on_deleted(&mut self, event: fn(OnDeletedEventArgs)) -> &mut Self {
-        self.on_deleted = Some(event);
+    // only called with the changed files under the prefix, a relative prefix is resolved against
+    // the watched directory; any number of them can be registered
+    pub fn with_on_changed_in(
+        &mut self,
+        prefix: &str,
+        action: impl FnMut(OnChangedEventArgs) + Send + 'static,
+    ) -> &mut Self {
+        self.on_changed_in
+            .push((prefix.to_string(), Callback::new(Box::new(action))));
 
         self
     }
 
-    pub fn with_on_renamed(&mut self, event: fn(OnRenamedEventArgs)) -> &mut Self {
-        self.on_renamed = Some(event);
+    pub fn with_on_created(
+        &mut self,
+        event: impl FnMut(OnCreatedEventArgs) + Send + 'static,
+    ) -> &mut Self {
+        self.on_created = Some(Callback::new(Box::new(event)));
 
         self
     }
 
-    pub fn with_notify_filters(&mut self, filters: NotifyFilters) -> &mut Self {
-        self.notify_filters = filters;
+    // the created files of the extension, compared ignoring case and with or without the leading
+    // dot, go to this callback instead of the one of with_on_created. The first registered for an
+    // extension gets its files.
+    pub fn with_on_created_for(
+        &mut self,
+        extension: &str,
+        action: impl FnMut(OnCreatedEventArgs) + Send + 'static,
+    ) -> &mut Self {
+        self.on_created_for
+            .push((extension.to_string(), Callback::new(Box::new(action))));
 
         self
     }
-    pub fn with_directory_depth(&mut self, depth: u8) -> &mut Self {
-        self.dir_depth = Some(depth);
+
+    pub fn with_on_deleted(
+        &mut self,
+        event: impl FnMut(OnDeletedEventArgs) + Send + 'static,
+    ) -> &mut Self {
+        self.on_deleted = Some(Callback::new(Box::new(event)));
 
         self
     }
-}
 
-#[derive(Debug)]
-pub struct FileWatcher {
-    dir_path: PathBuf,
-    filter: Option<String>,
-    last_sync: Option<SystemTime>,
-    refresh_rate_in_milliseconds: u64,
-    main_thread: Option<JoinHandle<()>>,
-    events_thread: Option<JoinHandle<()>>,
-    on_created: Option<fn(OnCreatedEventArgs)>,
-    on_deleted: Option<fn(OnDeletedEventArgs)>,
-    on_changed: Option<fn(OnChangedEventArgs)>,
-    on_renamed: Option<fn(OnRenamedEventArgs)>,
-    channel_sender: Option<Sender<ChannelOperation>>,
-    notify_filters: NotifyFilters,
-    dir_depth: Option<u8>,
-    is_started: Arc<Mutex<bool>>,
-}
+    pub fn with_on_renamed(
+        &mut self,
+        event: impl FnMut(OnRenamedEventArgs) + Send + 'static,
+    ) -> &mut Self {
+        self.on_renamed = Some(Callback::new(Box::new(event)));
 
-impl Drop for FileWatcher {
-    fn drop(&mut self) {
-        if *self.is_started.lock().unwrap() {
-            let _ = self.stop();
-        }
+        self
+    }
 
-        self.dir_path.clear();
+    // only called when combined events are enabled
+    pub fn with_on_batch(
+        &mut self,
+        event: impl FnMut(OnBatchEventArgs) + Send + 'static,
+    ) -> &mut Self {
+        self.on_batch = Some(Callback::new(Box::new(event)));
 
-        self.filter = None;
-        self.last_sync = None;
-        self.on_changed = None;
-        self.channel_sender = None;
-        self.refresh_rate_in_milliseconds = 0;
-        self.main_thread = None;
-        self.events_thread = None;
-        self.on_created = None;
-        self.on_deleted = None;
-        self.on_renamed = None;
+        self
+    }
 
-        drop(self.is_started.lock());
+    // the sink receives every operation, alongside the callbacks
+    pub fn with_sink(&mut self, sink: Box<dyn WatchSink + Send>) -> &mut Self {
+        self.sink = Some(Sink::new(sink));
+
+        self
     }
-}
 
-impl FileWatcher {
-    pub fn new_with_options(op: &FileWatcherOptions) -> Self {
-        let mut result = Self::new(
-            op.dir.as_str(),
-            op.filter.clone(),
-            op.refresh_rate_mils,
-            op.dir_depth,
-        );
+    // every poll sends all its changes as one BATCH to the batch callback; the created, changed,
+    // deleted and renamed callbacks are not called in this mode
+    pub fn with_combined_events(&mut self, combined_events: bool) -> &mut Self {
+        self.combined_events = combined_events;
 
-        result.notify_filters = op.notify_filters;
-        result.dir_depth = op.dir_depth;
+        self
+    }
 
-        if let Some(on_event) = op.on_created {
-            result.on_created(on_event);
-        }
+    // with LastAccess, an access time change is only reported along with a modification time
+    // change, reads alone are ignored. On by default.
+    pub fn with_ignore_atime_only(&mut self, ignore_atime_only: bool) -> &mut Self {
+        self.ignore_atime_only = ignore_atime_only;
 
-        if let Some(on_event) = op.on_deleted {
-            result.on_deleted(on_event);
-        }
+        self
+    }
 
-        if let Some(on_event) = op.on_changed {
-            result.on_changed(on_event);
-        }
+    pub fn with_notify_filters(&mut self, filters: NotifyFilters) -> &mut Self {
+        self.notify_filters = filters;
 
-        if let Some(on_event) = op.on_renamed {
-            result.on_renamed(on_event);
-        }
+        self
+    }
+    pub fn with_directory_depth(&mut self, depth: u8) -> &mut Self {
+        self.dir_depth = Some(depth);
 
-        result
+        self
     }
 
-    pub fn new(
-        dir: &str,
-        filter: Option<String>,
-        refresh_rate_in_milliseconds: u64,
-        dir_depth: Option<u8>,
-    ) -> Self {
-        let dir_path = PathBuf::from(dir);
-        if !dir_path.exists() {
-            panic!("The directory '{dir}' does not exist!")
-        }
+    pub fn with_ignore_file(&mut self, path: &str) -> &mut Self {
+        self.ignore_file = Some(path.to_string());
+        self.use_ignore_file = true;
 
-        let result = Self {
-            dir_path,
-            filter,
-            last_sync: None,
-            refresh_rate_in_milliseconds,
-            main_thread: None,
-            events_thread: None,
-            on_changed: None,
-            on_created: None,
-            on_deleted: None,
-            on_renamed: None,
-            channel_sender: None,
-            notify_filters: NotifyFilters::LastWrite,
-            dir_depth: dir_depth,
-            is_started: Arc::new(Mutex::new(false)),
-        };
+        self
+    }
 
-        result
+    pub fn with_use_ignore_file(&mut self, use_ignore_file: bool) -> &mut Self {
+        self.use_ignore_file = use_ignore_file;
+
+        self
     }
 
-    pub fn watched_dir(&self) -> &str {
-        &self.dir_path.as_os_str().to_str().unwrap()
+    // splits the scan of the tree across polls, each one examining at most `entries_per_poll`
+    // entries so large trees don't delay `stop()` or the callbacks. A created file is reported
+    // as soon as it is scanned and a deleted one once the whole tree was, so renames are never
+    // paired: a renamed file is a CREATE and a DELETE whatever the rename match
+    pub fn with_scan_budget(&mut self, entries_per_poll: usize) -> &mut Self {
+        self.scan_budget = Some(entries_per_poll.max(1));
+
+        self
     }
 
-    pub fn filter(&self) -> Option<&str> {
-        return match &self.filter {
-            Some(f) => Some(f),
-            None => None,
-        };
+    // called with the number of files found so far while the initial scan is running
+    pub fn with_scan_progress(
+        &mut self,
+        callback: impl FnMut(usize) + Send + 'static,
+    ) -> &mut Self {
+        self.on_scan_progress = Some(Callback::new(Box::new(callback)));
+
+        self
     }
 
-    pub fn on_created(&mut self, action: fn(OnCreatedEventArgs)) -> &Self {
-        self.on_created = Some(action);
+    // the watcher stops on its own once it ran for this long
+    pub fn with_max_runtime(&mut self, max_runtime: Duration) -> &mut Self {
+        self.max_runtime = Some(max_runtime);
 
         self
     }
 
-    pub fn on_changed(&mut self, action: fn(OnChangedEventArgs)) -> &Self {
-        self.on_changed = Some(action);
+    // called with the time the watcher ran when it stopped because of the max runtime
+    pub fn with_on_max_runtime(
+        &mut self,
+        callback: impl FnMut(Duration) + Send + 'static,
+    ) -> &mut Self {
+        self.on_max_runtime = Some(Callback::new(Box::new(callback)));
 
         self
     }
 
-    pub fn on_deleted(&mut self, action: fn(OnDeletedEventArgs)) -> &Self {
-        self.on_deleted = Some(action);
+    // polls taking longer than the refresh rate in a row add up to a lag, once it exceeds the
+    // threshold the overflow callback is called and the lag starts over
+    pub fn with_overflow_threshold(&mut self, threshold: Duration) -> &mut Self {
+        self.overflow_threshold = Some(threshold);
 
         self
     }
 
-    pub fn on_renamed(&mut self, action: fn(OnRenamedEventArgs)) -> &Self {
-        self.on_renamed = Some(action);
+    // called with the lag when polling cannot keep up with the refresh rate
+    pub fn with_on_overflow(
+        &mut self,
+        callback: impl FnMut(Duration) + Send + 'static,
+    ) -> &mut Self {
+        self.on_overflow = Some(Callback::new(Box::new(callback)));
 
         self
     }
 
-    pub fn start(&mut self) -> Result<bool, std::io::Error> {
-        if *self.is_started.lock().unwrap() {
-            return Ok(false);
-        }
+    pub fn with_rename_match(&mut self, rename_match: RenameMatch) -> &mut Self {
+        self.rename_match = rename_match;
 
-        // communication channel
-        let (sender, receiver) = channel::<ChannelOperation>();
-        let sender_mutex = Mutex::new(sender.clone());
-        let receiver_mutex = Mutex::new(receiver);
-        let filter_mutex = Mutex::new(self.filter.clone());
-        let notify_filters_mutex = Arc::new(Mutex::new(self.notify_filters));
-
-        let refresh_rate: u64 = self.refresh_rate_in_milliseconds;
-        let on_created = self.on_created;
-        let on_deleted = self.on_deleted;
-        let on_changed = self.on_changed;
-        let on_renamed = self.on_renamed;
-        let dir_path = self.dir_path.clone();
-
-        //child thread for receiving changed files
-        let child = thread::spawn(move || loop {
-            match receiver_mutex.lock().unwrap().recv() {
-                Ok(value) => match value {
-                    ChannelOperation::CONTINUE(op) => match op {
-                        OPERATION::CREATE(data) => {
-                            if let Some(func) = on_created {
-                                func(OnCreatedEventArgs::new(data.into_iter().collect()));
-                            }
-                        }
-                        OPERATION::CHANGE(data) => {
-                            if let Some(func) = on_changed {
-                                func(OnChangedEventArgs::new(data.into_iter().collect()));
-                            }
-                        }
-                        OPERATION::DELETE(data) => {
-                            if let Some(func) = on_deleted {
-                                func(OnDeletedEventArgs::new(data.into_iter().collect()));
-                            }
-                        }
-                        OPERATION::RENAME(data) => {
-                            if let Some(func) = on_renamed {
-                                func(OnRenamedEventArgs::new(data.into_iter().collect()));
-                            }
-                        }
-                        OPERATION::ERROR(_) => todo!(),
-                    },
-                    ChannelOperation::EXIT => {
-                        break;
-                    }
-                },
-                Err(error) => {
-                    println!("{}", error);
-                    break;
-                }
-            }
-        });
+        self
+    }
 
-        let depth = self.dir_depth.clone();
+    // unpaired creates and deletes of the same poll get a shared File::correlation_id
+    pub fn with_rename_fallback_reporting(&mut self, enabled: bool) -> &mut Self {
+        self.rename_fallback_reporting = enabled;
 
-        *self.is_started.lock().unwrap() = true;
-        let is_started = self.is_started.clone();
+        self
+    }
 
-        //main thread for checking for changes in the directory
-        let main =
-            thread::spawn(move || {
-                let mut all_files = HashSet::<File>::new();
+    // renames out of the scope are not reported, the files are still tracked under their new name
+    pub fn with_rename_scope(&mut self, rename_scope: RenameScope) -> &mut Self {
+        self.rename_scope = rename_scope;
 
-                let filter_mutex = filter_mutex.lock().unwrap();
-                let mut search_dir =
-                    SearchDir::new(dir_path.clone(), depth, (*filter_mutex).clone());
+        self
+    }
 
-                let notify_filters = Arc::clone(&notify_filters_mutex);
+    // decides with the old and the new state of a file whether it changed, instead of the notify filters
+    pub fn with_change_detector(&mut self, detector: ChangeDetectorFn) -> &mut Self {
+        self.change_detector = Some(ChangeDetector::new(detector));
 
-                //load existing files
-                for file in Self::get_files(&search_dir, *notify_filters.lock().unwrap()) {
-                    all_files.insert(file);
-                }
+        self
+    }
 
-                //check for directory changes
-                search_dir.sync_metadata();
-                loop {
-                    //check if the main thread should stop
-                    if !*is_started.lock().unwrap() {
-                        //send an exit message for the child thread handling events
-                        let _ = sender_mutex.lock().unwrap().send(ChannelOperation::EXIT);
-                        break;
-                    }
+    // changed files up to max_size bytes are read when the change is detected, see ChangedFile::contents.
+    // A file that grew past max_size by the time it is read is left unread, no more than max_size
+    // bytes are ever kept
+    pub fn with_read_on_change(&mut self, max_size: u64) -> &mut Self {
+        self.read_on_change = Some(max_size);
 
-                    //if there's no change in the directory do not get files
-                    if !search_dir.has_changed() {
-                        thread::sleep(Duration::from_millis(refresh_rate));
+        self
+    }
 
-                        continue;
-                    }
+    // caps the bytes any content feature reads of a file, larger files are not read and are
+    // flagged by ChangedFile::content_skipped
+    pub fn with_content_size_limit(&mut self, limit: u64) -> &mut Self {
+        self.content_size_limit = Some(limit);
 
-                    let latest_files =
-                        Self::get_files(&search_dir, *notify_filters.lock().unwrap());
+        self
+    }
 
-                    let mut created_files: HashSet<File> = latest_files
-                        .difference(&all_files)
-                        .map(|fe| fe.clone())
-                        .collect();
+    // every nth poll scans the whole tree even when nothing looks changed, bounding how long a
+    // change the cheap checks miss can go unreported. By default a poll only scans when a
+    // directory within the depth was modified since the last scan, i.e. a file was created,
+    // deleted or renamed, when a known file differs as of the notify filters, or when the ignore
+    // file changed; a file created in a directory whose modification time is then set back is
+    // only found by a full rescan. A scan budget scans on every poll
+    pub fn with_full_rescan_every(&mut self, polls: u32) -> &mut Self {
+        self.full_rescan_every = Some(polls.max(1));
 
-                    let mut deleted_files: HashSet<File> = all_files
-                        .difference(&latest_files)
-                        .map(|fe| fe.clone())
-                        .collect();
+        self
+    }
 
-                    let mut changed_files: HashSet<File> = HashSet::new();
-                    for file in latest_files.iter() {
-                        if let Some(fe) = all_files.get(&file) {
-                            // file was changed
-                            if fe.last_modified().unwrap() != file.last_modified().unwrap() {
-                                changed_files.insert(file.clone());
+    // only these kinds of operations are computed and reported, i.e. without RENAME the created
+    // and deleted files are not paired and a moved file is reported as created and deleted
+    pub fn with_operations(&mut self, operations: OperationMask) -> &mut Self {
+        self.operations = operations;
 
-                                all_files.remove(file);
-                                all_files.insert(file.clone());
+        self
+    }
+
+    // polls only from `start` to `end` local time, e.g. business hours; a start after the end
+    // spans midnight. Outside the window the watcher stays dormant, what changed meanwhile is
+    // reported by the first poll once it opens
+    #[cfg(feature = "schedule")]
+    pub fn with_active_window(
+        &mut self,
+        start: chrono::NaiveTime,
+        end: chrono::NaiveTime,
+    ) -> &mut Self {
+        self.active_window = Some(ActiveWindow::new(start, end));
+
+        self
+    }
+
+    // adds a random offset below jitter to each wait between polls, so watchers sharing a
+    // refresh rate spread their scans over time
+    pub fn with_poll_jitter(&mut self, jitter: Duration) -> &mut Self {
+        self.poll_jitter = Some(jitter);
+
+        self
+    }
+
+    // lists the names in the directory every interval between polls, the ones the next poll does
+    // not find are passed to the ephemeral callback. Best effort and not done with a scan budget.
+    pub fn with_ephemeral_probe(&mut self, interval: Duration) -> &mut Self {
+        self.ephemeral_probe = Some(interval);
+
+        self
+    }
+
+    // called with the paths of the files created and deleted between two polls
+    pub fn with_on_ephemeral(
+        &mut self,
+        callback: impl FnMut(HashSet<String>) + Send + 'static,
+    ) -> &mut Self {
+        self.on_ephemeral = Some(Callback::new(Box::new(callback)));
+
+        self
+    }
+
+    // at most `per_second` operations are delivered each second, the ones over the rate are held
+    // back and merged with the held back operation of the same kind, see WatcherStats
+    pub fn with_max_event_rate(&mut self, per_second: u32) -> &mut Self {
+        self.max_event_rate = Some(per_second.max(1));
+
+        self
+    }
+
+    // the operations are held until the next boundary of wall clock aligned ticks, e.g. the next
+    // whole second, and delivered there together; a tick without operations delivers nothing.
+    // With combined events they reach the batch callback as a single BATCH, otherwise the
+    // created, changed, deleted and renamed callbacks are each called once per batch. An
+    // operation on a path already in the batch starts the next batch of the same tick, so a file
+    // created and deleted within a tick is delivered in that order. The max event rate applies to
+    // the batches
+    pub fn with_tick(&mut self, tick: Duration) -> &mut Self {
+        self.tick = Some(tick);
+
+        self
+    }
+
+    // operations still queued when stop() is called are dropped instead of delivered
+    pub fn with_discard_on_stop(&mut self, discard_on_stop: bool) -> &mut Self {
+        self.discard_on_stop = discard_on_stop;
+
+        self
+    }
+
+    // once stopping, every tracked file is delivered in a last DELETE so a mirror of the
+    // watched files can be cleared; it is queued like any other operation
+    pub fn with_emit_deletes_on_stop(&mut self, emit_deletes_on_stop: bool) -> &mut Self {
+        self.emit_deletes_on_stop = emit_deletes_on_stop;
+
+        self
+    }
+
+    // start() no longer requires the directory to exist, watching begins once it was created
+    pub fn with_wait_for_directory(&mut self, wait_for_directory: bool) -> &mut Self {
+        self.wait_for_directory = wait_for_directory;
+
+        self
+    }
+
+    // paths sharing an inode are one file reported under its first path in name order, the
+    // others are listed by File::hardlinks. With with_scan_budget a file created mid-scan is
+    // reported before its other paths are scanned, they are listed from the next poll on.
+    pub fn with_dedup_hardlinks(&mut self, dedup_hardlinks: bool) -> &mut Self {
+        self.dedup_hardlinks = dedup_hardlinks;
+
+        self
+    }
+
+    // symlinks whose target does not exist are skipped unless reported, then they are files for
+    // which File::is_broken_symlink is true and a link losing its target is a CHANGE
+    pub fn with_report_broken_symlinks(&mut self, report_broken_symlinks: bool) -> &mut Self {
+        self.report_broken_symlinks = report_broken_symlinks;
+
+        self
+    }
+
+    // the target of every symlink is read, a symlink pointed elsewhere is a CHANGE even when
+    // both targets look the same. See File::symlink_target
+    pub fn with_track_symlink_targets(&mut self, track_symlink_targets: bool) -> &mut Self {
+        self.track_symlink_targets = track_symlink_targets;
+
+        self
+    }
+
+    // whether a path that is not valid UTF-8 is left out or matched by its lossy conversion
+    pub fn with_non_utf8(&mut self, non_utf8: NonUtf8) -> &mut Self {
+        self.non_utf8 = non_utf8;
+
+        self
+    }
+
+    // whether files, directories or both produce events whatever the DirectoryName notify filter
+    // says; the filter only applies to files
+    pub fn with_entries(&mut self, entries: Entries) -> &mut Self {
+        self.entries = Some(entries);
+
+        self
+    }
+
+    // build() fails unless a filter or a content type filter narrows down the files, so a
+    // watcher of a large tree does not track every file in it by accident
+    pub fn with_require_filter(&mut self, require_filter: bool) -> &mut Self {
+        self.require_filter = require_filter;
+
+        self
+    }
+
+    // a metadata read failing on busy or networked storage is retried `attempts` times, the wait
+    // starts at `backoff` and doubles after each retry. A file deleted meanwhile is not retried.
+    pub fn with_metadata_retry(&mut self, attempts: u32, backoff: Duration) -> &mut Self {
+        self.metadata_retry = (attempts, backoff);
+
+        self
+    }
+
+    // called with the files skipped because their metadata could not be read, after the retries
+    pub fn with_on_metadata_error(
+        &mut self,
+        callback: impl FnMut((PathBuf, std::io::Error)) + Send + 'static,
+    ) -> &mut Self {
+        self.on_metadata_error = Some(Callback::new(Box::new(callback)));
+
+        self
+    }
+
+    // called with the watched directory once its baseline was taken and polling begins
+    pub fn with_on_watching(
+        &mut self,
+        callback: impl FnMut(PathBuf) + Send + 'static,
+    ) -> &mut Self {
+        self.on_watching = Some(Callback::new(Box::new(callback)));
+
+        self
+    }
+
+    // called once the initial scan completed and polling begins, before any event
+    pub fn with_on_started(&mut self, mut callback: impl FnMut() + Send + 'static) -> &mut Self {
+        self.on_started = Some(Callback::new(Box::new(move |()| callback())));
+
+        self
+    }
+
+    // called once with every file of the baseline before polling begins, later events only
+    // report what changed since
+    pub fn with_on_snapshot(
+        &mut self,
+        callback: impl FnMut(HashSet<File>) + Send + 'static,
+    ) -> &mut Self {
+        self.on_snapshot = Some(Callback::new(Box::new(callback)));
+
+        self
+    }
+
+    // called once the watcher stopped and the operations still queued were handled, by stop() or
+    // as the watcher stops on its own, i.e. at the max runtime or once its receiver is dropped
+    pub fn with_on_stopped(&mut self, mut callback: impl FnMut() + Send + 'static) -> &mut Self {
+        self.on_stopped = Some(Callback::new(Box::new(move |()| callback())));
+
+        self
+    }
+
+    // called by the poll thread when the last tracked file is gone
+    pub fn with_on_empty(&mut self, mut callback: impl FnMut() + Send + 'static) -> &mut Self {
+        self.on_empty = Some(Callback::new(Box::new(move |()| callback())));
+
+        self
+    }
+
+    // called by the poll thread when a file is tracked again after none were; the baseline does
+    // not count as a change either way
+    pub fn with_on_non_empty(&mut self, mut callback: impl FnMut() + Send + 'static) -> &mut Self {
+        self.on_non_empty = Some(Callback::new(Box::new(move |()| callback())));
+
+        self
+    }
+
+    fn ignore_file_path(&self) -> Option<PathBuf> {
+        if !self.use_ignore_file {
+            return None;
+        }
+
+        // relative paths are resolved against the watched directory
+        let file = self.ignore_file.as_deref().unwrap_or(DEFAULT_IGNORE_FILE);
+
+        Some(PathBuf::from(&self.dir).join(file))
+    }
+}
+
+// the settings a watcher resolved from its options, as used by the poll loop. A started watcher
+// polls with a copy of them; the directories, the depth and the notify filters are shared between
+// the copies so add_path, remove_path, set_depth and set_notify_filters reach a running watcher
+#[derive(Debug, Clone)]
+pub struct WatcherConfig {
+    dir_path: PathBuf,
+    extra_dirs: Arc<Mutex<Vec<PathBuf>>>,
+    filter: Option<String>,
+    refresh_rate_in_milliseconds: u64,
+    dir_depth: Arc<Mutex<Option<u8>>>,
+    notify_filters: Arc<Mutex<NotifyFilters>>,
+    ignore_file: Option<PathBuf>,
+    implicit_ignores: Vec<PathBuf>,
+    scan_budget: Option<usize>,
+    max_runtime: Option<Duration>,
+    overflow_threshold: Option<Duration>,
+    rename_match: RenameMatch,
+    rename_fallback_reporting: bool,
+    rename_scope: RenameScope,
+    wait_for_directory: bool,
+    combined_events: bool,
+    dedup_hardlinks: bool,
+    report_broken_symlinks: bool,
+    track_symlink_targets: bool,
+    non_utf8: NonUtf8,
+    entries: Entries,
+    require_filter: bool,
+    metadata_retry: (u32, Duration),
+    read_on_change: Option<u64>,
+    content_size_limit: Option<u64>,
+    discard_on_stop: bool,
+    emit_deletes_on_stop: bool,
+    max_event_rate: Option<u32>,
+    tick: Option<Duration>,
+    filter_separators: Option<Vec<char>>,
+    content_type_filter: Option<Vec<String>>,
+    ignore_atime_only: bool,
+    poll_jitter: Option<Duration>,
+    ephemeral_probe: Option<Duration>,
+    full_rescan_every: Option<u32>,
+    operations: OperationMask,
+    #[cfg(feature = "schedule")]
+    active_window: Option<ActiveWindow>,
+}
+
+impl WatcherConfig {
+    fn from_options(op: &FileWatcherOptions) -> Self {
+        let ignore_file = op.ignore_file_path();
+
+        Self {
+            dir_path: PathBuf::from(&op.dir),
+            extra_dirs: Arc::new(Mutex::new(op.extra_dirs.clone())),
+            filter: op.filter.clone(),
+            refresh_rate_in_milliseconds: op.refresh_rate_mils,
+            dir_depth: Arc::new(Mutex::new(op.dir_depth)),
+            notify_filters: Arc::new(Mutex::new(op.notify_filters)),
+            implicit_ignores: ignore_file.iter().cloned().collect(),
+            ignore_file,
+            scan_budget: op.scan_budget,
+            max_runtime: op.max_runtime,
+            overflow_threshold: op.overflow_threshold,
+            rename_match: op.rename_match,
+            rename_fallback_reporting: op.rename_fallback_reporting,
+            rename_scope: op.rename_scope,
+            wait_for_directory: op.wait_for_directory,
+            combined_events: op.combined_events,
+            dedup_hardlinks: op.dedup_hardlinks,
+            report_broken_symlinks: op.report_broken_symlinks,
+            track_symlink_targets: op.track_symlink_targets,
+            non_utf8: op.non_utf8,
+            //chosen once, set_notify_filters does not change which entries are reported
+            entries: op
+                .entries
+                .unwrap_or_else(|| Entries::for_notify_filters(op.notify_filters)),
+            require_filter: op.require_filter,
+            metadata_retry: op.metadata_retry,
+            read_on_change: op.read_on_change,
+            content_size_limit: op.content_size_limit,
+            discard_on_stop: op.discard_on_stop,
+            emit_deletes_on_stop: op.emit_deletes_on_stop,
+            max_event_rate: op.max_event_rate,
+            tick: op.tick,
+            filter_separators: op.filter_separators.clone(),
+            content_type_filter: op.content_type_filter.clone(),
+            ignore_atime_only: op.ignore_atime_only,
+            poll_jitter: op.poll_jitter,
+            ephemeral_probe: op.ephemeral_probe,
+            full_rescan_every: op.full_rescan_every,
+            operations: op.operations,
+            #[cfg(feature = "schedule")]
+            active_window: op.active_window,
+        }
+    }
+
+    pub fn dir_path(&self) -> &PathBuf {
+        &self.dir_path
+    }
+
+    // the watched directories, dir_path first
+    pub fn directories(&self) -> Vec<PathBuf> {
+        std::iter::once(self.dir_path.clone())
+            .chain(self.extra_dirs.lock().unwrap().iter().cloned())
+            .collect()
+    }
+
+    pub fn filter(&self) -> Option<&str> {
+        self.filter.as_deref()
+    }
+
+    pub fn refresh_rate(&self) -> Duration {
+        Duration::from_millis(self.refresh_rate_in_milliseconds)
+    }
+
+    pub fn dir_depth(&self) -> Option<u8> {
+        *self.dir_depth.lock().unwrap()
+    }
+
+    pub fn notify_filters(&self) -> NotifyFilters {
+        *self.notify_filters.lock().unwrap()
+    }
+
+    pub fn ignore_file(&self) -> Option<&PathBuf> {
+        self.ignore_file.as_ref()
+    }
+
+    // the files the watcher manages itself, which never produce events; only the ignore file
+    pub fn implicit_ignores(&self) -> &[PathBuf] {
+        &self.implicit_ignores
+    }
+
+    pub fn scan_budget(&self) -> Option<usize> {
+        self.scan_budget
+    }
+
+    pub fn max_runtime(&self) -> Option<Duration> {
+        self.max_runtime
+    }
+
+    pub fn overflow_threshold(&self) -> Option<Duration> {
+        self.overflow_threshold
+    }
+
+    pub fn rename_match(&self) -> RenameMatch {
+        self.rename_match
+    }
+
+    pub fn rename_fallback_reporting(&self) -> bool {
+        self.rename_fallback_reporting
+    }
+
+    pub fn rename_scope(&self) -> RenameScope {
+        self.rename_scope
+    }
+
+    pub fn wait_for_directory(&self) -> bool {
+        self.wait_for_directory
+    }
+
+    pub fn dedup_hardlinks(&self) -> bool {
+        self.dedup_hardlinks
+    }
+
+    pub fn report_broken_symlinks(&self) -> bool {
+        self.report_broken_symlinks
+    }
+
+    pub fn track_symlink_targets(&self) -> bool {
+        self.track_symlink_targets
+    }
+
+    pub fn non_utf8(&self) -> NonUtf8 {
+        self.non_utf8
+    }
+
+    pub fn entries(&self) -> Entries {
+        self.entries
+    }
+
+    pub fn require_filter(&self) -> bool {
+        self.require_filter
+    }
+
+    // the retries of a failed metadata read and the backoff before the first one
+    pub fn metadata_retry(&self) -> (u32, Duration) {
+        self.metadata_retry
+    }
+
+    pub fn read_on_change(&self) -> Option<u64> {
+        self.read_on_change
+    }
+
+    pub fn content_size_limit(&self) -> Option<u64> {
+        self.content_size_limit
+    }
+
+    pub fn discard_on_stop(&self) -> bool {
+        self.discard_on_stop
+    }
+
+    pub fn emit_deletes_on_stop(&self) -> bool {
+        self.emit_deletes_on_stop
+    }
+
+    pub fn max_event_rate(&self) -> Option<u32> {
+        self.max_event_rate
+    }
+
+    pub fn tick(&self) -> Option<Duration> {
+        self.tick
+    }
+
+    pub fn filter_separators(&self) -> Option<&[char]> {
+        self.filter_separators.as_deref()
+    }
+
+    // the filters split at the separators, none when every file matches as with "*.*"
+    fn filters(&self) -> Vec<&str> {
+        let separators = self.filter_separators().unwrap_or(FILTER_SEPARATORS);
+        let filters: Vec<&str> = self
+            .filter()
+            .unwrap_or_default()
+            .split(separators)
+            .filter(|f| !f.is_empty())
+            .collect();
+
+        match filters.contains(&ALL_FILES_FILTER) {
+            true => vec![],
+            false => filters,
+        }
+    }
+
+    pub fn content_type_filter(&self) -> Option<&[String]> {
+        self.content_type_filter.as_deref()
+    }
+
+    pub fn ignore_atime_only(&self) -> bool {
+        self.ignore_atime_only
+    }
+
+    pub fn poll_jitter(&self) -> Option<Duration> {
+        self.poll_jitter
+    }
+
+    pub fn ephemeral_probe(&self) -> Option<Duration> {
+        self.ephemeral_probe
+    }
+
+    pub fn full_rescan_every(&self) -> Option<u32> {
+        self.full_rescan_every
+    }
+
+    pub fn operations(&self) -> OperationMask {
+        self.operations
+    }
+
+    // the start and the end of the active window
+    #[cfg(feature = "schedule")]
+    pub fn active_window(&self) -> Option<(chrono::NaiveTime, chrono::NaiveTime)> {
+        self.active_window.map(|w| (w.start(), w.end()))
+    }
+
+    pub fn combined_events(&self) -> bool {
+        self.combined_events
+    }
+}
+
+// what stop() did with the operations the events thread had not handled yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StopSummary {
+    stopped: bool,
+    flushed: usize,
+    discarded: bool,
+}
+
+impl StopSummary {
+    // false when the watcher was not running
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    // operations still queued when the watcher stopped
+    pub fn flushed(&self) -> usize {
+        self.flushed
+    }
+
+    // true when the flushed operations were dropped instead of delivered
+    pub fn discarded(&self) -> bool {
+        self.discarded
+    }
+}
+
+// the operations of a watcher started with FileWatcher::into_receiver, used as the Receiver it
+// derefs to; dropping it stops the watcher
+pub struct OperationReceiver {
+    receiver: Receiver<OPERATION>,
+    dropped: Sender<()>,
+}
+
+impl std::ops::Deref for OperationReceiver {
+    type Target = Receiver<OPERATION>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.receiver
+    }
+}
+
+impl Drop for OperationReceiver {
+    fn drop(&mut self) {
+        let _ = self.dropped.send(());
+    }
+}
+
+// why a directory cannot be watched
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatcherError {
+    NotFound(PathBuf),
+    NotADirectory(PathBuf),
+    //the directory or one of its parents cannot be read
+    PermissionDenied(PathBuf),
+    //the directory and a filter with an entry that is not *.*, *.ext, file_name.ext or file[0-9].ext
+    InvalidFilter(PathBuf, String),
+    //the directory when a filter is required, see with_require_filter, and none narrows the files
+    MissingFilter(PathBuf),
+}
+
+impl WatcherError {
+    pub(crate) fn check(dir: &Path) -> Result<(), WatcherError> {
+        let denied_or_missing = |error: std::io::Error| match error.kind() {
+            std::io::ErrorKind::PermissionDenied => WatcherError::PermissionDenied(dir.into()),
+            _ => WatcherError::NotFound(dir.into()),
+        };
+
+        if !fs::metadata(dir).map_err(denied_or_missing)?.is_dir() {
+            return Err(WatcherError::NotADirectory(dir.into()));
+        }
+        fs::read_dir(dir).map_err(denied_or_missing)?;
+
+        Ok(())
+    }
+
+    pub(crate) fn check_filter(
+        dir: &Path,
+        filter: Option<&str>,
+        filter_separators: &[char],
+    ) -> Result<(), WatcherError> {
+        match filter {
+            Some(filter) if !SearchDir::is_valid_filter(filter, filter_separators) => {
+                Err(WatcherError::InvalidFilter(dir.into(), filter.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            WatcherError::NotFound(path)
+            | WatcherError::NotADirectory(path)
+            | WatcherError::PermissionDenied(path)
+            | WatcherError::InvalidFilter(path, _)
+            | WatcherError::MissingFilter(path) => path,
+        }
+    }
+}
+
+impl Display for WatcherError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatcherError::NotFound(path) => {
+                write!(f, "The directory '{}' does not exist!", path.display())
+            }
+            WatcherError::NotADirectory(path) => {
+                write!(f, "'{}' is not a directory!", path.display())
+            }
+            WatcherError::PermissionDenied(path) => {
+                write!(f, "The directory '{}' cannot be read!", path.display())
+            }
+            WatcherError::InvalidFilter(_, filter) => write!(
+                f,
+                "The filter '{}' should contain valid file extensions! i.e: *.*, *.ext, file_name.ext, file[0-9].ext",
+                filter
+            ),
+            WatcherError::MissingFilter(path) => write!(
+                f,
+                "A filter is required, every file in '{}' would be watched without one!",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WatcherError {}
+
+impl From<WatcherError> for std::io::Error {
+    fn from(error: WatcherError) -> Self {
+        let kind = match error {
+            WatcherError::NotFound(_) => std::io::ErrorKind::NotFound,
+            WatcherError::NotADirectory(_)
+            | WatcherError::InvalidFilter(..)
+            | WatcherError::MissingFilter(_) => std::io::ErrorKind::InvalidInput,
+            WatcherError::PermissionDenied(_) => std::io::ErrorKind::PermissionDenied,
+        };
+
+        std::io::Error::new(kind, error)
+    }
+}
+
+/// A `FileWatcher` is `Send` and `Sync`, as are its options, `File` and the event args handed to
+/// the callbacks. Callbacks run on the watcher's events thread, progress and runtime callbacks on
+/// its poll thread, so they must be `Send`.
+#[derive(Debug)]
+pub struct FileWatcher {
+    config: WatcherConfig,
+    last_sync: Option<SystemTime>,
+    main_thread: Option<Worker<()>>,
+    events_thread: Option<Worker<usize>>,
+    spawner: Spawner,
+    on_created: Option<Callback<OnCreatedEventArgs>>,
+    on_deleted: Option<Callback<OnDeletedEventArgs>>,
+    on_changed: Option<Callback<OnChangedEventArgs>>,
+    on_changed_in: Vec<(PathBuf, Callback<OnChangedEventArgs>)>,
+    on_created_for: Vec<(String, Callback<OnCreatedEventArgs>)>,
+    on_renamed: Option<Callback<OnRenamedEventArgs>>,
+    on_batch: Option<Callback<OnBatchEventArgs>>,
+    channel_sender: Option<Sender<ChannelOperation>>,
+    on_scan_progress: Option<Callback<usize>>,
+    on_max_runtime: Option<Callback<Duration>>,
+    on_overflow: Option<Callback<Duration>>,
+    change_detector: Option<ChangeDetector>,
+    on_watching: Option<Callback<PathBuf>>,
+    sink: Option<Sink>,
+    on_started: Option<Callback<()>>,
+    on_stopped: Option<Callback<()>>,
+    on_empty: Option<Callback<()>>,
+    on_non_empty: Option<Callback<()>>,
+    on_snapshot: Option<Callback<HashSet<File>>>,
+    on_ephemeral: Option<Callback<HashSet<String>>>,
+    on_metadata_error: Option<Callback<(PathBuf, std::io::Error)>>,
+    event_senders: Arc<Mutex<Vec<EventSender>>>,
+    #[cfg(feature = "crossbeam")]
+    crossbeam_senders: Arc<Mutex<Vec<crossbeam_channel::Sender<OPERATION>>>>,
+    #[cfg(feature = "webhook")]
+    webhook: Option<WebhookUrl>,
+    suppressions: Suppressions,
+    unreadable_dirs: Arc<Mutex<BTreeSet<PathBuf>>>,
+    //the receiver of into_receiver, and where to signal that it was dropped
+    receiver_link: Option<(Sender<OPERATION>, Sender<()>)>,
+    //set by reset(), cleared by the poll thread once it took the new baseline
+    reset_requested: Arc<Mutex<bool>>,
+    stats: Arc<Mutex<WatcherStats>>,
+    is_started: Arc<Mutex<bool>>,
+    //whether on_stopped was called since the watcher started
+    stopped_reported: Arc<Mutex<bool>>,
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        if *self.is_started.lock().unwrap() {
+            let _ = self.stop();
+        }
+
+        self.config.dir_path.clear();
+
+        self.config.filter = None;
+        self.last_sync = None;
+        self.on_changed = None;
+        self.channel_sender = None;
+        self.config.refresh_rate_in_milliseconds = 0;
+        self.main_thread = None;
+        self.events_thread = None;
+        self.on_created = None;
+        self.on_deleted = None;
+        self.on_renamed = None;
+
+        drop(self.is_started.lock());
+    }
+}
+
+impl FileWatcher {
+    // panics where FileWatcherOptions::build errors, prefer build for directories that may be
+    // missing or wrong
+    pub fn new_with_options(op: &FileWatcherOptions) -> Self {
+        op.build().unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    // the watcher of the options, which build() checked
+    fn from_options(op: &FileWatcherOptions) -> Self {
+        let config = WatcherConfig::from_options(op);
+        let on_changed_in = op
+            .on_changed_in
+            .iter()
+            .map(|(prefix, on_event)| (config.dir_path.join(prefix), on_event.clone()))
+            .collect();
+        let on_created_for = op
+            .on_created_for
+            .iter()
+            .map(|(extension, on_event)| {
+                let extension = extension.trim_start_matches('.').to_string();
+                (extension, on_event.clone())
+            })
+            .collect();
+
+        Self {
+            config,
+            last_sync: None,
+            main_thread: None,
+            events_thread: None,
+            spawner: Spawner::default(),
+            on_changed: op.on_changed.clone(),
+            on_changed_in,
+            on_created_for,
+            on_created: op.on_created.clone(),
+            on_deleted: op.on_deleted.clone(),
+            on_renamed: op.on_renamed.clone(),
+            on_batch: op.on_batch.clone(),
+            channel_sender: None,
+            on_scan_progress: op.on_scan_progress.clone(),
+            on_max_runtime: op.on_max_runtime.clone(),
+            on_overflow: op.on_overflow.clone(),
+            change_detector: op.change_detector.clone(),
+            on_watching: op.on_watching.clone(),
+            sink: op.sink.clone(),
+            on_started: op.on_started.clone(),
+            on_stopped: op.on_stopped.clone(),
+            on_empty: op.on_empty.clone(),
+            on_non_empty: op.on_non_empty.clone(),
+            on_snapshot: op.on_snapshot.clone(),
+            on_ephemeral: op.on_ephemeral.clone(),
+            on_metadata_error: op.on_metadata_error.clone(),
+            event_senders: Arc::new(Mutex::new(vec![])),
+            #[cfg(feature = "crossbeam")]
+            crossbeam_senders: Arc::new(Mutex::new(vec![])),
+            #[cfg(feature = "webhook")]
+            webhook: None,
+            suppressions: Suppressions::default(),
+            unreadable_dirs: Arc::new(Mutex::new(BTreeSet::new())),
+            receiver_link: None,
+            reset_requested: Arc::new(Mutex::new(false)),
+            stats: Arc::new(Mutex::new(WatcherStats::default())),
+            is_started: Arc::new(Mutex::new(false)),
+            stopped_reported: Arc::new(Mutex::new(false)),
+        }
+    }
+
+    // panics where try_new errors, prefer try_new for directories that may be missing or wrong
+    pub fn new(
+        dir: &str,
+        filter: Option<String>,
+        refresh_rate_in_milliseconds: u64,
+        dir_depth: Option<u8>,
+    ) -> Self {
+        Self::try_new(dir, filter, refresh_rate_in_milliseconds, dir_depth)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    // the directory must exist and be readable, and the filter be valid
+    pub fn try_new(
+        dir: &str,
+        filter: Option<String>,
+        refresh_rate_in_milliseconds: u64,
+        dir_depth: Option<u8>,
+    ) -> Result<Self, WatcherError> {
+        let dir_path = PathBuf::from(dir);
+        WatcherError::check(&dir_path)?;
+        WatcherError::check_filter(&dir_path, filter.as_deref(), FILTER_SEPARATORS)?;
+
+        let mut op = FileWatcherOptions::new(dir);
+        op.filter = filter;
+        op.refresh_rate_mils = refresh_rate_in_milliseconds;
+        op.dir_depth = dir_depth;
+
+        Ok(Self::from_options(&op))
+    }
+
+    // a started watcher of the directory with the defaults, 250ms and NotifyFilters::DEFAULT, and
+    // a bounded receiver of its events, see events_rx_bounded
+    pub fn watch(
+        dir: &str,
+        filter: Option<String>,
+    ) -> Result<(FileWatcher, Receiver<WatchEvent>), std::io::Error> {
+        let mut fw = Self::try_new(dir, filter, WATCH_REFRESH_RATE, None)?;
+        let events = fw.events_rx_bounded(WATCH_BUFFER);
+        fw.start()?;
+
+        Ok((fw, events))
+    }
+
+    pub fn watched_dir(&self) -> &str {
+        self.config.dir_path.as_os_str().to_str().unwrap()
+    }
+
+    pub fn filter(&self) -> Option<&str> {
+        self.config.filter.as_deref()
+    }
+
+    pub fn on_created(&mut self, action: impl FnMut(OnCreatedEventArgs) + Send + 'static) -> &Self {
+        self.on_created = Some(Callback::new(Box::new(action)));
+
+        self
+    }
+
+    pub fn on_created_for(
+        &mut self,
+        extension: &str,
+        action: impl FnMut(OnCreatedEventArgs) + Send + 'static,
+    ) -> &Self {
+        let extension = extension.trim_start_matches('.').to_string();
+        self.on_created_for
+            .push((extension, Callback::new(Box::new(action))));
+
+        self
+    }
+
+    pub fn on_changed(&mut self, action: impl FnMut(OnChangedEventArgs) + Send + 'static) -> &Self {
+        self.on_changed = Some(Callback::new(Box::new(action)));
+
+        self
+    }
+
+    pub fn on_changed_in(
+        &mut self,
+        prefix: &str,
+        action: impl FnMut(OnChangedEventArgs) + Send + 'static,
+    ) -> &Self {
+        self.on_changed_in.push((
+            self.config.dir_path.join(prefix),
+            Callback::new(Box::new(action)),
+        ));
+
+        self
+    }
+
+    pub fn on_deleted(&mut self, action: impl FnMut(OnDeletedEventArgs) + Send + 'static) -> &Self {
+        self.on_deleted = Some(Callback::new(Box::new(action)));
+
+        self
+    }
+
+    pub fn on_renamed(&mut self, action: impl FnMut(OnRenamedEventArgs) + Send + 'static) -> &Self {
+        self.on_renamed = Some(Callback::new(Box::new(action)));
+
+        self
+    }
+
+    pub fn on_batch(&mut self, action: impl FnMut(OnBatchEventArgs) + Send + 'static) -> &Self {
+        self.on_batch = Some(Callback::new(Box::new(action)));
+
+        self
+    }
+
+    // posts every operation as JSON to an http:// or https:// url from a thread of its own,
+    // retrying failed posts; takes effect from the next start. The posts that failed every retry
+    // and the operations dropped because too many posts were waiting reach WatchSink::on_error
+    #[cfg(feature = "webhook")]
+    pub fn with_webhook(&mut self, url: &str) -> Result<&mut Self, std::io::Error> {
+        self.webhook = Some(WebhookUrl::parse(url)?);
+
+        Ok(self)
+    }
+
+    pub fn start(&mut self) -> Result<bool, std::io::Error> {
+        self.start_with_baseline(None)
+    }
+
+    // the first poll reports how the directory diverged from the manifest: files missing from it
+    // as created, files that differ according to the notify filters as changed and files missing
+    // from the disk as deleted
+    pub fn start_from_manifest(
+        &mut self,
+        manifest: Vec<(PathBuf, SystemTime, u64)>,
+    ) -> Result<bool, std::io::Error> {
+        let baseline: HashSet<File> = manifest
+            .into_iter()
+            .map(|(path, last_modified, size)| File::from_manifest(&path, last_modified, size))
+            .collect();
+
+        self.start_with_baseline(Some(baseline))
+    }
+
+    fn start_with_baseline(
+        &mut self,
+        baseline: Option<HashSet<File>>,
+    ) -> Result<bool, std::io::Error> {
+        if *self.is_started.lock().unwrap() {
+            return Ok(false);
+        }
+
+        let config = self.config.clone();
+        Self::check_require_filter(&config)?;
+        //the directories may have been removed since the watcher was created
+        if !config.wait_for_directory {
+            for dir in config.directories() {
+                WatcherError::check(&dir)?;
+            }
+        }
+
+        // communication channel
+        let (sender, receiver) = channel::<ChannelOperation>();
+        let sender_mutex = Mutex::new(sender.clone());
+        let receiver_mutex = Mutex::new(receiver);
+        let on_scan_progress = self.on_scan_progress.clone();
+        let change_detector = self.change_detector.clone();
+        let on_watching = self.on_watching.clone();
+        let on_started = self.on_started.clone();
+        let on_stopped = self.on_stopped.clone();
+        *self.stopped_reported.lock().unwrap() = false;
+        let stopped_reported = Arc::clone(&self.stopped_reported);
+        let on_empty = self.on_empty.clone();
+        let on_non_empty = self.on_non_empty.clone();
+        let on_snapshot = self.on_snapshot.clone();
+        let on_ephemeral = self.on_ephemeral.clone();
+        let on_metadata_error = self.on_metadata_error.clone();
+        let unreadable_dirs = Arc::clone(&self.unreadable_dirs);
+        let on_max_runtime = self.on_max_runtime.clone();
+        let on_overflow = self.on_overflow.clone();
+        let notify_filters_mutex = Arc::clone(&self.config.notify_filters);
+        let dir_depth_mutex = Arc::clone(&self.config.dir_depth);
+        let reset_requested = Arc::clone(&self.reset_requested);
+        let extra_dirs_mutex = Arc::clone(&self.config.extra_dirs);
+
+        let refresh_rate: u64 = self.config.refresh_rate_in_milliseconds;
+        let on_created = self.on_created.clone();
+        let on_deleted = self.on_deleted.clone();
+        let on_changed = self.on_changed.clone();
+        let on_changed_in = self.on_changed_in.clone();
+        let on_created_for = self.on_created_for.clone();
+        let on_renamed = self.on_renamed.clone();
+        let on_batch = self.on_batch.clone();
+        let sink = self.sink.clone();
+        let event_senders = Arc::clone(&self.event_senders);
+        #[cfg(feature = "crossbeam")]
+        let crossbeam_senders = Arc::clone(&self.crossbeam_senders);
+        #[cfg(feature = "webhook")]
+        let webhook = self
+            .webhook
+            .clone()
+            .map(|url| Webhook::spawn(url, self.sink.clone()));
+        let suppressions = self.suppressions.clone();
+        //only the events thread keeps the link, its exit then tells into_receiver to stop as well
+        let receiver_link = self.receiver_link.take();
+        let stats = Arc::clone(&self.stats);
+        let events_started = Arc::clone(&self.is_started);
+        let events_stats = Arc::clone(&self.stats);
+        let events_on_stopped = self.on_stopped.clone();
+        let events_stopped_reported = Arc::clone(&self.stopped_reported);
+
+        //child thread for receiving changed files, returns how many operations were still queued
+        //once the watcher was stopping
+        let child = self.spawner.spawn(move || {
+            let mut flushed: usize = 0;
+            let mut throttle = config.max_event_rate.map(EventThrottle::new);
+            let mut tick = config
+                .tick
+                .map(|tick| EventTick::new(tick, config.combined_events));
+
+            //numbers the event arguments handed to the callbacks, see OnCreatedEventArgs::seq
+            let seq = Cell::new(0_u64);
+            let next_seq = || {
+                seq.set(seq.get() + 1);
+                seq.get()
+            };
+
+            let dispatch = |op: OPERATION, poll_span: &PollSpan| {
+                let _dispatching = poll_span.dispatch(&op);
+                Self::send_watch_events(&op, &event_senders, &events_stats);
+
+                if let Some((sender, dropped)) = &receiver_link {
+                    if sender.send(op.clone()).is_err() {
+                        let _ = dropped.send(());
+                    }
+                }
+
+                #[cfg(feature = "crossbeam")]
+                {
+                    let mut senders = crossbeam_senders.lock().unwrap();
+                    senders.retain(|sender| sender.send(op.clone()).is_ok());
+                }
+
+                #[cfg(feature = "webhook")]
+                if let Some(webhook) = &webhook {
+                    webhook.send(&op);
+                }
+
+                if let Some(sink) = &sink {
+                    sink.send(&op);
+                }
+
+                match op {
+                    OPERATION::CREATE(mut data) => {
+                        for (extension, func) in on_created_for.iter() {
+                            let files: HashSet<File>;
+                            (files, data) = data.into_iter().partition(|f| {
+                                f.extension()
+                                    .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+                            });
+
+                            if !files.is_empty() {
+                                func.call(OnCreatedEventArgs::new(files).with_seq(next_seq()));
+                            }
+                        }
+
+                        if let (false, Some(func)) = (data.is_empty(), &on_created) {
+                            func.call(
+                                OnCreatedEventArgs::new(data.into_iter().collect())
+                                    .with_seq(next_seq()),
+                            );
+                        }
+                    }
+                    OPERATION::CHANGE(data) => {
+                        for (prefix, func) in on_changed_in.iter() {
+                            let files: HashSet<ChangedFile> = data
+                                .iter()
+                                .filter(|f| Path::new(f.name()).starts_with(prefix))
+                                .cloned()
+                                .collect();
+
+                            if !files.is_empty() {
+                                func.call(OnChangedEventArgs::new(files).with_seq(next_seq()));
+                            }
+                        }
+
+                        if let Some(func) = &on_changed {
+                            func.call(
+                                OnChangedEventArgs::new(data.into_iter().collect())
+                                    .with_seq(next_seq()),
+                            );
+                        }
+                    }
+                    OPERATION::DELETE(data) => {
+                        if let Some(func) = &on_deleted {
+                            func.call(
+                                OnDeletedEventArgs::new(data.into_iter().collect())
+                                    .with_seq(next_seq()),
+                            );
+                        }
+                    }
+                    OPERATION::RENAME(data) => {
+                        if let Some(func) = &on_renamed {
+                            func.call(
+                                OnRenamedEventArgs::new(data.into_iter().collect())
+                                    .with_seq(next_seq()),
+                            );
+                        }
+                    }
+                    OPERATION::BATCH {
+                        created,
+                        changed,
+                        deleted,
+                        renamed,
+                    } => {
+                        if let Some(func) = &on_batch {
+                            func.call(
+                                OnBatchEventArgs::new(created, changed, deleted, renamed)
+                                    .with_seq(next_seq()),
+                            );
+                        }
+                    }
+                    OPERATION::ERROR(_) => todo!(),
+                }
+            };
+
+            let deliver =
+                |op: OPERATION, throttle: &mut Option<EventThrottle>, poll_span: &PollSpan| {
+                    match throttle.as_mut() {
+                        Some(throttle) => {
+                            if throttle.push(op) {
+                                events_stats.lock().unwrap().record_coalesced();
                             }
                         }
+                        None => dispatch(op, poll_span),
+                    }
+                };
+
+            let flushed = loop {
+                //operations held back by the throttle or the tick are released once the rate or
+                //the boundary allows it
+                let wait = [
+                    throttle.as_ref().and_then(|t| t.next_release_in()),
+                    tick.as_ref().and_then(|t| t.next_release_in()),
+                ]
+                .into_iter()
+                .flatten()
+                .min();
+                let received = match wait {
+                    Some(wait) => receiver_mutex.lock().unwrap().recv_timeout(wait),
+                    None => receiver_mutex
+                        .lock()
+                        .unwrap()
+                        .recv()
+                        .map_err(RecvTimeoutError::from),
+                };
+
+                match received {
+                    Ok(ChannelOperation::CONTINUE(op, poll_span)) => {
+                        if !*events_started.lock().unwrap() {
+                            flushed += 1;
+
+                            if config.discard_on_stop {
+                                continue;
+                            }
+                        }
+
+                        //the watcher's own writes are dropped before they count against the rate
+                        let op = match suppressions.filter(*op) {
+                            Some(op) => op,
+                            None => continue,
+                        };
+
+                        match tick.as_mut() {
+                            Some(tick) => tick.push(op),
+                            None => deliver(op, &mut throttle, &poll_span),
+                        }
+                    }
+                    Ok(ChannelOperation::EXIT) => {
+                        //neither the boundary nor the rate delay stopping
+                        if let Some(tick) = tick.as_mut() {
+                            let held = tick.drain();
+                            if !config.discard_on_stop {
+                                for op in held {
+                                    deliver(op, &mut throttle, &PollSpan::none());
+                                }
+                            }
+                        }
+                        if let Some(throttle) = throttle.as_mut() {
+                            let held = throttle.drain();
+                            if !config.discard_on_stop {
+                                for op in held {
+                                    dispatch(op, &PollSpan::none());
+                                }
+                            }
+                        }
+
+                        break flushed;
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(error) => {
+                        println!("{}", error);
+                        break flushed;
+                    }
+                }
+
+                if let Some(tick) = tick.as_mut() {
+                    for op in tick.release() {
+                        deliver(op, &mut throttle, &PollSpan::none());
+                    }
+                }
+
+                if let Some(throttle) = throttle.as_mut() {
+                    for op in throttle.release() {
+                        dispatch(op, &PollSpan::none());
+                    }
+                }
+            };
+
+            //the last thread to exit, whether stopped or stopping on its own
+            Self::report_stopped(&events_on_stopped, &events_stopped_reported);
+            flushed
+        });
+
+        *self.is_started.lock().unwrap() = true;
+        let is_started = self.is_started.clone();
+
+        //main thread for checking for changes in the directory
+        let main = self.spawner.spawn(move || {
+            let started_at = Instant::now();
+
+            //a missing directory is only tolerated when waiting for it to be created
+            while config.wait_for_directory && !config.directories().iter().all(|d| d.is_dir()) {
+                if !*is_started.lock().unwrap() {
+                    let _ = sender_mutex.lock().unwrap().send(ChannelOperation::EXIT);
+                    return;
+                }
+
+                thread::sleep(Duration::from_millis(refresh_rate));
+            }
+
+            let new_poller = |dir: &Path, baseline: Option<HashSet<File>>| {
+                let search_dir = Self::build_search_dir(
+                    &config,
+                    dir,
+                    on_metadata_error.clone(),
+                    Arc::clone(&unreadable_dirs),
+                )?;
+
+                Ok::<_, WatcherError>(Self::build_poller(
+                    &config,
+                    search_dir,
+                    baseline,
+                    Arc::clone(&notify_filters_mutex),
+                    on_scan_progress.clone(),
+                    change_detector.clone(),
+                    Arc::clone(&dir_depth_mutex),
+                ))
+            };
+
+            let directories = config.directories();
+            let multiple_roots = directories.len() > 1;
+            let pollers: Result<Vec<(PathBuf, Poller)>, WatcherError> = directories
+                .into_iter()
+                .map(|dir| {
+                    //each directory starts from the files of the baseline under it
+                    let baseline = baseline.as_ref().map(|files| match multiple_roots {
+                        true => files
+                            .iter()
+                            .filter(|f| Path::new(f.name()).starts_with(&dir))
+                            .cloned()
+                            .collect(),
+                        false => files.clone(),
+                    });
+
+                    new_poller(&dir, baseline).map(|poller| (dir, poller))
+                })
+                .collect();
+            //a directory removed after start() checked it stops the watcher
+            let pollers = match pollers {
+                Ok(pollers) => pollers,
+                Err(_) => {
+                    *is_started.lock().unwrap() = false;
+                    let _ = sender_mutex.lock().unwrap().send(ChannelOperation::EXIT);
+                    return;
+                }
+            };
+            let mut roots = Roots::new(pollers);
+
+            stats
+                .lock()
+                .unwrap()
+                .set_tracked_bytes(roots.tracked_bytes());
+
+            if let Some(callback) = &on_watching {
+                for dir in config.directories() {
+                    callback.call(dir.to_path_buf());
+                }
+            }
+
+            if let Some(callback) = &on_snapshot {
+                callback.call(roots.files());
+            }
+
+            if let Some(callback) = &on_started {
+                callback.call(());
+            }
+
+            let refresh_interval = Duration::from_millis(refresh_rate);
+            let mut lag = Duration::ZERO;
+            let mut jitter = config.poll_jitter.map(Jitter::new);
+            let mut probe = Self::build_probe(&config);
+            let mut was_empty = roots.is_empty();
+
+            //false once the events thread is gone, e.g. after a callback panicked
+            let send_operations = |mut operations: Vec<OPERATION>, poll_span: &PollSpan| {
+                if config.combined_events {
+                    operations = OPERATION::combine(operations);
+                }
+
+                let local_sender = sender_mutex.lock().unwrap();
+                operations.into_iter().all(|operation| {
+                    local_sender
+                        .send(ChannelOperation::CONTINUE(
+                            Box::new(operation),
+                            poll_span.clone(),
+                        ))
+                        .is_ok()
+                })
+            };
+
+            //queued ahead of the exit message, the events thread delivers it before exiting
+            let send_final_deletes = |roots: &Roots| {
+                if config.emit_deletes_on_stop
+                    && config.operations.contains(OperationMask::DELETE)
+                    && !roots.is_empty()
+                {
+                    let operations = vec![OPERATION::DELETE(roots.files())];
+                    let _ = send_operations(operations, &PollSpan::none());
+                }
+            };
+
+            //a last poll, what changed before the watcher stopped is delivered ahead of the exit
+            //message since the channel keeps the order
+            let send_final_poll = |roots: &mut Roots, was_empty: &mut bool| {
+                let poll_span = PollSpan::new();
+                let operations = poll_span.in_scope(|| roots.poll());
+                let _ = send_operations(operations, &poll_span);
+                Self::report_emptiness(roots, was_empty, &on_empty, &on_non_empty);
+                send_final_deletes(roots);
+
+                //send an exit message for the child thread handling events
+                let _ = sender_mutex.lock().unwrap().send(ChannelOperation::EXIT);
+            };
+
+            loop {
+                //check if the main thread should stop
+                if !*is_started.lock().unwrap() {
+                    send_final_poll(&mut roots, &mut was_empty);
+                    break;
+                }
+
+                //stop on our own once the max runtime is exceeded
+                let runtime = started_at.elapsed();
+                if config.max_runtime.is_some_and(|max| runtime >= max) {
+                    *is_started.lock().unwrap() = false;
+                    send_final_poll(&mut roots, &mut was_empty);
+
+                    if let Some(callback) = &on_max_runtime {
+                        callback.call(runtime);
+                    }
+                    break;
+                }
+
+                #[cfg(feature = "schedule")]
+                if let Some(closed_for) = config.active_window.and_then(|w| w.closed_for()) {
+                    thread::sleep(closed_for.min(refresh_interval.max(DORMANT_INTERVAL)));
+                    continue;
+                }
+
+                if std::mem::take(&mut *reset_requested.lock().unwrap()) {
+                    roots.rebaseline();
+                    was_empty = roots.is_empty();
+                }
+
+                //the directories added or removed since the last poll, see add_path
+                let extra_dirs = extra_dirs_mutex.lock().unwrap().clone();
+                roots.retain_extra(|dir| extra_dirs.iter().any(|extra| extra == dir));
+                for dir in extra_dirs.iter() {
+                    if roots.watches(dir) {
+                        continue;
+                    }
+
+                    //one removed since it was added is tried again on the next poll
+                    match new_poller(dir, None) {
+                        Ok(poller) => roots.add(dir.clone(), poller),
+                        Err(_) => continue,
                     }
+                    if let Some(callback) = &on_watching {
+                        callback.call(dir.clone());
+                    }
+                }
+
+                let poll_span = PollSpan::new();
+                let scan_started = Instant::now();
+                let operations = poll_span.in_scope(|| roots.poll());
+                let scan_duration = scan_started.elapsed();
+                let mut local_stats = stats.lock().unwrap();
+                local_stats.record_scan(scan_duration);
+                local_stats.set_tracked_bytes(roots.tracked_bytes());
+                drop(local_stats);
+
+                //a poll within the refresh rate means polling caught up again
+                lag = match scan_duration.checked_sub(refresh_interval) {
+                    Some(over) if !over.is_zero() => lag + over,
+                    _ => Duration::ZERO,
+                };
+                if config
+                    .overflow_threshold
+                    .is_some_and(|threshold| lag > threshold)
+                {
+                    if let Some(callback) = &on_overflow {
+                        callback.call(lag);
+                    }
+                    lag = Duration::ZERO;
+                }
+
+                poll_span.record(roots.scanned(), scan_duration, &operations);
+                //nothing would receive the next polls either, stop instead of polling for nothing
+                if !send_operations(operations, &poll_span) {
+                    *is_started.lock().unwrap() = false;
+                    Self::report_stopped(&on_stopped, &stopped_reported);
+                    break;
+                }
+                Self::report_emptiness(&roots, &mut was_empty, &on_empty, &on_non_empty);
+                Self::report_ephemeral(probe.as_mut(), roots.primary(), &on_ephemeral);
+
+                let offset = jitter.as_mut().map_or(Duration::ZERO, Jitter::next_offset);
+                Self::wait_for_poll(probe.as_mut(), roots.primary(), refresh_interval + offset);
+            }
+        });
+
+        self.main_thread = Some(main);
+        self.events_thread = Some(child);
+        self.channel_sender = Some(sender.clone());
+
+        Ok(true)
+    }
+
+    // calls on_stopped unless it was already called since the watcher started
+    fn report_stopped(on_stopped: &Option<Callback<()>>, stopped_reported: &Mutex<bool>) {
+        if std::mem::replace(&mut *stopped_reported.lock().unwrap(), true) {
+            return;
+        }
+
+        if let Some(callback) = on_stopped {
+            callback.call(());
+        }
+    }
+
+    // polls a last time and waits for the operations still queued to be delivered, or dropped
+    // with discard_on_stop; every change made before the call is among them
+    pub fn stop(&mut self) -> Result<StopSummary, Error> {
+        let mut summary = StopSummary {
+            stopped: false,
+            flushed: 0,
+            discarded: self.config.discard_on_stop,
+        };
+
+        if !*self.is_started.lock().unwrap() {
+            return Ok(summary);
+        }
+
+        //set the flag to false to be picked up by the main thread
+        *self.is_started.lock().unwrap() = false;
+
+        //wait for the main thread to pick up the flag, it also tells the events thread to exit
+        if let Some(main) = self.main_thread.take() {
+            let _ = main.join();
+        }
+
+        if let Some(events) = self.events_thread.take() {
+            summary.flushed = events.join().unwrap_or(0);
+        }
+        summary.stopped = true;
+
+        //already called when the events thread exited, unless it panicked
+        Self::report_stopped(&self.on_stopped, &self.stopped_reported);
+
+        Ok(summary)
+    }
+
+    // starts the watcher and blocks until `stop` receives a message or is disconnected, or the
+    // watcher stops on its own, then stops it and waits for pending events to be dispatched
+    pub fn watch_until(&mut self, stop: Receiver<()>) -> Result<bool, std::io::Error> {
+        if !self.start()? {
+            return Ok(false);
+        }
+
+        let check_interval = Duration::from_millis(self.config.refresh_rate_in_milliseconds.max(1));
+        loop {
+            match stop.recv_timeout(check_interval) {
+                Err(RecvTimeoutError::Timeout) if self.is_running() => continue,
+                _ => break,
+            }
+        }
+
+        let _ = self.stop();
+
+        Ok(true)
+    }
+
+    // for services: watches until SIGINT or SIGTERM (Ctrl-C on Windows) is received
+    #[cfg(feature = "signals")]
+    pub fn watch_and_block(&mut self) -> Result<bool, std::io::Error> {
+        let (sender, receiver) = channel::<()>();
+        ctrlc::set_handler(move || {
+            let _ = sender.send(());
+        })
+        .map_err(std::io::Error::other)?;
+
+        self.watch_until(receiver)
+    }
+
+    // runs the poll loop on the current thread for the given window and returns every
+    // operation detected, in detection order; registered callbacks are not invoked. A watched
+    // directory that can't be read is returned as an error
+    pub fn collect_changes(&mut self, window: Duration) -> Result<Vec<OPERATION>, WatcherError> {
+        let deadline = Instant::now() + window;
+        let refresh_rate = Duration::from_millis(self.config.refresh_rate_in_milliseconds);
+
+        let config = &self.config;
+        Self::check_require_filter(config)?;
+        let pollers: Vec<(PathBuf, Poller)> = config
+            .directories()
+            .into_iter()
+            .map(|dir| {
+                let search_dir = Self::build_search_dir(
+                    config,
+                    &dir,
+                    self.on_metadata_error.clone(),
+                    Arc::clone(&self.unreadable_dirs),
+                )?;
+
+                let poller = Self::build_poller(
+                    config,
+                    search_dir,
+                    None,
+                    Arc::clone(&config.notify_filters),
+                    self.on_scan_progress.clone(),
+                    self.change_detector.clone(),
+                    Arc::clone(&config.dir_depth),
+                );
+
+                Ok((dir, poller))
+            })
+            .collect::<Result<_, WatcherError>>()?;
+        let mut roots = Roots::new(pollers);
+
+        let mut probe = Self::build_probe(config);
+        let mut result: Vec<OPERATION> = vec![];
+        loop {
+            let wait = refresh_rate.min(deadline.saturating_duration_since(Instant::now()));
+            Self::wait_for_poll(probe.as_mut(), roots.primary(), wait);
+
+            let poll_span = PollSpan::new();
+            let scan_started = Instant::now();
+            let mut operations = poll_span.in_scope(|| roots.poll());
+            if config.combined_events {
+                operations = OPERATION::combine(operations);
+            }
+            poll_span.record(roots.scanned(), scan_started.elapsed(), &operations);
+            result.extend(operations);
+            Self::report_ephemeral(probe.as_mut(), roots.primary(), &self.on_ephemeral);
+
+            if Instant::now() >= deadline {
+                break;
+            }
+        }
+
+        Ok(result)
+    }
+
+    // "*.*" watches every file just as no filter does
+    fn check_require_filter(config: &WatcherConfig) -> Result<(), WatcherError> {
+        if config.require_filter
+            && config.filters().is_empty()
+            && config.content_type_filter().is_none()
+        {
+            return Err(WatcherError::MissingFilter(config.dir_path.clone()));
+        }
+
+        Ok(())
+    }
+
+    // the poller of one of the watched directories, starting from the baseline when given
+    fn build_poller(
+        config: &WatcherConfig,
+        search_dir: SearchDir,
+        baseline: Option<HashSet<File>>,
+        notify_filters: Arc<Mutex<NotifyFilters>>,
+        on_scan_progress: Option<Callback<usize>>,
+        change_detector: Option<ChangeDetector>,
+        dir_depth: Arc<Mutex<Option<u8>>>,
+    ) -> Poller {
+        let mut poller = match baseline {
+            Some(files) => {
+                Poller::from_baseline(search_dir, notify_filters, config.scan_budget, files)
+            }
+            None => Poller::new(
+                search_dir,
+                notify_filters,
+                config.scan_budget,
+                on_scan_progress,
+            ),
+        };
+        poller
+            .with_rename_match(config.rename_match)
+            .with_rename_fallback_reporting(config.rename_fallback_reporting)
+            .with_rename_scope(config.rename_scope)
+            .with_change_detector(change_detector)
+            .with_read_on_change(config.read_on_change)
+            .with_content_size_limit(config.content_size_limit)
+            .with_ignore_atime_only(config.ignore_atime_only)
+            .with_full_rescan_every(config.full_rescan_every)
+            .with_operations(config.operations)
+            .with_depth(dir_depth);
+
+        poller
+    }
+
+    // an incremental scan does not hold every file after a poll, the probe would take the others
+    // for ephemeral files
+    fn build_probe(config: &WatcherConfig) -> Option<EphemeralProbe> {
+        match config.scan_budget {
+            Some(_) => None,
+            None => config.ephemeral_probe.map(EphemeralProbe::new),
+        }
+    }
+
+    fn wait_for_poll(probe: Option<&mut EphemeralProbe>, poller: &Poller, wait: Duration) {
+        match probe {
+            Some(probe) => probe.sleep(wait, poller.search_dir(), poller.files()),
+            None => thread::sleep(wait),
+        }
+    }
+
+    fn report_ephemeral(
+        probe: Option<&mut EphemeralProbe>,
+        poller: &Poller,
+        on_ephemeral: &Option<Callback<HashSet<String>>>,
+    ) {
+        let ephemeral = match probe {
+            Some(probe) => probe.take_ephemeral(poller.files()),
+            None => return,
+        };
+
+        if let (false, Some(callback)) = (ephemeral.is_empty(), on_ephemeral) {
+            callback.call(ephemeral);
+        }
+    }
+
+    // calls back when the tracked files went from none to some or back since the last poll
+    fn report_emptiness(
+        roots: &Roots,
+        was_empty: &mut bool,
+        on_empty: &Option<Callback<()>>,
+        on_non_empty: &Option<Callback<()>>,
+    ) {
+        let is_empty = roots.is_empty();
+        if is_empty == *was_empty {
+            return;
+        }
+        *was_empty = is_empty;
+
+        let callback = match is_empty {
+            true => on_empty,
+            false => on_non_empty,
+        };
+        if let Some(callback) = callback {
+            callback.call(());
+        }
+    }
+
+    // starts the watcher and hands out its operations, the watcher keeps running in the background
+    // until the receiver is dropped. A watcher that cannot start leaves the receiver disconnected.
+    pub fn into_receiver(mut self) -> OperationReceiver {
+        let (sender, receiver) = channel::<OPERATION>();
+        let (dropped_sender, dropped) = channel::<()>();
+        self.receiver_link = Some((sender, dropped_sender.clone()));
+
+        if let Ok(true) = self.start() {
+            //also woken up when the events thread exits on its own, i.e. at the max runtime
+            thread::spawn(move || {
+                let _ = dropped.recv();
+                let _ = self.stop();
+            });
+        }
+
+        OperationReceiver {
+            receiver,
+            dropped: dropped_sender,
+        }
+    }
+
+    // every file of every operation as its own event, alongside the callbacks; works for
+    // watchers started before or after the call and stops when the receiver is dropped
+    pub fn events_rx(&self) -> Receiver<WatchEvent> {
+        let (sender, receiver) = channel::<WatchEvent>();
+        self.event_senders
+            .lock()
+            .unwrap()
+            .push(EventSender::Unbounded(sender));
+
+        receiver
+    }
+
+    // the same holding at most `capacity` events, the ones arriving while it is full are dropped
+    // and counted in WatcherStats::dropped_events instead of holding up the events thread
+    pub fn events_rx_bounded(&self, capacity: usize) -> Receiver<WatchEvent> {
+        let (sender, receiver) = sync_channel::<WatchEvent>(capacity);
+        self.event_senders
+            .lock()
+            .unwrap()
+            .push(EventSender::Bounded(sender));
+
+        receiver
+    }
+
+    // every operation on a crossbeam channel, to select! over it along with other channels; works
+    // for watchers started before or after the call and stops when the receiver is dropped
+    #[cfg(feature = "crossbeam")]
+    pub fn crossbeam_receiver(&self) -> crossbeam_channel::Receiver<OPERATION> {
+        let (sender, receiver) = crossbeam_channel::unbounded::<OPERATION>();
+        self.crossbeam_senders.lock().unwrap().push(sender);
+
+        receiver
+    }
+
+    fn send_watch_events(
+        op: &OPERATION,
+        event_senders: &Mutex<Vec<EventSender>>,
+        stats: &Mutex<WatcherStats>,
+    ) {
+        let mut senders = event_senders.lock().unwrap();
+        if senders.is_empty() {
+            return;
+        }
+
+        let events = WatchEvent::from_operation(op);
+        let mut dropped: u64 = 0;
+        senders.retain(|sender| match sender.send(&events) {
+            Some(count) => {
+                dropped += count;
+                true
+            }
+            None => false,
+        });
+
+        if dropped > 0 {
+            stats.lock().unwrap().record_dropped_events(dropped);
+        }
+    }
+
+    // a refresh rate for the watched trees, based on how long a trial scan of them takes now
+    pub fn rescan_interval_hint(&self) -> Duration {
+        let config = &self.config;
+        let scan_started = Instant::now();
+        for dir in config.directories() {
+            let search_dir = Self::build_search_dir(
+                config,
+                &dir,
+                self.on_metadata_error.clone(),
+                Arc::clone(&self.unreadable_dirs),
+            );
+            if let Ok(search_dir) = search_dir {
+                let _ = search_dir.get_files();
+            }
+        }
+        let scan_duration = scan_started.elapsed();
+
+        (scan_duration * REFRESH_HINT_SCAN_MULTIPLE).clamp(REFRESH_HINT_MIN, REFRESH_HINT_MAX)
+    }
+
+    pub fn stats(&self) -> WatcherStats {
+        *self.stats.lock().unwrap()
+    }
+
+    // false once stopped, including when the watcher stopped on its own
+    pub fn is_running(&self) -> bool {
+        *self.is_started.lock().unwrap()
+    }
+
+    // the poll and events work of the next start() is handed to `spawner` instead of running on
+    // threads of their own, i.e. to run them on a pool of the caller. Both run until stop().
+    pub fn run_on(&mut self, spawner: SpawnFn) -> &mut Self {
+        self.spawner = Spawner::new(spawner);
+
+        self
+    }
+
+    // watches one more directory along with the others, see with_directories; a running watcher
+    // takes its files as they are on the next poll as the baseline. Adding a watched one does
+    // nothing
+    pub fn add_path(&mut self, dir: &str) -> Result<(), WatcherError> {
+        let dir = PathBuf::from(dir);
+        WatcherError::check(&dir)?;
+
+        let mut extra_dirs = self.config.extra_dirs.lock().unwrap();
+        if dir != self.config.dir_path && !extra_dirs.contains(&dir) {
+            extra_dirs.push(dir);
+        }
+
+        Ok(())
+    }
+
+    // stops watching a directory added with add_path or with_directories, from the next poll
+    // and without events for its files; false for the directory the watcher was created with
+    // and ones not watched
+    pub fn remove_path(&mut self, dir: &str) -> bool {
+        let mut extra_dirs = self.config.extra_dirs.lock().unwrap();
+        let count = extra_dirs.len();
+        extra_dirs.retain(|extra| extra != Path::new(dir));
+
+        extra_dirs.len() < count
+    }
+
+    // takes effect from the next poll, a running watcher included
+    pub fn set_notify_filters(&self, filters: NotifyFilters) {
+        *self.config.notify_filters.lock().unwrap() = filters;
+    }
+
+    // takes effect from the next poll, files brought in reach are reported as created and the
+    // ones out of reach as deleted
+    pub fn set_depth(&self, depth: Option<u8>) {
+        *self.config.dir_depth.lock().unwrap() = depth;
+    }
+
+    // the next poll takes the directory as it is for the new baseline instead of reporting what
+    // changed, e.g. after a large change the caller handled itself. Unlike a rescan it is
+    // silent; operations of the polls before the call may still be delivered
+    pub fn reset(&self) {
+        *self.reset_requested.lock().unwrap() = true;
+    }
+
+    // the events of the path are dropped until the deadline, so that writes of its own into the
+    // watched directory do not come back to the caller; a relative path is resolved against the
+    // watched directory
+    pub fn suppress_path(&self, path: &Path, until: Instant) {
+        self.suppressions
+            .add(self.config.dir_path.join(path), false, Some(until));
+    }
+
+    // the same for the path and everything under it
+    pub fn suppress_tree(&self, path: &Path, until: Instant) {
+        self.suppressions
+            .add(self.config.dir_path.join(path), true, Some(until));
+    }
+
+    // suppresses the path, and everything under it with `descendants`, until the guard is
+    // dropped and two refresh intervals more, time for a poll to see the last write
+    pub fn suppress(&self, path: &Path, descendants: bool) -> SuppressionGuard {
+        let id = self
+            .suppressions
+            .add(self.config.dir_path.join(path), descendants, None);
+        let grace = Duration::from_millis(self.config.refresh_rate_in_milliseconds) * 2;
+
+        SuppressionGuard::new(self.suppressions.clone(), id, grace)
+    }
+
+    // the directories the last scan could not list, for lack of permissions or otherwise; none of
+    // their files are watched. Set by the initial scan once the watcher started.
+    pub fn scan_warnings(&self) -> Vec<PathBuf> {
+        self.unreadable_dirs
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    // a line describing what is watched, e.g. "/data (depth 4, *.txt;*.pdf, LastWrite|Size)",
+    // as of the current depth and notify filters
+    pub fn watched_filter_summary(&self) -> String {
+        let config = &self.config;
+
+        let depth = match config.dir_depth() {
+            Some(depth) => format!("depth {}", depth),
+            None => "unlimited depth".to_string(),
+        };
+
+        let filters = config.filters();
+        let filter = match filters.is_empty() {
+            true => "all files".to_string(),
+            false => filters.join(";"),
+        };
+
+        let notify_filters: Vec<&str> = config
+            .notify_filters()
+            .iter_names()
+            .map(|(name, _)| name)
+            .collect();
+
+        format!(
+            "{} ({}, {}, {})",
+            config.dir_path.display(),
+            depth,
+            filter,
+            notify_filters.join("|")
+        )
+    }
+
+    pub fn config(&self) -> &WatcherConfig {
+        &self.config
+    }
+
+    // the search dir of one of the watched directories, see WatcherConfig::directories
+    fn build_search_dir(
+        config: &WatcherConfig,
+        dir: &Path,
+        on_metadata_error: Option<Callback<(PathBuf, std::io::Error)>>,
+        unreadable_dirs: Arc<Mutex<BTreeSet<PathBuf>>>,
+    ) -> Result<SearchDir, WatcherError> {
+        let mut search_dir = SearchDir::try_new_with_filter_separators(
+            dir.to_path_buf(),
+            config.dir_depth(),
+            config.filter.clone(),
+            config.filter_separators().unwrap_or(FILTER_SEPARATORS),
+        )?;
+
+        if let Some(path) = &config.ignore_file {
+            search_dir.with_ignore_file(path.clone());
+        }
+
+        for path in config.implicit_ignores.iter() {
+            search_dir.with_excluded_path(path.clone());
+        }
+
+        search_dir
+            .with_unreadable_dirs(unreadable_dirs)
+            .with_dedup_hardlinks(config.dedup_hardlinks)
+            .with_report_broken_symlinks(config.report_broken_symlinks)
+            .with_track_symlink_targets(config.track_symlink_targets)
+            .with_non_utf8(config.non_utf8)
+            .with_entries(config.entries)
+            .with_metadata_retry(config.metadata_retry.0, config.metadata_retry.1);
+
+        if let Some(callback) = on_metadata_error {
+            search_dir.with_on_metadata_error(callback);
+        }
+
+        #[cfg(feature = "content-type")]
+        if let Some(content_types) = config.content_type_filter() {
+            search_dir.with_content_type_filter(content_types);
+        }
+
+        Ok(search_dir)
+    }
+}
+
+// compile time check of the documented thread safety guarantees
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+
+    assert_send_sync::<FileWatcher>();
+    assert_send_sync::<FileWatcherOptions>();
+    assert_send_sync::<WatcherConfig>();
+    assert_send_sync::<OPERATION>();
+    assert_send_sync::<WatchEvent>();
+    assert_send_sync::<WatcherStats>();
+    assert_send_sync::<StopSummary>();
+    assert_send_sync::<File>();
+    assert_send_sync::<ChangedFile>();
+    assert_send_sync::<RenamedFileEntry>();
+    assert_send_sync::<OnCreatedEventArgs>();
+    assert_send_sync::<OnChangedEventArgs>();
+    assert_send_sync::<OnDeletedEventArgs>();
+    assert_send_sync::<OnRenamedEventArgs>();
+    assert_send_sync::<OnBatchEventArgs>();
+};
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use self::{events::OnChangedEventArgs, FileWatcher};
+
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rwatcher_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    fn names(files: &HashSet<File>) -> Vec<String> {
+        let mut result: Vec<String> = files.iter().map(|f| f.name().to_string()).collect();
+        result.sort();
+
+        result
+    }
+
+    // creates, changes, deletes and renames a file in a new test directory after the first poll
+    // of the watcher configured by `configure`, the last poll of stop() sees them all at once
+    fn change_every_kind_in_one_poll(
+        name: &str,
+        configure: impl FnOnce(&mut FileWatcherOptions),
+    ) -> PathBuf {
+        let dir = test_dir(name);
+        fs::write(dir.join("changed.txt"), "one").unwrap();
+        thread::sleep(Duration::from_millis(20));
+        fs::write(dir.join("deleted.txt"), "one").unwrap();
+        thread::sleep(Duration::from_millis(20));
+        fs::write(dir.join("old_name.txt"), "one").unwrap();
+
+        //a refresh rate well above the time the changes take, the poll after the first one is
+        //the last poll of stop()
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(1000);
+        configure(&mut op);
+
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start().unwrap();
+
+        //the first poll runs right after the baseline and is counted once done
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while fw.stats().scans() == 0 {
+            assert!(Instant::now() < deadline, "the watcher never polled");
+            thread::sleep(Duration::from_millis(1));
+        }
+        fs::rename(dir.join("old_name.txt"), dir.join("new_name.txt")).unwrap();
+        fs::remove_file(dir.join("deleted.txt")).unwrap();
+        fs::write(dir.join("changed.txt"), "two").unwrap();
+        fs::write(dir.join("created.txt"), "one").unwrap();
+
+        //delivered before stop() returns
+        fw.stop().unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn it_works() {
+        let folder = "D:\\Test";
+
+        let mut fw = FileWatcher::new(folder, Some(String::from("*.txt")), 250, None);
+        fw.on_changed(|ev: OnChangedEventArgs| {
+            println!("{:?}", ev.files());
+        });
+
+        let _ = match fw.start() {
+            Ok(started) => started,
+            Err(error) => panic!("Could not start the file watcher: {}", error),
+        };
+    }
+
+    #[test]
+    fn callbacks_can_capture_their_environment() {
+        let dir = test_dir("closure_callbacks");
+        fs::write(dir.join("changed.txt"), "one").unwrap();
+
+        let (tx, rx) = channel::<OnChangedEventArgs>();
+        let mut created = 0;
+        let (created_tx, created_rx) = channel::<usize>();
+
+        let mut fw = FileWatcher::new(dir.to_str().unwrap(), None, 10, None);
+        fw.on_changed(move |ev| tx.send(ev).unwrap());
+        fw.on_created(move |ev| {
+            created += ev.files().len();
+            created_tx.send(created).unwrap();
+        });
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        fs::write(dir.join("changed.txt"), "two").unwrap();
+        fs::File::create(dir.join("a.txt")).unwrap();
+
+        let changed = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(changed.files()[0].name().ends_with("changed.txt"));
+        assert_eq!(created_rx.recv_timeout(Duration::from_secs(1)), Ok(1));
+        fw.stop().unwrap();
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn collect_changes_returns_all_operations_in_window() {
+        let dir = test_dir("collect_changes");
+        let changed = dir.join("changed.txt");
+        let deleted = dir.join("deleted.txt");
+        let created = dir.join("created.txt");
+        fs::write(&changed, "one").unwrap();
+        fs::write(&deleted, "one").unwrap();
+
+        let mut fw = FileWatcher::new(dir.to_str().unwrap(), None, 10, None);
+
+        let (c, d, n) = (changed.clone(), deleted.clone(), created.clone());
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::write(&n, "one").unwrap();
+            thread::sleep(Duration::from_millis(100));
+            fs::write(&c, "two").unwrap();
+            thread::sleep(Duration::from_millis(100));
+            fs::remove_file(&d).unwrap();
+        });
+
+        let operations = fw.collect_changes(Duration::from_millis(600)).unwrap();
+        writer.join().unwrap();
+
+        assert_eq!(operations.len(), 3);
+        match (&operations[0], &operations[1], &operations[2]) {
+            (OPERATION::CREATE(c), OPERATION::CHANGE(u), OPERATION::DELETE(d)) => {
+                assert_eq!(names(c), vec![created.to_str().unwrap()]);
+                let u: HashSet<File> = u.iter().map(|f| f.file().clone()).collect();
+                assert_eq!(names(&u), vec![changed.to_str().unwrap()]);
+                assert_eq!(names(d), vec![deleted.to_str().unwrap()]);
+            }
+            other => panic!("unexpected operations: {:?}", other),
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn collect_changes_errors_on_a_directory_removed_since_new() {
+        let dir = test_dir("collect_changes_removed");
+        let mut fw = FileWatcher::new(dir.to_str().unwrap(), None, 10, None);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(
+            fw.collect_changes(Duration::from_millis(50)),
+            Err(WatcherError::NotFound(path)) if path == dir
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dedup_hardlinks_reports_one_change_per_inode() {
+        let dir = test_dir("dedup_hardlinks");
+        let original = dir.join("a.txt");
+        let link = dir.join("b.txt");
+        fs::write(&original, "one").unwrap();
+        fs::hard_link(&original, &link).unwrap();
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10).with_dedup_hardlinks(true);
+        let mut fw = FileWatcher::new_with_options(&op);
+        assert!(fw.config().dedup_hardlinks());
+
+        let o = original.clone();
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::write(&o, "two").unwrap();
+        });
+
+        let operations = fw.collect_changes(Duration::from_millis(400)).unwrap();
+        writer.join().unwrap();
+
+        let changed: Vec<&ChangedFile> = operations
+            .iter()
+            .filter_map(|op| match op {
+                OPERATION::CHANGE(files) => Some(files.iter()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].name(), original.to_str().unwrap());
+        assert_eq!(changed[0].file().hardlinks(), [link.to_str().unwrap()]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dedup_hardlinks_applies_to_incremental_scans() {
+        let dir = test_dir("dedup_hardlinks_incremental");
+        let original = dir.join("a.txt");
+        let (link, new_link) = (dir.join("b.txt"), dir.join("c.txt"));
+        fs::write(&original, "one").unwrap();
+        fs::hard_link(&original, &link).unwrap();
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_scan_budget(100)
+            .with_dedup_hardlinks(true);
+        let mut fw = FileWatcher::new_with_options(&op);
+
+        let (o, l) = (original.clone(), new_link.clone());
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::hard_link(&o, &l).unwrap();
+            thread::sleep(Duration::from_millis(100));
+            fs::write(&o, "two").unwrap();
+        });
+
+        let operations = fw.collect_changes(Duration::from_millis(400)).unwrap();
+        writer.join().unwrap();
+
+        //the new link is listed with the file instead of being created
+        let changed: Vec<&ChangedFile> = operations
+            .iter()
+            .filter_map(|op| match op {
+                OPERATION::CHANGE(files) => Some(files.iter()),
+                OPERATION::CREATE(files) => panic!("unexpected create: {:?}", files),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].name(), original.to_str().unwrap());
+        assert_eq!(
+            changed[0].file().hardlinks(),
+            [link.to_str().unwrap(), new_link.to_str().unwrap()]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    //the operations seen while the target of a symlink is deleted
+    #[cfg(unix)]
+    fn break_symlink(name: &str, report_broken_symlinks: bool) -> (PathBuf, Vec<OPERATION>) {
+        let dir = test_dir(name);
+        let target = dir.join("target.txt");
+        fs::File::create(&target).unwrap();
+        std::os::unix::fs::symlink(&target, dir.join("link.txt")).unwrap();
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_report_broken_symlinks(report_broken_symlinks);
+        let mut fw = FileWatcher::new_with_options(&op);
+        assert_eq!(fw.config().report_broken_symlinks(), report_broken_symlinks);
+
+        let remover = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::remove_file(&target).unwrap();
+        });
+        let operations = fw.collect_changes(Duration::from_millis(300)).unwrap();
+        remover.join().unwrap();
+
+        (dir, operations)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn broken_symlinks_are_skipped_or_reported() {
+        let (dir, operations) = break_symlink("broken_symlink_skipped", false);
+        let link = dir.join("link.txt");
+        let deleted: Vec<&str> = operations
+            .iter()
+            .filter_map(|op| match op {
+                OPERATION::DELETE(files) => Some(files.iter().map(File::name)),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        assert!(deleted.contains(&link.to_str().unwrap()));
+        assert!(!operations
+            .iter()
+            .any(|op| matches!(op, OPERATION::CHANGE(_))));
+        let _ = fs::remove_dir_all(&dir);
+
+        let (dir, operations) = break_symlink("broken_symlink_reported", true);
+        let link = dir.join("link.txt");
+        let changed: Vec<&ChangedFile> = operations
+            .iter()
+            .filter_map(|op| match op {
+                OPERATION::CHANGE(files) => Some(files.iter()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].name(), link.to_str().unwrap());
+        assert!(changed[0].file().is_broken_symlink());
+        assert!(!changed[0].previous().is_broken_symlink());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn repointed_symlink_is_a_change_of_its_target() {
+        let dir = test_dir("symlink_target");
+        let (first, second, link) = (dir.join("a.txt"), dir.join("b.txt"), dir.join("link.txt"));
+        fs::File::create(&first).unwrap();
+        fs::File::create(&second).unwrap();
+        std::os::unix::fs::symlink(&first, &link).unwrap();
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10).with_track_symlink_targets(true);
+        let mut fw = FileWatcher::new_with_options(&op);
+        assert!(fw.config().track_symlink_targets());
+
+        let (target, path) = (second.clone(), link.clone());
+        let repointer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::remove_file(&path).unwrap();
+            std::os::unix::fs::symlink(&target, &path).unwrap();
+        });
+        let operations = fw.collect_changes(Duration::from_millis(300)).unwrap();
+        repointer.join().unwrap();
+
+        let changed: Vec<&ChangedFile> = operations
+            .iter()
+            .filter_map(|op| match op {
+                OPERATION::CHANGE(files) => Some(files.iter()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].name(), link.to_str().unwrap());
+        assert_eq!(
+            changed[0].previous().symlink_target(),
+            Some(first.as_path())
+        );
+        assert_eq!(changed[0].file().symlink_target(), Some(second.as_path()));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn read_on_change_attaches_the_contents_at_detection() {
+        let dir = test_dir("read_on_change");
+        let small = dir.join("small.txt");
+        let large = dir.join("large.txt");
+        fs::write(&small, "one").unwrap();
+        fs::write(&large, "one").unwrap();
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10).with_read_on_change(8);
+        let mut fw = FileWatcher::new_with_options(&op);
+        assert_eq!(fw.config().read_on_change(), Some(8));
+
+        let (s, l) = (small.clone(), large.clone());
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::write(&s, "two").unwrap();
+            fs::write(&l, "longer than allowed").unwrap();
+        });
+
+        let operations = fw.collect_changes(Duration::from_millis(300)).unwrap();
+        writer.join().unwrap();
+
+        let changed: Vec<&ChangedFile> = operations
+            .iter()
+            .filter_map(|op| match op {
+                OPERATION::CHANGE(files) => Some(files.iter()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        assert_eq!(changed.len(), 2);
+        for file in changed {
+            match file.name() == small.to_str().unwrap() {
+                true => assert_eq!(file.contents(), Some(&b"two"[..])),
+                false => assert_eq!(file.contents(), None),
+            }
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn content_size_limit_skips_reading_larger_files() {
+        let dir = test_dir("content_size_limit");
+        let large = dir.join("large.txt");
+        fs::write(&large, "one").unwrap();
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_read_on_change(1024)
+            .with_content_size_limit(8);
+        let mut fw = FileWatcher::new_with_options(&op);
+        assert_eq!(fw.config().content_size_limit(), Some(8));
+
+        let l = large.clone();
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::write(&l, "longer than the limit").unwrap();
+        });
+
+        let operations = fw.collect_changes(Duration::from_millis(300)).unwrap();
+        writer.join().unwrap();
+
+        let changed: Vec<&ChangedFile> = operations
+            .iter()
+            .filter_map(|op| match op {
+                OPERATION::CHANGE(files) => Some(files.iter()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].contents(), None);
+        assert!(changed[0].content_skipped());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    // creates a file every poll while the first created callback is still running, then stops
+    fn stop_while_delivering(
+        name: &str,
+        discard_on_stop: bool,
+        on_created: fn(OnCreatedEventArgs),
+    ) -> StopSummary {
+        let dir = test_dir(name);
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        //only creates are queued, a write racing a poll cannot add a change
+        op.with_refresh_rate(10)
+            .with_operations(OperationMask::CREATE)
+            .with_discard_on_stop(discard_on_stop)
+            .with_on_created(on_created);
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        for i in 0..3 {
+            fs::File::create(dir.join(format!("{}.txt", i))).unwrap();
+            thread::sleep(Duration::from_millis(60));
+        }
+        let summary = fw.stop().unwrap();
+
+        let _ = fs::remove_dir_all(&dir);
+
+        summary
+    }
+
+    #[test]
+    fn stop_delivers_the_changes_made_before_it_was_called() {
+        static CREATED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        let dir = test_dir("stop_last_poll");
+
+        //the files are created while the poll thread sleeps
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(300).with_on_created(|ev| {
+            let mut created = CREATED.lock().unwrap();
+            created.extend(ev.files().iter().map(|f| f.name().to_string()));
+        });
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        let paths: Vec<String> = (0..3)
+            .map(|i| dir.join(format!("{}.txt", i)).to_str().unwrap().to_string())
+            .collect();
+        for path in paths.iter() {
+            fs::File::create(path).unwrap();
+        }
+        fw.stop().unwrap();
+
+        let mut created = CREATED.lock().unwrap().clone();
+        created.sort();
+        assert_eq!(created, paths);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stop_deletes_every_tracked_file_when_asked_to() {
+        static DELETED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        let dir = test_dir("emit_deletes_on_stop");
+        fs::File::create(dir.join("a.txt")).unwrap();
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_emit_deletes_on_stop(true)
+            .with_on_deleted(|ev| {
+                let mut deleted = DELETED.lock().unwrap();
+                deleted.extend(ev.files().iter().map(|f| f.name().to_string()));
+            });
+        let mut fw = FileWatcher::new_with_options(&op);
+        assert!(fw.config().emit_deletes_on_stop());
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        fs::File::create(dir.join("b.txt")).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        fw.stop().unwrap();
+
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let mut deleted = DELETED.lock().unwrap().clone();
+        deleted.sort();
+        assert_eq!(deleted, vec![path("a.txt"), path("b.txt")]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stop_delivers_and_counts_the_queued_operations() {
+        static CREATED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        let summary = stop_while_delivering("stop_delivers", false, |ev| {
+            thread::sleep(Duration::from_millis(300));
+            let mut created = CREATED.lock().unwrap();
+            created.extend(ev.files().iter().map(|f| f.name().to_string()));
+        });
+
+        assert!(summary.stopped());
+        assert!(!summary.discarded());
+        assert_eq!(summary.flushed(), 2);
+        assert_eq!(CREATED.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn stop_discards_the_queued_operations_when_asked_to() {
+        static CREATED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        let summary = stop_while_delivering("stop_discards", true, |ev| {
+            thread::sleep(Duration::from_millis(300));
+            let mut created = CREATED.lock().unwrap();
+            created.extend(ev.files().iter().map(|f| f.name().to_string()));
+        });
+
+        assert!(summary.stopped());
+        assert!(summary.discarded());
+        assert_eq!(summary.flushed(), 2);
+        assert_eq!(CREATED.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn operations_compare_by_kind_and_file_names() {
+        let files = |names: &[&str]| -> HashSet<File> {
+            names
+                .iter()
+                .map(|name| File::from_manifest(Path::new(name), SystemTime::now(), 0))
+                .collect()
+        };
+
+        assert_eq!(
+            OPERATION::CREATE(files(&["a.txt", "b.txt"])),
+            OPERATION::CREATE(files(&["b.txt", "a.txt"]))
+        );
+        assert_ne!(
+            OPERATION::CREATE(files(&["a.txt"])),
+            OPERATION::CREATE(files(&["b.txt"]))
+        );
+        assert_ne!(
+            OPERATION::CREATE(files(&["a.txt"])),
+            OPERATION::DELETE(files(&["a.txt"]))
+        );
+    }
+
+    #[test]
+    fn max_event_rate_holds_back_and_merges_a_burst() {
+        static DELIVERED: Mutex<Vec<(Instant, usize)>> = Mutex::new(Vec::new());
+
+        let dir = test_dir("max_event_rate");
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_max_event_rate(2)
+            .with_on_created(|ev| {
+                DELIVERED
+                    .lock()
+                    .unwrap()
+                    .push((Instant::now(), ev.files().len()));
+            });
+        let mut fw = FileWatcher::new_with_options(&op);
+        assert_eq!(fw.config().max_event_rate(), Some(2));
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        //one created operation per poll
+        let started = Instant::now();
+        for i in 0..20 {
+            fs::File::create(dir.join(format!("{}.txt", i))).unwrap();
+            thread::sleep(Duration::from_millis(25));
+        }
+        thread::sleep(Duration::from_millis(1200));
+        fw.stop().unwrap();
+
+        let delivered = DELIVERED.lock().unwrap();
+        let last = delivered.last().unwrap().0.duration_since(started);
+        let allowed = 2 + (2.0 * last.as_secs_f64()).ceil() as usize;
+        assert!(
+            delivered.len() <= allowed,
+            "{} in {:?}",
+            delivered.len(),
+            last
+        );
+        assert_eq!(delivered.iter().map(|(_, files)| files).sum::<usize>(), 20);
+        assert!(fw.stats().coalesced_events() > 0);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn tick_delivers_one_batch_at_each_boundary() {
+        static DELIVERED: Mutex<Vec<(SystemTime, Vec<String>)>> = Mutex::new(Vec::new());
+
+        let dir = test_dir("tick");
+        let tick = Duration::from_millis(500);
+        let since_tick = || {
+            let since_epoch = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH);
+            Duration::from_nanos((since_epoch.unwrap().as_nanos() % tick.as_nanos()) as u64)
+        };
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_tick(tick)
+            .with_combined_events(true)
+            .with_on_batch(|ev| {
+                let mut names: Vec<String> =
+                    ev.created().iter().map(|f| f.name().to_string()).collect();
+                names.sort();
+                DELIVERED.lock().unwrap().push((SystemTime::now(), names));
+            });
+        let mut fw = FileWatcher::new_with_options(&op);
+        assert_eq!(fw.config().tick(), Some(tick));
+        fw.start().unwrap();
+
+        //two files early in a tick, across several polls, and one in the next tick
+        thread::sleep(tick - since_tick() + Duration::from_millis(50));
+        fs::File::create(dir.join("a.txt")).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        fs::File::create(dir.join("b.txt")).unwrap();
+        thread::sleep(tick);
+        fs::File::create(dir.join("c.txt")).unwrap();
+        thread::sleep(tick + Duration::from_millis(200));
+        fw.stop().unwrap();
+
+        let delivered = DELIVERED.lock().unwrap();
+        let names = |files: &[&str]| -> Vec<String> {
+            files
+                .iter()
+                .map(|f| dir.join(f).to_string_lossy().to_string())
+                .collect()
+        };
+        assert_eq!(delivered.len(), 2, "{:?}", delivered);
+        assert_eq!(delivered[0].1, names(&["a.txt", "b.txt"]));
+        assert_eq!(delivered[1].1, names(&["c.txt"]));
+        for (at, _) in delivered.iter() {
+            let since_epoch = at.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+            assert!(
+                since_epoch.as_nanos() % tick.as_nanos() < 100_000_000,
+                "{:?}",
+                at
+            );
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn tick_without_combined_events_calls_the_created_callback() {
+        static CREATED: Mutex<Vec<Vec<String>>> = Mutex::new(Vec::new());
+
+        let dir = test_dir("tick_created");
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_operations(OperationMask::CREATE)
+            .with_tick(Duration::from_millis(200))
+            .with_on_created(|ev| {
+                let mut names: Vec<String> =
+                    ev.files().iter().map(|f| f.name().to_string()).collect();
+                names.sort();
+                CREATED.lock().unwrap().push(names);
+            });
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        fs::File::create(dir.join("a.txt")).unwrap();
+        fs::File::create(dir.join("b.txt")).unwrap();
+        thread::sleep(Duration::from_millis(500));
+        fw.stop().unwrap();
+
+        let created: Vec<String> = CREATED.lock().unwrap().concat();
+        let names: Vec<String> = ["a.txt", "b.txt"]
+            .iter()
+            .map(|f| dir.join(f).to_string_lossy().to_string())
+            .collect();
+        assert_eq!(created, names);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn moved_directory_is_reported_as_one_rename() {
+        let dir = test_dir("directory_move");
+        let (old_dir, new_dir) = (dir.join("drafts"), dir.join("published"));
+        fs::create_dir_all(old_dir.join("nested")).unwrap();
+        for name in ["a.txt", "b.txt", "nested/c.txt"] {
+            fs::write(old_dir.join(name), "one").unwrap();
+        }
+
+        let mut fw = FileWatcher::new(dir.to_str().unwrap(), None, 10, None);
+
+        let (from, to) = (old_dir.clone(), new_dir.clone());
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::rename(&from, &to).unwrap();
+        });
+
+        let operations = fw.collect_changes(Duration::from_millis(300)).unwrap();
+        writer.join().unwrap();
+
+        assert_eq!(
+            operations,
+            vec![OPERATION::RENAME(HashSet::from([RenamedFileEntry::new(
+                new_dir.to_str().unwrap(),
+                old_dir.to_str().unwrap()
+            )]))]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dirs_rename_scope_reports_only_the_moved_directory() {
+        let dir = test_dir("rename_scope_dirs");
+        let (old_dir, new_dir) = (dir.join("drafts"), dir.join("published"));
+        fs::create_dir_all(&old_dir).unwrap();
+        fs::write(old_dir.join("a.txt"), "one").unwrap();
+        fs::write(dir.join("old.txt"), "two").unwrap();
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_rename_scope(RenameScope::Dirs);
+        let mut fw = FileWatcher::new_with_options(&op);
+        assert_eq!(fw.config().rename_scope(), RenameScope::Dirs);
+
+        let (from, to, root) = (old_dir.clone(), new_dir.clone(), dir.clone());
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::rename(&from, &to).unwrap();
+            fs::rename(root.join("old.txt"), root.join("new.txt")).unwrap();
+        });
+
+        let operations = fw.collect_changes(Duration::from_millis(300)).unwrap();
+        writer.join().unwrap();
+
+        assert_eq!(
+            operations,
+            vec![OPERATION::RENAME(HashSet::from([RenamedFileEntry::new(
+                new_dir.to_str().unwrap(),
+                old_dir.to_str().unwrap()
+            )]))]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_directories_are_reported_as_scan_warnings() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = test_dir("scan_warnings");
+        let locked = dir.join("locked");
+        fs::create_dir_all(&locked).unwrap();
+        fs::write(locked.join("secret.txt"), "one").unwrap();
+        fs::write(dir.join("open.txt"), "one").unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+
+        //permissions do not hold for root
+        if fs::read_dir(&locked).is_ok() {
+            fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+            let _ = fs::remove_dir_all(&dir);
+            return;
+        }
+
+        //an empty manifest reports every file the first scan finds as created
+        let mut fw = FileWatcher::new(dir.to_str().unwrap(), None, 10, None);
+        let events = fw.events_rx();
+        fw.start_from_manifest(vec![]).unwrap();
+
+        let mut created: Vec<String> = vec![];
+        while let Ok(WatchEvent::Created(file)) = events.recv_timeout(Duration::from_millis(300)) {
+            created.push(file.name().to_string());
+        }
+        let warnings = fw.scan_warnings();
+        fw.stop().unwrap();
+
+        assert_eq!(created, vec![dir.join("open.txt").to_str().unwrap()]);
+        assert_eq!(warnings, vec![locked.clone()]);
+
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rescan_interval_hint_grows_with_the_tree() {
+        let tiny = test_dir("hint_tiny");
+        fs::write(tiny.join("a.txt"), "one").unwrap();
+
+        let large = test_dir("hint_large");
+        for d in 0..20 {
+            let sub = large.join(format!("dir{}", d));
+            fs::create_dir_all(&sub).unwrap();
+            for f in 0..500 {
+                fs::File::create(sub.join(format!("file{}.txt", f))).unwrap();
+            }
+        }
+
+        let tiny_hint =
+            FileWatcher::new(tiny.to_str().unwrap(), None, 250, None).rescan_interval_hint();
+        let large_hint =
+            FileWatcher::new(large.to_str().unwrap(), None, 250, None).rescan_interval_hint();
+
+        assert!(tiny_hint < Duration::from_millis(100), "{:?}", tiny_hint);
+        assert!(
+            large_hint > tiny_hint,
+            "{:?} <= {:?}",
+            large_hint,
+            tiny_hint
+        );
+
+        let _ = fs::remove_dir_all(&tiny);
+        let _ = fs::remove_dir_all(&large);
+    }
+
+    //whether moving the access time of a file forward, as a read does, is reported as a change
+    fn is_access_reported(name: &str, ignore_atime_only: bool) -> bool {
+        let dir = test_dir(name);
+        let file = dir.join("a.txt");
+        fs::write(&file, "one").unwrap();
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_notify_filters(NotifyFilters::LastAccess | NotifyFilters::LastWrite)
+            .with_ignore_atime_only(ignore_atime_only);
+        let mut fw = FileWatcher::new_with_options(&op);
+        assert_eq!(fw.config().ignore_atime_only(), ignore_atime_only);
+
+        let path = file.clone();
+        let reader = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            let accessed = SystemTime::now() + Duration::from_secs(60);
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_times(fs::FileTimes::new().set_accessed(accessed))
+                .unwrap();
+        });
+        let operations = fw.collect_changes(Duration::from_millis(300)).unwrap();
+        reader.join().unwrap();
+
+        let _ = fs::remove_dir_all(&dir);
+
+        operations
+            .iter()
+            .any(|op| matches!(op, OPERATION::CHANGE(_)))
+    }
+
+    #[test]
+    fn access_only_changes_are_ignored_by_default() {
+        assert!(FileWatcherOptions::new(".").ignore_atime_only);
+        assert!(!is_access_reported("atime_ignored", true));
+        assert!(is_access_reported("atime_reported", false));
+    }
+
+    #[test]
+    fn notify_filters_presets_contain_expected_flags() {
+        let flags = [
+            NotifyFilters::Attributes,
+            NotifyFilters::CreationTime,
+            NotifyFilters::DirectoryName,
+            NotifyFilters::FileName,
+            NotifyFilters::LastAccess,
+            NotifyFilters::LastWrite,
+            NotifyFilters::Security,
+            NotifyFilters::Size,
+        ];
+        for (i, flag) in flags.iter().enumerate() {
+            assert_eq!(flag.bits().count_ones(), 1);
+            assert!(flags[i + 1..].iter().all(|other| !flag.intersects(*other)));
+            assert!(NotifyFilters::ALL.contains(*flag));
+        }
+
+        assert_eq!(
+            NotifyFilters::DEFAULT,
+            NotifyFilters::LastWrite | NotifyFilters::Size
+        );
+        assert_eq!(NotifyFilters::CONTENT, NotifyFilters::DEFAULT);
+        assert_eq!(
+            NotifyFilters::TIMESTAMPS,
+            NotifyFilters::CreationTime | NotifyFilters::LastWrite | NotifyFilters::LastAccess
+        );
+        assert!(!NotifyFilters::TIMESTAMPS.contains(NotifyFilters::Size));
+
+        //every constructor starts from the same default
+        let dir = test_dir("default_notify_filters");
+        let options = FileWatcherOptions::new(dir.to_str().unwrap());
+        assert_eq!(options.notify_filters, NotifyFilters::DEFAULT);
+        let fw = FileWatcher::new(dir.to_str().unwrap(), None, 250, None);
+        assert_eq!(fw.config().notify_filters(), NotifyFilters::DEFAULT);
+        let (fw, _events) = FileWatcher::watch(dir.to_str().unwrap(), None).unwrap();
+        assert_eq!(fw.config().notify_filters(), NotifyFilters::DEFAULT);
+        drop(fw);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn notify_filters_parse_from_flag_and_preset_names() {
+        assert_eq!(
+            "LastWrite,Size,CreationTime"
+                .parse::<NotifyFilters>()
+                .unwrap(),
+            NotifyFilters::LastWrite | NotifyFilters::Size | NotifyFilters::CreationTime
+        );
+        assert_eq!(
+            " lastwrite , SIZE ".parse::<NotifyFilters>().unwrap(),
+            NotifyFilters::DEFAULT
+        );
+        assert_eq!("all".parse::<NotifyFilters>().unwrap(), NotifyFilters::ALL);
+        assert_eq!(
+            "Timestamps,Size".parse::<NotifyFilters>().unwrap(),
+            NotifyFilters::TIMESTAMPS | NotifyFilters::Size
+        );
+
+        let error = "LastWrite,Modified".parse::<NotifyFilters>().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("'Modified'"));
+    }
+
+    #[test]
+    fn stop_is_not_delayed_by_scanning_a_large_tree() {
+        let dir = test_dir("scan_budget");
+        for d in 0..20 {
+            let sub = dir.join(format!("dir{}", d));
+            fs::create_dir_all(&sub).unwrap();
+            for f in 0..200 {
+                fs::write(sub.join(format!("file{}.txt", f)), "").unwrap();
+            }
+        }
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(5).with_scan_budget(100);
+
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(30));
+
+        let stop_started = Instant::now();
+        assert!(fw.stop().unwrap().stopped());
+        assert!(stop_started.elapsed() < Duration::from_millis(500));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn scan_budget_reports_a_rename_as_create_and_delete() {
+        let dir = test_dir("scan_budget_rename");
+        let (old_name, new_name) = (dir.join("old.txt"), dir.join("new.txt"));
+        fs::write(&old_name, "one").unwrap();
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10).with_scan_budget(100);
+        let mut fw = FileWatcher::new_with_options(&op);
+
+        let (from, to) = (old_name.clone(), new_name.clone());
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::rename(&from, &to).unwrap();
+        });
+
+        let operations = fw.collect_changes(Duration::from_millis(300)).unwrap();
+        writer.join().unwrap();
+
+        let file = |path: &Path| File::from_manifest(path, SystemTime::now(), 3);
+        assert_eq!(
+            operations,
+            vec![
+                OPERATION::CREATE(HashSet::from([file(&new_name)])),
+                OPERATION::DELETE(HashSet::from([file(&old_name)])),
+            ]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn callbacks_fire_in_documented_order_within_a_poll() {
+        static EVENTS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+        let dir = change_every_kind_in_one_poll("event_order", |op| {
+            op.with_on_created(|_| EVENTS.lock().unwrap().push("created"))
+                .with_on_changed(|_| EVENTS.lock().unwrap().push("changed"))
+                .with_on_deleted(|_| EVENTS.lock().unwrap().push("deleted"))
+                .with_on_renamed(|_| EVENTS.lock().unwrap().push("renamed"));
+        });
+
+        assert_eq!(
+            *EVENTS.lock().unwrap(),
+            vec!["created", "changed", "deleted", "renamed"]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn sink_receives_every_operation() {
+        struct RecordingSink(Arc<Mutex<Vec<String>>>);
+
+        impl WatchSink for RecordingSink {
+            fn on_created(&mut self, files: &[File]) {
+                for f in files {
+                    self.0
+                        .lock()
+                        .unwrap()
+                        .push(format!("created {}", f.file_name().unwrap()));
+                }
+            }
+
+            fn on_changed(&mut self, files: &[ChangedFile]) {
+                for f in files {
+                    let name = f.file().file_name().unwrap();
+                    self.0.lock().unwrap().push(format!("changed {}", name));
+                }
+            }
+
+            fn on_deleted(&mut self, files: &[File]) {
+                for f in files {
+                    self.0
+                        .lock()
+                        .unwrap()
+                        .push(format!("deleted {}", f.file_name().unwrap()));
+                }
+            }
+
+            fn on_renamed(&mut self, files: &[RenamedFileEntry]) {
+                for f in files {
+                    let name = |path: &str| {
+                        Path::new(path)
+                            .file_name()
+                            .unwrap()
+                            .to_str()
+                            .unwrap()
+                            .to_string()
+                    };
+                    let entry = format!("renamed {} {}", name(f.old_name()), name(f.name()));
+                    self.0.lock().unwrap().push(entry);
+                }
+            }
+        }
+
+        let received = Arc::new(Mutex::new(vec![]));
+        let sink = RecordingSink(Arc::clone(&received));
+        let dir = change_every_kind_in_one_poll("sink", |op| {
+            op.with_sink(Box::new(sink));
+        });
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![
+                "created created.txt",
+                "changed changed.txt",
+                "deleted deleted.txt",
+                "renamed old_name.txt new_name.txt"
+            ]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn run_on_executes_the_watcher_on_the_given_threads() {
+        static CREATED_ON: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        let dir = test_dir("run_on");
+        let spawned = Arc::new(Mutex::new(0));
+        let spawned_clone = Arc::clone(&spawned);
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10).with_on_created(|_| {
+            let name = thread::current().name().unwrap_or_default().to_string();
+            CREATED_ON.lock().unwrap().push(name);
+        });
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.run_on(Box::new(move |work| {
+            *spawned_clone.lock().unwrap() += 1;
+            thread::Builder::new()
+                .name("caller".to_string())
+                .spawn(work)
+                .unwrap();
+        }));
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        fs::File::create(dir.join("a.txt")).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert!(fw.stop().unwrap().stopped());
+
+        assert_eq!(*spawned.lock().unwrap(), 2);
+        assert_eq!(*CREATED_ON.lock().unwrap(), vec!["caller"]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn emptiness_callbacks_fire_when_the_count_crosses_zero() {
+        static LOG: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+        let dir = test_dir("emptiness");
+        let (first, second) = (dir.join("first.txt"), dir.join("second.txt"));
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_on_empty(|| LOG.lock().unwrap().push("empty"))
+            .with_on_non_empty(|| LOG.lock().unwrap().push("non-empty"));
+
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        fs::File::create(&first).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        fs::File::create(&second).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        fs::remove_file(&first).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(*LOG.lock().unwrap(), vec!["non-empty"]);
+
+        fs::remove_file(&second).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        fw.stop().unwrap();
+
+        assert_eq!(*LOG.lock().unwrap(), vec!["non-empty", "empty"]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn watched_filter_summary_describes_the_resolved_config() {
+        let dir = test_dir("filter_summary");
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_filter("*.txt,*.pdf")
+            .with_directory_depth(4)
+            .with_notify_filters(NotifyFilters::LastWrite | NotifyFilters::CreationTime);
+        let fw = FileWatcher::new_with_options(&op);
+        assert_eq!(
+            fw.watched_filter_summary(),
+            format!(
+                "{} (depth 4, *.txt;*.pdf, CreationTime|LastWrite)",
+                dir.display()
+            )
+        );
+
+        fw.set_depth(None);
+        fw.set_notify_filters(NotifyFilters::Size);
+        assert!(fw
+            .watched_filter_summary()
+            .ends_with("(unlimited depth, *.txt;*.pdf, Size)"));
+
+        let fw = FileWatcher::new(dir.to_str().unwrap(), Some("*.*".to_string()), 10, None);
+        assert!(fw
+            .watched_filter_summary()
+            .ends_with("(unlimited depth, all files, LastWrite|Size)"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn files_only_reports_the_files_of_a_created_directory_but_not_the_directory() {
+        let created = |entries: Option<Entries>, filters: NotifyFilters, name: &str| {
+            let dir = test_dir(name);
+
+            let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+            op.with_refresh_rate(10).with_notify_filters(filters);
+            if let Some(entries) = entries {
+                op.with_entries(entries);
+            }
+            let mut fw = FileWatcher::new_with_options(&op);
+            let selected = entries.unwrap_or(Entries::for_notify_filters(filters));
+            assert_eq!(fw.config().entries(), selected);
+            let events = fw.events_rx();
+            fw.start().unwrap();
+            thread::sleep(Duration::from_millis(50));
+
+            fs::create_dir_all(dir.join("sub").join("nested")).unwrap();
+            fs::File::create(dir.join("sub").join("nested").join("a.txt")).unwrap();
+            thread::sleep(Duration::from_millis(100));
+            fw.stop().unwrap();
+
+            let mut names: Vec<String> = events
+                .try_iter()
+                .filter_map(|event| match event {
+                    WatchEvent::Created(f) => Some(f.name().to_string()),
+                    _ => None,
+                })
+                .map(|name| {
+                    let relative = Path::new(&name).strip_prefix(&dir).unwrap();
+                    relative.to_string_lossy().replace('\\', "/")
+                })
+                .collect();
+            names.sort();
+
+            let _ = fs::remove_dir_all(&dir);
+            names
+        };
+
+        let (files, dirs) = (NotifyFilters::LastWrite, NotifyFilters::DirectoryName);
+        assert_eq!(
+            created(Some(Entries::FilesOnly), files, "entries_files"),
+            vec!["sub/nested/a.txt"]
+        );
+        assert_eq!(
+            created(Some(Entries::DirsOnly), files, "entries_dirs"),
+            vec!["sub", "sub/nested"]
+        );
+        assert_eq!(
+            created(Some(Entries::Both), files, "entries_both"),
+            vec!["sub", "sub/nested", "sub/nested/a.txt"]
+        );
+        //DirectoryName reports the directories unless only files are selected
+        assert_eq!(
+            created(None, files, "entries_default"),
+            vec!["sub/nested/a.txt"]
+        );
+        assert_eq!(
+            created(None, files | dirs, "entries_directory_name"),
+            vec!["sub", "sub/nested", "sub/nested/a.txt"]
+        );
+        assert_eq!(
+            created(
+                Some(Entries::FilesOnly),
+                files | dirs,
+                "entries_files_over_name"
+            ),
+            vec!["sub/nested/a.txt"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn empty_directories_are_created_renamed_and_deleted() {
+        let dir = test_dir("entries_empty_dirs");
+        let (old_dir, new_dir) = (dir.join("drafts"), dir.join("published"));
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10).with_entries(Entries::DirsOnly);
+        let mut fw = FileWatcher::new_with_options(&op);
+        let mut changes = |change: Box<dyn FnOnce() + Send>| {
+            let writer = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                change();
+            });
+            let operations = fw.collect_changes(Duration::from_millis(300)).unwrap();
+            writer.join().unwrap();
+            operations
+        };
+
+        let created = old_dir.clone();
+        let operations = changes(Box::new(move || fs::create_dir(&created).unwrap()));
+        match operations.as_slice() {
+            [OPERATION::CREATE(files)] => {
+                let file = files.iter().next().unwrap();
+                assert_eq!(file.name(), old_dir.to_str().unwrap());
+                assert!(file.is_dir());
+            }
+            other => panic!("expected the directory to be created, got {:?}", other),
+        }
+
+        let (from, to) = (old_dir.clone(), new_dir.clone());
+        assert_eq!(
+            changes(Box::new(move || fs::rename(&from, &to).unwrap())),
+            vec![OPERATION::RENAME(HashSet::from([RenamedFileEntry::new(
+                new_dir.to_str().unwrap(),
+                old_dir.to_str().unwrap()
+            )]))]
+        );
+
+        let deleted = new_dir.clone();
+        let operations = changes(Box::new(move || fs::remove_dir(&deleted).unwrap()));
+        match operations.as_slice() {
+            [OPERATION::DELETE(files)] => {
+                let file = files.iter().next().unwrap();
+                assert_eq!(file.name(), new_dir.to_str().unwrap());
+                assert!(file.is_dir());
+            }
+            other => panic!("expected the directory to be deleted, got {:?}", other),
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn renamed_directory_with_entries_is_one_rename() {
+        let dir = test_dir("entries_renamed_dir");
+        let (old_dir, new_dir) = (dir.join("drafts"), dir.join("published"));
+        fs::create_dir_all(old_dir.join("nested")).unwrap();
+        fs::write(old_dir.join("a.txt"), "one").unwrap();
+        fs::write(old_dir.join("nested").join("b.txt"), "two").unwrap();
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_notify_filters(NotifyFilters::LastWrite | NotifyFilters::DirectoryName);
+        let mut fw = FileWatcher::new_with_options(&op);
+
+        let (from, to) = (old_dir.clone(), new_dir.clone());
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::rename(&from, &to).unwrap();
+        });
+
+        let operations = fw.collect_changes(Duration::from_millis(300)).unwrap();
+        writer.join().unwrap();
+
+        //neither the nested directory nor the files are reported on their own
+        assert_eq!(
+            operations,
+            vec![OPERATION::RENAME(HashSet::from([RenamedFileEntry::new(
+                new_dir.to_str().unwrap(),
+                old_dir.to_str().unwrap()
+            )]))]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn require_filter_refuses_to_watch_every_file() {
+        let dir = test_dir("require_filter");
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_require_filter(true);
+        assert_eq!(
+            op.build().unwrap_err(),
+            WatcherError::MissingFilter(dir.clone())
+        );
+
+        op.with_filter("*.txt;*.*");
+        let error = op.build().unwrap_err();
+        assert_eq!(error, WatcherError::MissingFilter(dir.clone()));
+        assert_eq!(
+            std::io::Error::from(error).kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+
+        op.with_filter("*.txt");
+        let mut fw = FileWatcher::new_with_options(&op);
+        assert!(fw.start().unwrap());
+        fw.stop().unwrap();
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn poll_thread_stops_once_the_events_thread_is_gone() {
+        let dir = test_dir("events_thread_gone");
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_on_created(|_| panic!("the events thread exits"));
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        fs::File::create(dir.join("a.txt")).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert!(fw.is_running());
+
+        //the first operation nothing receives stops the poll thread
+        fs::File::create(dir.join("b.txt")).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert!(!fw.is_running());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn event_sequence_numbers_strictly_increase() {
+        static SEQS: Mutex<Vec<(&'static str, u64)>> = Mutex::new(Vec::new());
+
+        let dir = test_dir("event_seq");
+        let changed = dir.join("changed.txt");
+        fs::write(&changed, "one").unwrap();
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_on_created(|ev| SEQS.lock().unwrap().push(("created", ev.seq())))
+            .with_on_changed(|ev| SEQS.lock().unwrap().push(("changed", ev.seq())))
+            .with_on_deleted(|ev| SEQS.lock().unwrap().push(("deleted", ev.seq())));
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        for i in 0..3 {
+            fs::File::create(dir.join(format!("{}.txt", i))).unwrap();
+            thread::sleep(Duration::from_millis(50));
+        }
+        fs::write(&changed, "two").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        fs::remove_file(&changed).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        fw.stop().unwrap();
+
+        let seqs = SEQS.lock().unwrap();
+        let kinds: Vec<&str> = seqs.iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(
+            kinds,
+            vec!["created", "created", "created", "changed", "deleted"]
+        );
+        let numbers: Vec<u64> = seqs.iter().map(|(_, seq)| *seq).collect();
+        assert_eq!(numbers, vec![1, 2, 3, 4, 5]);
+        assert_eq!(OnCreatedEventArgs::new(HashSet::new()).seq(), 0);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reset_takes_a_new_baseline_without_reporting_it() {
+        let dir = test_dir("reset");
+        let (changed, deleted) = (dir.join("changed.txt"), dir.join("deleted.txt"));
+        fs::write(&changed, "one").unwrap();
+        fs::write(&deleted, "one").unwrap();
+
+        let mut fw = FileWatcher::new(dir.to_str().unwrap(), None, 300, None);
+        let events = fw.events_rx();
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        //changed between two polls and reset before the next one
+        fs::File::create(dir.join("created.txt")).unwrap();
+        fs::write(&changed, "two").unwrap();
+        fs::remove_file(&deleted).unwrap();
+        fw.reset();
+        thread::sleep(Duration::from_millis(400));
+        assert!(events.try_recv().is_err());
+
+        fs::File::create(dir.join("after.txt")).unwrap();
+        let event = events.recv_timeout(Duration::from_millis(700)).unwrap();
+        fw.stop().unwrap();
+
+        assert!(
+            matches!(&event, WatchEvent::Created(f) if f.name().ends_with("after.txt")),
+            "{:?}",
+            event
+        );
+        assert!(events.try_recv().is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn lifecycle_callbacks_surround_the_events() {
+        static LOG: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+        let dir = test_dir("lifecycle");
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_on_started(|| LOG.lock().unwrap().push("started"))
+            .with_on_stopped(|| LOG.lock().unwrap().push("stopped"))
+            .with_on_created(|_| LOG.lock().unwrap().push("created"));
+
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        fs::File::create(dir.join("a.txt")).unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        fw.stop().unwrap();
+        LOG.lock().unwrap().push("stop returned");
+
+        assert_eq!(
+            *LOG.lock().unwrap(),
+            vec!["started", "created", "stopped", "stop returned"]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn snapshot_holds_the_existing_files_and_events_only_the_new_ones() {
+        static CREATED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        let dir = test_dir("snapshot");
+        fs::File::create(dir.join("a.txt")).unwrap();
+        fs::File::create(dir.join("b.txt")).unwrap();
+
+        let snapshots = Arc::new(Mutex::new(vec![]));
+        let snapshots_clone = Arc::clone(&snapshots);
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_on_snapshot(move |files| {
+                let mut names: Vec<String> = files.iter().map(|f| f.name().to_string()).collect();
+                names.sort();
+                snapshots_clone.lock().unwrap().push(names);
+            })
+            .with_on_created(|ev| {
+                let mut created = CREATED.lock().unwrap();
+                created.extend(ev.files().iter().map(|f| f.name().to_string()));
+            });
+
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        fs::File::create(dir.join("c.txt")).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        fw.stop().unwrap();
+
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        assert_eq!(
+            *snapshots.lock().unwrap(),
+            vec![vec![path("a.txt"), path("b.txt")]]
+        );
+        assert_eq!(*CREATED.lock().unwrap(), vec![path("c.txt")]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    //whether a file created in a nested directory is reported once the directory's modification
+    //time is set back, as an archive tool restoring it would
+    fn is_nested_creation_reported(name: &str, full_rescan_every: Option<u32>) -> bool {
+        let dir = test_dir(name);
+        let nested = dir.join("nested");
+        fs::create_dir_all(&nested).unwrap();
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10);
+        if let Some(polls) = full_rescan_every {
+            op.with_full_rescan_every(polls);
+        }
+        let mut fw = FileWatcher::new_with_options(&op);
+        assert_eq!(fw.config().full_rescan_every(), full_rescan_every);
+
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            let modified = fs::metadata(&nested).unwrap().modified().unwrap();
+            fs::write(nested.join("a.txt"), "one").unwrap();
+            fs::File::open(&nested)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        });
+        let operations = fw.collect_changes(Duration::from_millis(300)).unwrap();
+        writer.join().unwrap();
+
+        let _ = fs::remove_dir_all(&dir);
+
+        operations
+            .iter()
+            .any(|op| matches!(op, OPERATION::CREATE(_)))
+    }
+
+    #[test]
+    fn full_rescan_surfaces_changes_the_directory_check_misses() {
+        assert!(!is_nested_creation_reported("no_full_rescan", None));
+        assert!(is_nested_creation_reported("full_rescan", Some(3)));
+    }
+
+    #[test]
+    fn operation_mask_leaves_out_changes_and_renames() {
+        let dir = test_dir("operation_mask");
+        for name in ["changed.txt", "moved.txt", "deleted.txt"] {
+            fs::write(dir.join(name), "one").unwrap();
+        }
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_operations(OperationMask::CREATE | OperationMask::DELETE);
+        let mut fw = FileWatcher::new_with_options(&op);
+        assert_eq!(
+            fw.config().operations(),
+            OperationMask::CREATE | OperationMask::DELETE
+        );
+
+        let root = dir.clone();
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::write(root.join("changed.txt"), "one two").unwrap();
+            fs::rename(root.join("moved.txt"), root.join("moved_to.txt")).unwrap();
+            fs::remove_file(root.join("deleted.txt")).unwrap();
+            fs::File::create(root.join("created.txt")).unwrap();
+        });
+        let operations = fw.collect_changes(Duration::from_millis(300)).unwrap();
+        writer.join().unwrap();
+
+        let (mut created, mut deleted) = (vec![], vec![]);
+        for operation in operations {
+            match operation {
+                OPERATION::CREATE(files) => {
+                    created.extend(files.iter().map(|f| f.name().to_string()))
+                }
+                OPERATION::DELETE(files) => {
+                    deleted.extend(files.iter().map(|f| f.name().to_string()))
+                }
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        created.sort();
+        deleted.sort();
+
+        //the move was not paired into a rename
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        assert_eq!(created, vec![path("created.txt"), path("moved_to.txt")]);
+        assert_eq!(deleted, vec![path("deleted.txt"), path("moved.txt")]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn every_poll_is_recorded_as_a_span() {
+        //records the name of every span created
+        struct SpanNames(Arc<Mutex<Vec<String>>>);
+
+        impl tracing::Subscriber for SpanNames {
+            fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+                let mut names = self.0.lock().unwrap();
+                names.push(span.metadata().name().to_string());
+
+                tracing::span::Id::from_u64(names.len() as u64)
+            }
+
+            fn record(&self, _span: &tracing::span::Id, _values: &tracing::span::Record<'_>) {}
+
+            fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {
+            }
+
+            fn event(&self, _event: &tracing::Event<'_>) {}
+
+            fn enter(&self, _span: &tracing::span::Id) {}
+
+            fn exit(&self, _span: &tracing::span::Id) {}
+        }
+
+        let dir = test_dir("tracing");
+        let names = Arc::new(Mutex::new(vec![]));
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10);
+        let mut fw = FileWatcher::new_with_options(&op);
+
+        //collect_changes polls on the current thread, where the subscriber is the default
+        tracing::subscriber::with_default(SpanNames(Arc::clone(&names)), || {
+            fw.collect_changes(Duration::from_millis(50)).unwrap()
+        });
+
+        assert!(names.lock().unwrap().iter().any(|name| name == "poll"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn probe_catches_a_file_living_shorter_than_the_refresh_rate() {
+        let dir = test_dir("ephemeral_probe");
+        let ephemeral: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(vec![]));
+
+        let reported = Arc::clone(&ephemeral);
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(400)
+            .with_ephemeral_probe(Duration::from_millis(5))
+            .with_on_ephemeral(move |names| {
+                reported.lock().unwrap().extend(names);
+            });
+        let mut fw = FileWatcher::new_with_options(&op);
+        assert_eq!(
+            fw.config().ephemeral_probe(),
+            Some(Duration::from_millis(5))
+        );
+
+        let (short, kept) = (dir.join("short.tmp"), dir.join("kept.txt"));
+        let (created, deleted) = (short.clone(), kept.clone());
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::File::create(&created).unwrap();
+            fs::File::create(&deleted).unwrap();
+            thread::sleep(Duration::from_millis(50));
+            fs::remove_file(&created).unwrap();
+        });
+
+        let operations = fw.collect_changes(Duration::from_millis(500)).unwrap();
+        writer.join().unwrap();
+
+        //the poll only ever saw the file that stayed
+        assert_eq!(
+            operations,
+            vec![OPERATION::CREATE(HashSet::from([File::from_manifest(
+                &kept,
+                SystemTime::now(),
+                0
+            )]))]
+        );
+        assert_eq!(
+            *ephemeral.lock().unwrap(),
+            vec![short.to_str().unwrap().to_string()]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn min_refresh_for_ephemeral_leaves_half_the_lifetime_to_the_scan() {
+        let mut op = FileWatcherOptions::new(".");
+        op.with_min_refresh_for_ephemeral(Duration::from_millis(100));
+        assert_eq!(
+            FileWatcher::new_with_options(&op).config().refresh_rate(),
+            Duration::from_millis(50)
+        );
+
+        op.with_min_refresh_for_ephemeral(Duration::ZERO);
+        assert_eq!(
+            FileWatcher::new_with_options(&op).config().refresh_rate(),
+            Duration::from_millis(1)
+        );
+    }
+
+    #[test]
+    fn tracked_bytes_follow_the_watched_files() {
+        let dir = test_dir("tracked_bytes");
+        fs::write(dir.join("a.txt"), "12345").unwrap();
+        fs::write(dir.join("b.txt"), "123").unwrap();
+
+        let search_dir = SearchDir::new(dir.clone(), None, None);
+        assert_eq!(search_dir.total_size(), 8);
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10);
+
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(fw.stats().tracked_bytes(), 8);
+
+        fs::write(dir.join("a.txt"), "1234567890").unwrap();
+        fs::File::create(dir.join("c.txt")).unwrap();
+        fs::remove_file(dir.join("b.txt")).unwrap();
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(fw.stats().tracked_bytes(), 10);
+
+        fw.stop().unwrap();
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn watcher_managed_files_never_produce_events() {
+        let dir = test_dir("implicit_ignores");
+
+        //only creates are reported, a write racing a poll cannot add a change of data.txt
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_use_ignore_file(true)
+            .with_operations(OperationMask::CREATE);
+
+        let mut fw = FileWatcher::new_with_options(&op);
+        assert_eq!(
+            fw.config().implicit_ignores(),
+            &[dir.join(DEFAULT_IGNORE_FILE)]
+        );
+
+        let (ignore_file, data_file) = (dir.join(DEFAULT_IGNORE_FILE), dir.join("data.txt"));
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::write(&ignore_file, "*.log\n").unwrap();
+            fs::write(&data_file, "one").unwrap();
+        });
+
+        let operations = fw.collect_changes(Duration::from_millis(300)).unwrap();
+        writer.join().unwrap();
+
+        assert_eq!(operations.len(), 1);
+        match &operations[0] {
+            OPERATION::CREATE(files) => {
+                assert_eq!(names(files), vec![dir.join("data.txt").to_str().unwrap()])
+            }
+            other => panic!("unexpected operation: {:?}", other),
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn scan_progress_is_reported_during_the_initial_scan() {
+        let dir = test_dir("scan_progress");
+        for d in 0..5 {
+            let sub = dir.join(format!("dir{}", d));
+            fs::create_dir_all(&sub).unwrap();
+            for f in 0..500 {
+                fs::write(sub.join(format!("file{}.txt", f)), "").unwrap();
+            }
+        }
+
+        let (sender, receiver) = channel::<usize>();
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10).with_scan_progress(move |count| {
+            let _ = sender.send(count);
+        });
+
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start().unwrap();
+
+        let mut counts: Vec<usize> = vec![];
+        while let Ok(count) = receiver.recv_timeout(Duration::from_secs(5)) {
+            counts.push(count);
+            if count == 2500 {
+                break;
+            }
+        }
+        fw.stop().unwrap();
+
+        assert!(counts.len() >= 2, "{:?}", counts);
+        assert!(counts.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(counts.last(), Some(&2500));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn set_notify_filters_applies_to_a_running_watcher() {
+        static CHANGED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        let dir = test_dir("set_notify_filters");
+        let log = dir.join("app.log");
+        fs::write(&log, "one").unwrap();
+
+        //grows the file while keeping its modification time, so only the size differs
+        let grow = |path: &PathBuf| {
+            let modified = fs::metadata(path).unwrap().modified().unwrap();
+            let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+            std::io::Write::write_all(&mut file, b"more").unwrap();
+            file.set_modified(modified).unwrap();
+        };
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_notify_filters(NotifyFilters::LastWrite)
+            .with_on_changed(|ev| {
+                let mut changed = CHANGED.lock().unwrap();
+                changed.extend(ev.files().iter().map(|f| f.name().to_string()));
+            });
+
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        grow(&log);
+        thread::sleep(Duration::from_millis(100));
+        assert!(CHANGED.lock().unwrap().is_empty());
+
+        fw.set_notify_filters(NotifyFilters::LastWrite | NotifyFilters::Size);
+        assert_eq!(
+            fw.config().notify_filters(),
+            NotifyFilters::LastWrite | NotifyFilters::Size
+        );
+
+        grow(&log);
+        thread::sleep(Duration::from_millis(100));
+        fw.stop().unwrap();
+
+        assert_eq!(*CHANGED.lock().unwrap(), vec![log.to_str().unwrap()]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn set_depth_brings_files_in_and_out_of_reach() {
+        static CREATED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        static DELETED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        let dir = test_dir("set_depth");
+        let deeper = dir.join("nested").join("deeper");
+        fs::create_dir_all(&deeper).unwrap();
+        for file in [
+            dir.join("a.txt"),
+            dir.join("nested").join("b.txt"),
+            deeper.join("c.txt"),
+        ] {
+            fs::File::create(file).unwrap();
+        }
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_directory_depth(0)
+            .with_on_created(|ev| {
+                let mut created = CREATED.lock().unwrap();
+                created.extend(ev.files().iter().map(|f| f.name().to_string()));
+            })
+            .with_on_deleted(|ev| {
+                let mut deleted = DELETED.lock().unwrap();
+                deleted.extend(ev.files().iter().map(|f| f.name().to_string()));
+            });
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        let path = |file: PathBuf| file.to_str().unwrap().to_string();
+        let sorted = |files: &Mutex<Vec<String>>| {
+            let mut files = files.lock().unwrap().clone();
+            files.sort();
+            files
+        };
+
+        fw.set_depth(Some(1));
+        assert_eq!(fw.config().dir_depth(), Some(1));
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(sorted(&CREATED), [path(dir.join("nested").join("b.txt"))]);
+
+        fw.set_depth(None);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(
+            sorted(&CREATED),
+            [
+                path(dir.join("nested").join("b.txt")),
+                path(deeper.join("c.txt"))
+            ]
+        );
+
+        fw.set_depth(Some(0));
+        thread::sleep(Duration::from_millis(100));
+        fw.stop().unwrap();
+        assert_eq!(
+            sorted(&DELETED),
+            [
+                path(dir.join("nested").join("b.txt")),
+                path(deeper.join("c.txt"))
+            ]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn grown_file_reports_the_offset_of_the_appended_data() {
+        let dir = test_dir("append_offset");
+        let log = dir.join("app.log");
+        fs::write(&log, "first line\n").unwrap();
+        let size_before = fs::metadata(&log).unwrap().len();
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_notify_filters(NotifyFilters::CONTENT);
+        let mut fw = FileWatcher::new_with_options(&op);
+
+        let path = log.clone();
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+            std::io::Write::write_all(&mut file, b"second line\n").unwrap();
+            drop(file);
+            thread::sleep(Duration::from_millis(100));
+            fs::write(&path, "short").unwrap();
+        });
+
+        let operations = fw.collect_changes(Duration::from_millis(400)).unwrap();
+        writer.join().unwrap();
+
+        let changes: Vec<&ChangedFile> = operations
+            .iter()
+            .filter_map(|op| match op {
+                OPERATION::CHANGE(files) => Some(files.iter()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+
+        assert_eq!(changes.len(), 2, "{:?}", operations);
+        assert_eq!(changes[0].previous_size(), size_before);
+        assert_eq!(changes[0].append_offset(), Some(size_before));
+        assert_eq!(changes[1].append_offset(), None);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn default_poll_sees_an_append() {
+        let dir = test_dir("append_default");
+        let log = dir.join("app.log");
+        fs::write(&log, "first line\n").unwrap();
+        let size_before = fs::metadata(&log).unwrap().len();
+
+        let mut fw = FileWatcher::new(dir.to_str().unwrap(), None, 10, None);
+        let events = fw.events_rx();
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        //the directory is not touched, the file itself is
+        let mut file = fs::OpenOptions::new().append(true).open(&log).unwrap();
+        std::io::Write::write_all(&mut file, b"second line\n").unwrap();
+        drop(file);
+
+        let mut received: Vec<WatchEvent> = vec![];
+        while let Ok(event) = events.recv_timeout(Duration::from_millis(300)) {
+            received.push(event);
+        }
+        fw.stop().unwrap();
+
+        assert_eq!(received.len(), 1, "{:?}", received);
+        let appended = received.iter().find_map(|ev| match ev {
+            WatchEvent::Changed(file) => Some(file),
+            _ => None,
+        });
+        assert_eq!(appended.unwrap().append_offset(), Some(size_before));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn first_write_to_an_empty_file_is_flagged() {
+        let dir = test_dir("first_write");
+        let output = dir.join("output.csv");
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_notify_filters(NotifyFilters::CONTENT);
+        let mut fw = FileWatcher::new_with_options(&op);
+
+        let path = output.clone();
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::File::create(&path).unwrap();
+            thread::sleep(Duration::from_millis(100));
+            fs::write(&path, "header\n").unwrap();
+            thread::sleep(Duration::from_millis(100));
+            let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+            std::io::Write::write_all(&mut file, b"row\n").unwrap();
+        });
+
+        let operations = fw.collect_changes(Duration::from_millis(500)).unwrap();
+        writer.join().unwrap();
+
+        let created: Vec<&File> = operations
+            .iter()
+            .filter_map(|op| match op {
+                OPERATION::CREATE(files) => Some(files.iter()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        let changes: Vec<&ChangedFile> = operations
+            .iter()
+            .filter_map(|op| match op {
+                OPERATION::CHANGE(files) => Some(files.iter()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+
+        assert_eq!(created.len(), 1, "{:?}", operations);
+        assert!(created[0].is_empty());
+        assert_eq!(changes.len(), 2, "{:?}", operations);
+        assert!(changes[0].is_first_write());
+        assert!(!changes[1].is_first_write());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "schedule")]
+    #[test]
+    fn active_window_keeps_the_watcher_dormant_until_it_opens() {
+        let dir = test_dir("active_window");
+        let opens = chrono::Local::now().time() + Duration::from_millis(400);
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_active_window(opens, opens + Duration::from_secs(60 * 60));
+        let mut fw = FileWatcher::new_with_options(&op);
+        let events = fw.events_rx();
+        fw.start().unwrap();
+
+        thread::sleep(Duration::from_millis(100));
+        fs::File::create(dir.join("dormant.txt")).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(fw.stats().scans(), 0);
+
+        //the file created while dormant is reported once the window opens
+        let event = events.recv_timeout(Duration::from_millis(1000)).unwrap();
+        assert!(
+            matches!(&event, WatchEvent::Created(f) if f.name().ends_with("dormant.txt")),
+            "{:?}",
+            event
+        );
+        assert!(fw.stats().scans() > 0);
+        fw.stop().unwrap();
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn max_runtime_stops_the_watcher_on_its_own() {
+        static STOPPED: Mutex<usize> = Mutex::new(0);
+
+        let dir = test_dir("max_runtime");
+
+        let (sender, receiver) = channel::<Duration>();
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_max_runtime(Duration::from_secs(1))
+            .with_on_max_runtime(move |runtime| {
+                let _ = sender.send(runtime);
+            })
+            .with_on_stopped(|| *STOPPED.lock().unwrap() += 1);
+
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start().unwrap();
+        assert!(fw.is_running());
+
+        let runtime = receiver.recv_timeout(Duration::from_secs(3)).unwrap();
+        assert!(runtime >= Duration::from_secs(1));
+        assert!(!fw.is_running());
+
+        //reported as the events thread exits, stop() does not report it again
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(*STOPPED.lock().unwrap(), 1);
+        assert!(!fw.stop().unwrap().stopped());
+        drop(fw);
+        assert_eq!(*STOPPED.lock().unwrap(), 1);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn max_runtime_delivers_the_changes_made_before_it_was_exceeded() {
+        static CREATED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        let dir = test_dir("max_runtime_last_poll");
+
+        //the file is created while the poll thread sleeps past the max runtime
+        let (sender, receiver) = channel::<Duration>();
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(500)
+            .with_max_runtime(Duration::from_millis(300))
+            .with_on_max_runtime(move |runtime| {
+                let _ = sender.send(runtime);
+            })
+            .with_on_created(|ev| {
+                let mut created = CREATED.lock().unwrap();
+                created.extend(ev.files().iter().map(|f| f.name().to_string()));
+            });
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        let path = dir.join("a.txt").to_str().unwrap().to_string();
+        fs::File::create(&path).unwrap();
+
+        receiver.recv_timeout(Duration::from_secs(3)).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(*CREATED.lock().unwrap(), vec![path]);
+        drop(fw);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn overflow_is_reported_when_polls_are_slower_than_the_refresh_rate() {
+        let dir = test_dir("overflow");
+        fs::write(dir.join("a.txt"), "one").unwrap();
+
+        //an incremental scan asks the detector about every file on every poll
+        let (sender, receiver) = channel::<Duration>();
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_scan_budget(100)
+            .with_change_detector(Box::new(|_, _| {
+                thread::sleep(Duration::from_millis(40));
+                false
+            }))
+            .with_overflow_threshold(Duration::from_millis(50))
+            .with_on_overflow(move |lag| {
+                let _ = sender.send(lag);
+            });
+
+        let mut fw = FileWatcher::new_with_options(&op);
+        assert_eq!(
+            fw.config().overflow_threshold(),
+            Some(Duration::from_millis(50))
+        );
+        fw.start().unwrap();
+
+        let lag = receiver.recv_timeout(Duration::from_secs(3)).unwrap();
+        fw.stop().unwrap();
+
+        //every slow poll is about 30ms over the refresh rate
+        assert!(lag > Duration::from_millis(50), "{:?}", lag);
+        assert!(lag < Duration::from_millis(500), "{:?}", lag);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn start_from_manifest_reports_divergence_from_the_manifest() {
+        static EVENTS: Mutex<Vec<(&'static str, String)>> = Mutex::new(Vec::new());
+
+        let dir = test_dir("manifest");
+        let unchanged = dir.join("unchanged.txt");
+        let changed = dir.join("changed.txt");
+        let created = dir.join("created.txt");
+        let deleted = dir.join("deleted.txt");
+        fs::write(&unchanged, "one").unwrap();
+        fs::write(&changed, "two").unwrap();
+        fs::write(&created, "three").unwrap();
+
+        let modified = |path: &PathBuf| fs::metadata(path).unwrap().modified().unwrap();
+        let manifest = vec![
+            (unchanged.clone(), modified(&unchanged), 3),
+            (changed.clone(), SystemTime::UNIX_EPOCH, 3),
+            (deleted.clone(), SystemTime::UNIX_EPOCH, 4),
+        ];
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_on_created(|ev| {
+                for f in ev.files() {
+                    EVENTS
+                        .lock()
+                        .unwrap()
+                        .push(("created", f.name().to_string()));
+                }
+            })
+            .with_on_changed(|ev| {
+                for f in ev.files() {
+                    EVENTS
+                        .lock()
+                        .unwrap()
+                        .push(("changed", f.name().to_string()));
+                }
+            })
+            .with_on_deleted(|ev| {
+                for f in ev.files() {
+                    EVENTS
+                        .lock()
+                        .unwrap()
+                        .push(("deleted", f.name().to_string()));
+                }
+            });
+
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start_from_manifest(manifest).unwrap();
+        thread::sleep(Duration::from_millis(200));
+        fw.stop().unwrap();
+
+        assert_eq!(
+            *EVENTS.lock().unwrap(),
+            vec![
+                ("created", created.to_str().unwrap().to_string()),
+                ("changed", changed.to_str().unwrap().to_string()),
+                ("deleted", deleted.to_str().unwrap().to_string()),
+            ]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    //whether the watcher reports the change made by `action` to old.txt as a rename to new.txt
+    fn is_reported_as_rename(
+        name: &str,
+        rename_match: RenameMatch,
+        action: fn(&Path, &Path),
+    ) -> bool {
+        let dir = test_dir(name);
+        let (old, new) = (dir.join("old.txt"), dir.join("new.txt"));
+        fs::write(&old, "content").unwrap();
+        thread::sleep(Duration::from_millis(20));
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(200).with_rename_match(rename_match);
+        let mut fw = FileWatcher::new_with_options(&op);
+
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            action(&old, &new);
+        });
+        let operations = fw.collect_changes(Duration::from_millis(500)).unwrap();
+        writer.join().unwrap();
+
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_file(dir.with_extension("staging"));
+
+        operations
+            .iter()
+            .any(|op| matches!(op, OPERATION::RENAME(_)))
+    }
+
+    fn move_file(old: &Path, new: &Path) {
+        fs::rename(old, new).unwrap();
+    }
+
+    //a new file with the same content and size, staged outside the watched directory
+    fn copy_then_delete(old: &Path, new: &Path) {
+        let staging = old.parent().unwrap().with_extension("staging");
+        fs::copy(old, &staging).unwrap();
+        fs::rename(&staging, new).unwrap();
+        fs::remove_file(old).unwrap();
+    }
+
+    fn copy_keeping_modified_then_delete(old: &Path, new: &Path) {
+        let staging = old.parent().unwrap().with_extension("staging");
+        let modified = fs::metadata(old).unwrap().modified().unwrap();
+        fs::copy(old, &staging).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&staging)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        fs::rename(&staging, new).unwrap();
+        fs::remove_file(old).unwrap();
+    }
+
+    #[test]
+    fn rename_match_modified_pairs_files_with_equal_modification_times() {
+        let mode = RenameMatch::Modified;
+        assert!(is_reported_as_rename("rename_modified_1", mode, move_file));
+        assert!(is_reported_as_rename(
+            "rename_modified_2",
+            mode,
+            copy_keeping_modified_then_delete
+        ));
+        assert!(!is_reported_as_rename(
+            "rename_modified_3",
+            mode,
+            copy_then_delete
+        ));
+    }
+
+    #[test]
+    fn files_sharing_a_modification_time_are_not_paired_arbitrarily() {
+        let dir = test_dir("rename_ambiguous");
+        let modified = SystemTime::now() - Duration::from_secs(60);
+        let write = move |path: PathBuf, content: &str| {
+            fs::write(&path, content).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        };
+        for (i, content) in ["a", "bb", "ccc"].iter().enumerate() {
+            write(dir.join(format!("deleted{}.txt", i)), content);
+        }
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(200)
+            .with_rename_match(RenameMatch::Modified);
+        let mut fw = FileWatcher::new_with_options(&op);
+
+        let d = dir.clone();
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            for (i, content) in ["dddd", "eeeee", "ffffff"].iter().enumerate() {
+                fs::remove_file(d.join(format!("deleted{}.txt", i))).unwrap();
+                write(d.join(format!("created{}.txt", i)), content);
+            }
+        });
+        let operations = fw.collect_changes(Duration::from_millis(500)).unwrap();
+        writer.join().unwrap();
+
+        assert!(
+            !operations
+                .iter()
+                .any(|op| matches!(op, OPERATION::RENAME(_))),
+            "{:?}",
+            operations
+        );
+        let (created, deleted) = operations.iter().fold((0, 0), |(c, d), op| match op {
+            OPERATION::CREATE(files) => (c + files.len(), d),
+            OPERATION::DELETE(files) => (c, d + files.len()),
+            _ => (c, d),
+        });
+        assert_eq!((created, deleted), (3, 3));
 
-                    let mut renamed_files: Vec<RenamedFileEntry> = Vec::new();
-                    for created in created_files.clone().iter() {
-                        for deleted in deleted_files.clone().iter() {
-                            if deleted.last_modified() == created.last_modified() {
-                                renamed_files
-                                    .push(RenamedFileEntry::new(created.name(), deleted.name()));
+        let _ = fs::remove_dir_all(&dir);
+    }
 
-                                all_files.remove(deleted);
-                                all_files.insert(created.clone());
-                                break;
-                            }
-                        }
-                    }
+    #[test]
+    fn rename_match_created_pairs_files_with_equal_creation_times() {
+        let mode = RenameMatch::Created;
+        assert!(is_reported_as_rename("rename_created_1", mode, move_file));
+        assert!(!is_reported_as_rename(
+            "rename_created_2",
+            mode,
+            copy_keeping_modified_then_delete
+        ));
+    }
+
+    #[test]
+    fn rename_match_size_pairs_files_with_equal_sizes() {
+        let mode = RenameMatch::Size;
+        assert!(is_reported_as_rename("rename_size_1", mode, move_file));
+        assert!(is_reported_as_rename(
+            "rename_size_2",
+            mode,
+            copy_then_delete
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rename_match_inode_pairs_only_the_same_file() {
+        let mode = RenameMatch::Inode;
+        assert_eq!(RenameMatch::default(), mode);
+        assert!(is_reported_as_rename("rename_inode_1", mode, move_file));
+        assert!(!is_reported_as_rename(
+            "rename_inode_2",
+            mode,
+            copy_keeping_modified_then_delete
+        ));
+    }
+
+    #[test]
+    fn deleted_files_carry_their_last_known_metadata() {
+        let dir = test_dir("deleted_metadata");
+        let report = dir.join("report.pdf");
+        fs::write(&report, vec![0u8; 2048]).unwrap();
+        let modified = fs::metadata(&report).unwrap().modified().unwrap();
+
+        let mut fw = FileWatcher::new(dir.to_str().unwrap(), None, 10, None);
+
+        let path = report.clone();
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::remove_file(&path).unwrap();
+        });
+        let operations = fw.collect_changes(Duration::from_millis(300)).unwrap();
+        writer.join().unwrap();
+
+        match operations.as_slice() {
+            [OPERATION::DELETE(files)] => {
+                let deleted = files.iter().next().unwrap();
+                assert_eq!(deleted.name(), report.to_str().unwrap());
+                assert_eq!(deleted.size(), 2048);
+                assert_eq!(deleted.last_modified(), Some(modified));
+            }
+            other => panic!("unexpected operations: {:?}", other),
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn into_receiver_stops_the_watcher_once_dropped() {
+        let dir = test_dir("into_receiver");
+        let (stopped_sender, stopped) = channel::<()>();
+        let stopped_sender = Mutex::new(stopped_sender);
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10).with_on_stopped(move || {
+            let _ = stopped_sender.lock().unwrap().send(());
+        });
+        let receiver = FileWatcher::new_with_options(&op).into_receiver();
+        thread::sleep(Duration::from_millis(100));
+
+        for i in 0..3 {
+            let file = dir.join(format!("created{}.txt", i));
+            fs::File::create(&file).unwrap();
+
+            let operation = receiver.recv_timeout(Duration::from_secs(2)).unwrap();
+            let expected = File::from_manifest(&file, SystemTime::now(), 0);
+            assert_eq!(operation, OPERATION::CREATE(HashSet::from([expected])));
+        }
+        assert!(stopped.try_recv().is_err());
+
+        //noticed without any further operation
+        drop(receiver);
+        assert!(stopped.recv_timeout(Duration::from_secs(2)).is_ok());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn events_rx_delivers_one_event_per_file() {
+        let dir = test_dir("events_rx");
+        let (changed, deleted, renamed) = (
+            dir.join("changed.txt"),
+            dir.join("deleted.txt"),
+            dir.join("renamed.txt"),
+        );
+        fs::write(&changed, "one").unwrap();
+        fs::write(&deleted, "one").unwrap();
+        fs::write(&renamed, "one").unwrap();
+
+        let mut fw = FileWatcher::new(dir.to_str().unwrap(), None, 10, None);
+        let events = fw.events_rx();
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(100));
+
+        for i in 0..3 {
+            fs::write(dir.join(format!("created{}.txt", i)), "one").unwrap();
+        }
+        fs::write(&changed, "two").unwrap();
+        fs::remove_file(&deleted).unwrap();
+        fs::rename(&renamed, dir.join("renamed_to.txt")).unwrap();
+
+        let mut received: Vec<WatchEvent> = vec![];
+        while let Ok(event) = events.recv_timeout(Duration::from_millis(500)) {
+            received.push(event);
+        }
+        fw.stop().unwrap();
+
+        //a poll between the create and the write of a new file adds a change of it
+        received.retain(
+            |ev| !matches!(ev, WatchEvent::Changed(file) if file.name().contains("created")),
+        );
+        let count = |kind: fn(&WatchEvent) -> bool| received.iter().filter(|ev| kind(ev)).count();
+        assert_eq!(received.len(), 6, "{:?}", received);
+        assert_eq!(count(|ev| matches!(ev, WatchEvent::Created(_))), 3);
+        assert_eq!(count(|ev| matches!(ev, WatchEvent::Changed(_))), 1);
+        assert_eq!(count(|ev| matches!(ev, WatchEvent::Deleted(_))), 1);
+        assert_eq!(count(|ev| matches!(ev, WatchEvent::Renamed(_))), 1);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn with_directories_watches_every_directory() {
+        let src = test_dir("roots_src");
+        let assets = test_dir("roots_assets");
+
+        let mut op = FileWatcherOptions::new(src.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_directories(&[assets.to_str().unwrap(), src.to_str().unwrap()]);
+        let mut fw = op.build().unwrap();
+        assert_eq!(fw.config().directories(), [src.as_path(), assets.as_path()]);
+
+        let events = fw.events_rx();
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        fs::File::create(src.join("main.rs")).unwrap();
+        fs::File::create(assets.join("logo.png")).unwrap();
+
+        let mut created: Vec<String> = vec![];
+        while let Ok(WatchEvent::Created(file)) = events.recv_timeout(Duration::from_millis(300)) {
+            created.push(file.name().to_string());
+        }
+        fw.stop().unwrap();
+
+        created.sort();
+        let mut expected = vec![
+            src.join("main.rs").to_str().unwrap().to_string(),
+            assets.join("logo.png").to_str().unwrap().to_string(),
+        ];
+        expected.sort();
+        assert_eq!(created, expected);
+
+        let _ = fs::remove_dir_all(&src);
+        let _ = fs::remove_dir_all(&assets);
+    }
+
+    #[test]
+    fn paths_are_added_and_removed_while_running() {
+        let dir = test_dir("add_path");
+        let added = test_dir("add_path_added");
+        fs::File::create(added.join("existing.txt")).unwrap();
+
+        let mut fw = FileWatcher::new(dir.to_str().unwrap(), None, 10, None);
+        let events = fw.events_rx();
+        fw.start().unwrap();
+        assert_eq!(
+            fw.add_path("/does/not/exist"),
+            Err(WatcherError::NotFound(PathBuf::from("/does/not/exist")))
+        );
+
+        //files already there are the baseline of the added directory
+        fw.add_path(added.to_str().unwrap()).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        fs::File::create(added.join("new.txt")).unwrap();
+
+        let created = |events: &Receiver<WatchEvent>| -> Vec<String> {
+            let mut result = vec![];
+            while let Ok(WatchEvent::Created(file)) =
+                events.recv_timeout(Duration::from_millis(300))
+            {
+                result.push(file.name().to_string());
+            }
+            result
+        };
+        assert_eq!(created(&events), [added.join("new.txt").to_str().unwrap()]);
+
+        assert!(fw.remove_path(added.to_str().unwrap()));
+        assert!(!fw.remove_path(added.to_str().unwrap()));
+        assert!(!fw.remove_path(dir.to_str().unwrap()));
+        thread::sleep(Duration::from_millis(100));
+        fs::File::create(added.join("ignored.txt")).unwrap();
+        fs::File::create(dir.join("watched.txt")).unwrap();
+
+        assert_eq!(
+            created(&events),
+            [dir.join("watched.txt").to_str().unwrap()]
+        );
+        fw.stop().unwrap();
+
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_dir_all(&added);
+    }
+
+    #[test]
+    fn start_errors_on_a_directory_removed_since_try_new() {
+        let dir = test_dir("removed_before_start");
+        let mut fw = FileWatcher::try_new(dir.to_str().unwrap(), None, 10, None).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let error = fw.start().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+        assert!(!fw.is_running());
+
+        //no threads were spawned, stop() has nothing to wait for
+        let stop_started = Instant::now();
+        assert!(!fw.stop().unwrap().stopped());
+        assert!(stop_started.elapsed() < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn try_new_errors_instead_of_panicking() {
+        let missing = FileWatcher::try_new("/does/not/exist", None, 250, None);
+        assert_eq!(
+            missing.err(),
+            Some(WatcherError::NotFound(PathBuf::from("/does/not/exist")))
+        );
+
+        let dir = test_dir("try_new");
+        let file = dir.join("file.txt");
+        fs::write(&file, "").unwrap();
+        let not_a_dir = FileWatcher::try_new(file.to_str().unwrap(), None, 250, None);
+        assert_eq!(not_a_dir.err(), Some(WatcherError::NotADirectory(file)));
+
+        assert!(FileWatcher::try_new(dir.to_str().unwrap(), None, 250, None).is_ok());
+        assert!(matches!(
+            FileWatcherOptions::new("/does/not/exist").build(),
+            Err(WatcherError::NotFound(_))
+        ));
+        //a directory that is waited for may not exist yet
+        assert!(FileWatcherOptions::new("/does/not/exist")
+            .with_wait_for_directory(true)
+            .build()
+            .is_ok());
+
+        //an invalid filter is an error before SearchDir::new could panic on it
+        let invalid =
+            FileWatcher::try_new(dir.to_str().unwrap(), Some("*.txt;a b".into()), 250, None);
+        assert_eq!(
+            invalid.err(),
+            Some(WatcherError::InvalidFilter(dir.clone(), "*.txt;a b".into()))
+        );
+        assert!(matches!(
+            FileWatcherOptions::new(dir.to_str().unwrap())
+                .with_filter("file[z-a].txt")
+                .build(),
+            Err(WatcherError::InvalidFilter(..))
+        ));
+        //the filter is split on the separators of the options
+        assert!(FileWatcherOptions::new(dir.to_str().unwrap())
+            .with_filter("*.txt *.log")
+            .with_filter_separators(&[' '])
+            .build()
+            .is_ok());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn watch_drops_no_events_with_a_fast_consumer() {
+        let dir = test_dir("watch");
+        assert_eq!(
+            FileWatcher::watch(dir.join("missing").to_str().unwrap(), None)
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::NotFound
+        );
+
+        let (mut fw, events) = FileWatcher::watch(dir.to_str().unwrap(), None).unwrap();
+        thread::sleep(Duration::from_millis(300));
+
+        for i in 0..20 {
+            fs::write(dir.join(format!("file{}.txt", i)), "one").unwrap();
+        }
+
+        let mut created = 0;
+        while let Ok(event) = events.recv_timeout(Duration::from_millis(1000)) {
+            if matches!(event, WatchEvent::Created(_)) {
+                created += 1;
+            }
+        }
+        fw.stop().unwrap();
+
+        assert_eq!(created, 20);
+        assert_eq!(fw.stats().dropped_events(), 0);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn bounded_events_rx_counts_the_events_it_drops() {
+        let dir = test_dir("events_rx_bounded");
+
+        let mut fw = FileWatcher::new(dir.to_str().unwrap(), None, 10, None);
+        let events = fw.events_rx_bounded(2);
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(100));
 
-                    created_files.retain(|f| !renamed_files.iter().any(|v| v.name().eq(f.name())));
-                    deleted_files
-                        .retain(|f| !renamed_files.iter().any(|v| v.old_name().eq(f.name())));
+        for i in 0..5 {
+            fs::File::create(dir.join(format!("created{}.txt", i))).unwrap();
+        }
+        thread::sleep(Duration::from_millis(300));
+        fw.stop().unwrap();
+
+        assert_eq!(events.try_iter().count(), 2);
+        assert_eq!(fw.stats().dropped_events(), 3);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
 
-                    let local_sender = sender_mutex.lock().unwrap();
-                    if created_files.len() > 0 {
-                        all_files = all_files.union(&created_files).map(|f| f.clone()).collect();
+    #[cfg(feature = "webhook")]
+    #[test]
+    fn webhook_posts_the_operations_as_json() {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+
+        let dir = test_dir("webhook");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let url = format!("http://{}/hooks", listener.local_addr().unwrap());
+
+        //answers a single request with 200, handing over its request line and body; gives up
+        //once no request came in time so a missed event fails the test instead of hanging it
+        let server = thread::spawn(move || {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            let stream = loop {
+                match listener.accept() {
+                    Ok((stream, _)) => break stream,
+                    Err(_) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+                    Err(error) => panic!("no webhook post arrived: {}", error),
+                }
+            };
+            stream.set_nonblocking(false).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
 
-                        // trigger event for added files
-                        let _ = local_sender
-                            .clone()
-                            .send(ChannelOperation::CONTINUE(OPERATION::CREATE(created_files)));
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim_end().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
                     }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            (&stream)
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+
+            (request_line, String::from_utf8(body).unwrap())
+        });
+
+        let mut fw = FileWatcher::new(dir.to_str().unwrap(), None, 10, None);
+        fw.with_webhook(&url).unwrap();
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        fs::File::create(dir.join("a.txt")).unwrap();
+
+        let served = server.join();
+        fw.stop().unwrap();
+        let (request_line, body) = served.unwrap();
+
+        assert_eq!(request_line, "POST /hooks HTTP/1.1\r\n");
+        let name = dir.join("a.txt").to_str().unwrap().replace('\\', "\\\\");
+        assert_eq!(
+            body,
+            format!(
+                r#"{{"kind":"created","files":[{{"name":"{}","size":0}}]}}"#,
+                name
+            )
+        );
+        assert!(fw.with_webhook("https://localhost/hooks").is_ok());
+        assert!(fw.with_webhook("ftp://localhost").is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "webhook")]
+    #[test]
+    fn webhook_errors_reach_the_sink() {
+        use std::net::TcpListener;
+
+        struct ErrorSink(Sender<()>);
+
+        impl WatchSink for ErrorSink {
+            fn on_error(&mut self, _error: &Error) {
+                let _ = self.0.send(());
+            }
+        }
+
+        let dir = test_dir("webhook_error");
+        //nothing listens on the port once the listener is dropped
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hooks", listener.local_addr().unwrap());
+        drop(listener);
+
+        let (sender, errors) = channel::<()>();
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_sink(Box::new(ErrorSink(sender)));
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.with_webhook(&url).unwrap();
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(50));
+
+        fs::File::create(dir.join("a.txt")).unwrap();
+
+        assert!(errors.recv_timeout(Duration::from_secs(5)).is_ok());
+        fw.stop().unwrap();
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "crossbeam")]
+    #[test]
+    fn crossbeam_receiver_takes_part_in_a_select() {
+        let dir = test_dir("crossbeam");
+
+        let mut fw = FileWatcher::new(dir.to_str().unwrap(), None, 10, None);
+        let operations = fw.crossbeam_receiver();
+        let ticks = crossbeam_channel::tick(Duration::from_millis(20));
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(50));
 
-                    // trigger event for changed files
-                    if changed_files.len() > 0 {
-                        let _ = local_sender
-                            .clone()
-                            .send(ChannelOperation::CONTINUE(OPERATION::CHANGE(changed_files)));
+        fs::File::create(dir.join("a.txt")).unwrap();
+
+        let (mut created, mut ticked) = (0, 0);
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while created == 0 && Instant::now() < deadline {
+            crossbeam_channel::select! {
+                recv(operations) -> op => {
+                    if let Ok(OPERATION::CREATE(files)) = op {
+                        created += files.len();
                     }
+                }
+                recv(ticks) -> _ => ticked += 1,
+            }
+        }
+        fw.stop().unwrap();
 
-                    if deleted_files.len() > 0 {
-                        for file in deleted_files.iter() {
-                            all_files.remove(&file);
-                        }
+        assert_eq!(created, 1);
+        assert!(ticked > 0);
 
-                        // trigger event for added files
-                        let _ = local_sender.clone().send(ChannelOperation::CONTINUE(
-                            OPERATION::DELETE(deleted_files.clone()),
-                        ));
-                    };
+        let _ = fs::remove_dir_all(&dir);
+    }
 
-                    if renamed_files.len() > 0 {
-                        // trigger event for added files
-                        let _ = local_sender.clone().send(ChannelOperation::CONTINUE(
-                            OPERATION::RENAME(renamed_files.into_iter().collect()),
-                        ));
-                    };
+    #[test]
+    fn suppressed_paths_do_not_come_back_as_events() {
+        let dir = test_dir("suppress_path");
+        let own = dir.join("own.txt");
+        fs::create_dir_all(dir.join("cache")).unwrap();
+        fs::write(&own, "one").unwrap();
+        let markers: Vec<PathBuf> = (1..=3).map(|i| dir.join(format!("marker{}", i))).collect();
+        for marker in markers.iter() {
+            fs::File::create(marker).unwrap();
+        }
 
-                    drop(local_sender);
+        let mut fw = FileWatcher::new(dir.to_str().unwrap(), None, 10, None);
+        let events = fw.events_rx();
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(100));
 
-                    thread::sleep(Duration::from_millis(refresh_rate));
+        //the names of the events up to the one of the deleted marker, deletes are dispatched
+        //last so the poll of the writes before it was delivered whole
+        let names_until = |marker: &Path| {
+            fs::remove_file(marker).unwrap();
+            let mut names: Vec<String> = vec![];
+            while let Ok(event) = events.recv_timeout(Duration::from_millis(500)) {
+                let name = match event {
+                    WatchEvent::Created(f) | WatchEvent::Deleted(f) => f.name().to_string(),
+                    WatchEvent::Changed(f) => f.name().to_string(),
+                    WatchEvent::Renamed(f) => f.name().to_string(),
+                };
+                if Path::new(&name) == marker {
+                    break;
                 }
+                names.push(name);
+            }
+            names
+        };
+
+        let until = Instant::now() + Duration::from_millis(300);
+        fw.suppress_path(Path::new("own.txt"), until);
+        fs::write(&own, "two, suppressed").unwrap();
+        assert!(names_until(&markers[0]).is_empty());
+
+        let guard = fw.suppress(Path::new("cache"), true);
+        fs::File::create(dir.join("cache").join("entry.bin")).unwrap();
+        assert!(names_until(&markers[1]).is_empty());
+        drop(guard);
+
+        thread::sleep(until.saturating_duration_since(Instant::now()));
+        fs::write(&own, "three, reported").unwrap();
+        assert_eq!(
+            names_until(&markers[2]),
+            vec![own.to_str().unwrap().to_string()]
+        );
+        fw.stop().unwrap();
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unpaired_move_is_reported_with_a_shared_correlation_id() {
+        let dir = test_dir("rename_fallback");
+        let (old, new) = (dir.join("old.txt"), dir.join("new.txt"));
+        fs::write(&old, "content").unwrap();
+        thread::sleep(Duration::from_millis(20));
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(200)
+            .with_rename_match(RenameMatch::Modified)
+            .with_rename_fallback_reporting(true);
+        let mut fw = FileWatcher::new_with_options(&op);
+
+        //the copy gets a new modification time, so it cannot be paired with the original
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            copy_then_delete(&old, &new);
+        });
+        let operations = fw.collect_changes(Duration::from_millis(500)).unwrap();
+        writer.join().unwrap();
+
+        match operations.as_slice() {
+            [OPERATION::CREATE(created), OPERATION::DELETE(deleted)] => {
+                let created = created.iter().next().unwrap();
+                let deleted = deleted.iter().next().unwrap();
+                assert!(created.correlation_id().is_some());
+                assert_eq!(created.correlation_id(), deleted.correlation_id());
+            }
+            other => panic!("unexpected operations: {:?}", other),
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+        let _ = fs::remove_file(dir.with_extension("staging"));
+    }
+
+    #[test]
+    fn case_only_rename_is_reported_with_both_casings() {
+        let dir = test_dir("case_rename");
+        let (old, new) = (dir.join("Report.TXT"), dir.join("report.txt"));
+        fs::write(&old, "content").unwrap();
+
+        let mut fw = FileWatcher::new(dir.to_str().unwrap(), None, 10, None);
+
+        let (from, to) = (old.clone(), new.clone());
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::rename(&from, &to).unwrap();
+        });
+        let operations = fw.collect_changes(Duration::from_millis(300)).unwrap();
+        writer.join().unwrap();
+
+        match operations.as_slice() {
+            [OPERATION::RENAME(renamed)] => {
+                let renamed = renamed.iter().next().unwrap();
+                assert_eq!(renamed.old_name(), old.to_str().unwrap());
+                assert_eq!(renamed.name(), new.to_str().unwrap());
+            }
+            other => panic!("unexpected operations: {:?}", other),
+        }
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn created_files_are_routed_by_extension() {
+        static IMAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        static DATA: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        static OTHERS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        let dir = test_dir("created_for");
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_on_created_for("jpg", |ev| {
+                let mut images = IMAGES.lock().unwrap();
+                images.extend(ev.files().iter().map(|f| f.name().to_string()));
+            })
+            .with_on_created_for(".csv", |ev| {
+                let mut data = DATA.lock().unwrap();
+                data.extend(ev.files().iter().map(|f| f.name().to_string()));
+            })
+            .with_on_created(|ev| {
+                let mut others = OTHERS.lock().unwrap();
+                others.extend(ev.files().iter().map(|f| f.name().to_string()));
             });
 
-        self.main_thread = Some(main);
-        self.events_thread = Some(child);
-        self.channel_sender = Some(sender.clone());
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(100));
 
-        Ok(true)
+        for name in ["a.jpg", "b.JPG", "c.csv", "d.txt"] {
+            fs::File::create(dir.join(name)).unwrap();
+        }
+        thread::sleep(Duration::from_millis(200));
+        fw.stop().unwrap();
+
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        assert_eq!(*IMAGES.lock().unwrap(), vec![path("a.jpg"), path("b.JPG")]);
+        assert_eq!(*DATA.lock().unwrap(), vec![path("c.csv")]);
+        assert_eq!(*OTHERS.lock().unwrap(), vec![path("d.txt")]);
+
+        let _ = fs::remove_dir_all(&dir);
     }
 
-    pub fn stop(&mut self) -> Result<bool, Error> {
-        if !*self.is_started.lock().unwrap() {
-            return Ok(false);
+    #[test]
+    fn prefixed_change_callbacks_only_see_their_subtree() {
+        static LOGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        static DOCS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        let dir = test_dir("changed_in");
+        for sub in ["logs", "docs", "other"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+            fs::write(dir.join(sub).join("a.txt"), "one").unwrap();
         }
+        //a sibling sharing the prefix as a string is not part of the subtree
+        fs::create_dir_all(dir.join("logs_old")).unwrap();
+        fs::write(dir.join("logs_old").join("a.txt"), "one").unwrap();
 
-        //set the flag to false to be picked up by the main thread
-        *self.is_started.lock().unwrap() = false;
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_on_changed_in("logs", |ev| {
+                let mut logs = LOGS.lock().unwrap();
+                logs.extend(ev.files().iter().map(|f| f.name().to_string()));
+            })
+            .with_on_changed_in(dir.join("docs").to_str().unwrap(), |ev| {
+                let mut docs = DOCS.lock().unwrap();
+                docs.extend(ev.files().iter().map(|f| f.name().to_string()));
+            });
 
-        self.events_thread = None;
-        self.main_thread = None;
-        self.events_thread = None;
+        let mut fw = FileWatcher::new_with_options(&op);
+        fw.start().unwrap();
+        thread::sleep(Duration::from_millis(100));
 
-        Ok(true)
+        for sub in ["logs", "docs", "other", "logs_old"] {
+            fs::write(dir.join(sub).join("a.txt"), "two").unwrap();
+        }
+        thread::sleep(Duration::from_millis(200));
+        fw.stop().unwrap();
+
+        let path = |sub: &str| dir.join(sub).join("a.txt").to_str().unwrap().to_string();
+        assert_eq!(*LOGS.lock().unwrap(), vec![path("logs")]);
+        assert_eq!(*DOCS.lock().unwrap(), vec![path("docs")]);
+
+        let _ = fs::remove_dir_all(&dir);
     }
 
-    fn get_files(search_dir: &SearchDir, notify_filters: NotifyFilters) -> HashSet<File> {
-        let meta: &Metadata = search_dir.metadata();
-        let mut result: HashSet<File> = HashSet::new();
+    #[test]
+    fn modification_time_going_backwards_is_a_change() {
+        let dir = test_dir("clock_backwards");
+        let file = dir.join("clock.txt");
+        fs::write(&file, "one").unwrap();
+
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10).with_scan_budget(100);
+        let mut fw = FileWatcher::new_with_options(&op);
+
+        let path = file.clone();
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            let earlier = SystemTime::UNIX_EPOCH + Duration::from_secs(86_400);
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(earlier)
+                .unwrap();
+        });
+        let operations = fw.collect_changes(Duration::from_millis(300)).unwrap();
+        writer.join().unwrap();
 
-        for file in search_dir.get_files() {
-            if Self::apply_notify_filters(&file, meta, notify_filters) {
-                result.insert(file);
+        match operations.as_slice() {
+            [OPERATION::CHANGE(changed)] => {
+                let changed = changed.iter().next().unwrap();
+                assert_eq!(changed.name(), file.to_str().unwrap());
+                assert!(changed.file().last_modified() < changed.previous().last_modified());
             }
+            other => panic!("unexpected operations: {:?}", other),
         }
 
-        result
+        let _ = fs::remove_dir_all(&dir);
     }
 
-    fn apply_notify_filters(
-        file: &File,
-        old_meta: &Metadata,
-        notify_filters: NotifyFilters,
-    ) -> bool {
-        let last_write = notify_filters.contains(NotifyFilters::LastWrite)
-            && old_meta.modified().unwrap() != file.last_modified().unwrap();
+    #[test]
+    fn change_detector_replaces_the_notify_filters() {
+        let dir = test_dir("change_detector");
+        let file = dir.join("data.txt");
+        fs::write(&file, "one").unwrap();
 
-        if last_write {
-            return last_write;
-        }
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_scan_budget(100)
+            .with_notify_filters(NotifyFilters::ALL)
+            .with_change_detector(Box::new(|old, new| old.size() != new.size()));
+        let mut fw = FileWatcher::new_with_options(&op);
 
-        let last_access = notify_filters.contains(NotifyFilters::LastAccess)
-            && old_meta.accessed().unwrap() != file.last_modified().unwrap();
+        let path = file.clone();
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::write(&path, "two").unwrap();
+            thread::sleep(Duration::from_millis(100));
+            fs::write(&path, "three").unwrap();
+        });
+        let operations = fw.collect_changes(Duration::from_millis(400)).unwrap();
+        writer.join().unwrap();
 
-        if last_access {
-            return last_access;
+        match operations.as_slice() {
+            [OPERATION::CHANGE(changed)] => {
+                let changed = changed.iter().next().unwrap();
+                assert_eq!(changed.previous_size(), 3);
+                assert_eq!(changed.file().size(), 5);
+            }
+            other => panic!("unexpected operations: {:?}", other),
         }
 
-        let creation_time = notify_filters.contains(NotifyFilters::CreationTime)
-            && old_meta.created().unwrap() != file.created();
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn watch_until_returns_once_stop_is_signalled() {
+        static CREATED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        let dir = test_dir("watch_until");
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10).with_on_created(|ev| {
+            let mut created = CREATED.lock().unwrap();
+            created.extend(ev.files().iter().map(|f| f.name().to_string()));
+        });
+        let mut fw = FileWatcher::new_with_options(&op);
+
+        let (stop, stop_receiver) = channel::<()>();
+        let file = dir.join("created.txt");
+        let path = file.clone();
+        let signaller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::write(&path, "one").unwrap();
+            thread::sleep(Duration::from_millis(100));
+            stop.send(()).unwrap();
+        });
+
+        assert!(fw.watch_until(stop_receiver).unwrap());
+        signaller.join().unwrap();
+
+        assert!(!fw.is_running());
+        assert_eq!(*CREATED.lock().unwrap(), vec![file.to_str().unwrap()]);
 
-        creation_time
+        let _ = fs::remove_dir_all(&dir);
     }
-}
 
-#[cfg(test)]
-mod tests {
+    #[test]
+    fn batch_callbacks_see_files_ordered_by_name() {
+        static CREATED: Mutex<Vec<String>> = Mutex::new(Vec::new());
 
-    use self::{events::OnChangedEventArgs, FileWatcher};
+        let names = [
+            "m.txt", "b.txt", "z.txt", "a.txt", "k.txt", "c.txt", "y.txt", "d.txt",
+        ];
+        let mut runs: Vec<Vec<String>> = vec![];
 
-    use super::*;
+        for run in 0..3 {
+            CREATED.lock().unwrap().clear();
+
+            let dir = test_dir(&format!("batch_order_{}", run));
+            let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+            op.with_refresh_rate(1000).with_on_created(|ev| {
+                let mut created = CREATED.lock().unwrap();
+                created.extend(
+                    ev.files()
+                        .iter()
+                        .map(|f| f.file_name().unwrap().to_string()),
+                );
+            });
+
+            let mut fw = FileWatcher::new_with_options(&op);
+            fw.start().unwrap();
+
+            //all files land in the poll running a second after the first one
+            thread::sleep(Duration::from_millis(200));
+            for name in names {
+                fs::write(dir.join(name), "one").unwrap();
+            }
+            thread::sleep(Duration::from_millis(1200));
+            fw.stop().unwrap();
+
+            runs.push(CREATED.lock().unwrap().clone());
+            let _ = fs::remove_dir_all(&dir);
+        }
+
+        let mut sorted: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        sorted.sort();
+        assert!(runs.iter().all(|run| *run == sorted), "{:?}", runs);
+    }
 
     #[test]
-    fn it_works() {
-        let folder = "D:\\Test";
+    fn wait_for_directory_starts_watching_once_it_is_created() {
+        static CREATED: Mutex<Vec<String>> = Mutex::new(Vec::new());
 
-        let mut fw = FileWatcher::new(folder, Some(String::from("*.txt")), 250, None);
-        fw.on_changed(|ev: OnChangedEventArgs| {
-            println!("{:?}", ev.files());
+        let dir = test_dir("wait_for_directory").join("later");
+
+        let (sender, watching) = channel::<PathBuf>();
+        let mut op = FileWatcherOptions::new(dir.to_str().unwrap());
+        op.with_refresh_rate(10)
+            .with_wait_for_directory(true)
+            .with_on_watching(move |path| {
+                let _ = sender.send(path);
+            })
+            .with_on_created(|ev| {
+                let mut created = CREATED.lock().unwrap();
+                created.extend(ev.files().iter().map(|f| f.name().to_string()));
+            });
+
+        let mut fw = FileWatcher::new_with_options(&op);
+        assert!(fw.start().unwrap());
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(watching.try_recv().is_err());
+
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(watching.recv_timeout(Duration::from_secs(2)).unwrap(), dir);
+
+        let file = dir.join("created.txt");
+        fs::write(&file, "one").unwrap();
+        thread::sleep(Duration::from_millis(200));
+        fw.stop().unwrap();
+
+        assert_eq!(*CREATED.lock().unwrap(), vec![file.to_str().unwrap()]);
+
+        let _ = fs::remove_dir_all(dir.parent().unwrap());
+    }
+
+    #[test]
+    fn operation_kind_names_every_variant() {
+        let files = HashSet::from([File::from_manifest(
+            Path::new("/a.txt"),
+            SystemTime::now(),
+            0,
+        )]);
+        let kinds: Vec<(EventKind, &str)> = [
+            OPERATION::CREATE(files.clone()),
+            OPERATION::CHANGE(HashSet::new()),
+            OPERATION::DELETE(files),
+            OPERATION::RENAME(HashSet::new()),
+            OPERATION::BATCH {
+                created: HashSet::new(),
+                changed: HashSet::new(),
+                deleted: HashSet::new(),
+                renamed: HashSet::new(),
+            },
+            OPERATION::ERROR(Error),
+        ]
+        .iter()
+        .map(|op| (op.kind(), op.kind().as_str()))
+        .collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                (EventKind::Created, "created"),
+                (EventKind::Changed, "changed"),
+                (EventKind::Deleted, "deleted"),
+                (EventKind::Renamed, "renamed"),
+                (EventKind::Batch, "batch"),
+                (EventKind::Error, "error"),
+            ]
+        );
+        assert_eq!(EventKind::Renamed.to_string(), "renamed");
+    }
+
+    #[test]
+    fn combined_events_deliver_one_batch_per_poll() {
+        static BATCHES: Mutex<Vec<[usize; 4]>> = Mutex::new(Vec::new());
+        static SINGLE: Mutex<usize> = Mutex::new(0);
+
+        let dir = change_every_kind_in_one_poll("combined_events", |op| {
+            op.with_combined_events(true)
+                .with_on_batch(|ev| {
+                    let counts = [
+                        ev.created().len(),
+                        ev.changed().len(),
+                        ev.deleted().len(),
+                        ev.renamed().len(),
+                    ];
+                    BATCHES.lock().unwrap().push(counts);
+                })
+                .with_on_created(|_| *SINGLE.lock().unwrap() += 1)
+                .with_on_changed(|_| *SINGLE.lock().unwrap() += 1)
+                .with_on_deleted(|_| *SINGLE.lock().unwrap() += 1)
+                .with_on_renamed(|_| *SINGLE.lock().unwrap() += 1);
         });
 
-        let _ = match fw.start() {
-            Ok(started) => started,
-            Err(error) => panic!("Could not start the file watcher: {}", error),
-        };
+        assert_eq!(*BATCHES.lock().unwrap(), vec![[1, 1, 1, 1]]);
+        assert_eq!(*SINGLE.lock().unwrap(), 0);
+
+        let _ = fs::remove_dir_all(&dir);
     }
 }
@@ -1,9 +1,6 @@
 use rwatcher::{FileWatcher, FileWatcherOptions, NotifyFilters};
 use std::io;
 
-mod events;
-mod search_dir;
-
 fn main() -> std::io::Result<()> {
     let mut op = FileWatcherOptions::new("D:\\Test");
     op.with_filter("*.txt;*.pdf;*.sql;*.jpg")
@@ -12,7 +9,7 @@ fn main() -> std::io::Result<()> {
         .with_directory_depth(4)
         .with_on_created(|ev| {
             let files = ev.files();
-            println!("{} -> {}", "CREATED", files.len());
+            println!("CREATED -> {}", files.len());
 
             for f in files {
                 println!("-> {}", f.name());
@@ -32,7 +29,7 @@ fn main() -> std::io::Result<()> {
         })
         .with_on_changed(|ev| {
             let files = ev.files();
-            println!("{} -> {}", "CHANGED", files.len());
+            println!("CHANGED -> {}", files.len());
 
             for f in files {
                 println!("-> {}", f.name());
@@ -42,7 +39,7 @@ fn main() -> std::io::Result<()> {
         })
         .with_on_renamed(|ev| {
             let files = ev.files();
-            println!("{} -> {}", "RENAMED", files.len());
+            println!("RENAMED -> {}", files.len());
 
             for f in files {
                 println!("-> {} -> {}", f.old_name(), f.name());
@@ -66,7 +63,7 @@ fn main() -> std::io::Result<()> {
     let _ = io::stdin().read_line(&mut String::new());
 
     match fw.stop() {
-        Ok(stopped) => match stopped {
+        Ok(summary) => match summary.stopped() {
             true => println!("File watcher has stopped!"),
             _ => println!("File watcher is already stopped!"),
         },
@@ -0,0 +1,898 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::callback::{Callback, ChangeDetector};
+use crate::search_dir::{
+    apply_notify_filters, ChangedFile, File, FileId, RenamedFileEntry, ScanCursor, SearchDir,
+};
+use crate::{NotifyFilters, OperationMask, RenameMatch, RenameScope, OPERATION};
+
+const SCAN_PROGRESS_STEP: usize = 256;
+const SCAN_PROGRESS_FILES: usize = 1000;
+const SCAN_PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+// reports how many files the baseline scan found so far, every SCAN_PROGRESS_FILES files or
+// SCAN_PROGRESS_INTERVAL, whichever comes first
+#[derive(Debug)]
+struct ScanProgress {
+    callback: Callback<usize>,
+    reported_count: usize,
+    reported_at: Instant,
+}
+
+impl ScanProgress {
+    fn new(callback: Callback<usize>) -> Self {
+        Self {
+            callback,
+            reported_count: 0,
+            reported_at: Instant::now(),
+        }
+    }
+
+    fn update(&mut self, count: usize) {
+        if count - self.reported_count >= SCAN_PROGRESS_FILES
+            || self.reported_at.elapsed() >= SCAN_PROGRESS_INTERVAL
+        {
+            self.report(count);
+        }
+    }
+
+    fn finish(&mut self, count: usize) {
+        if count != self.reported_count {
+            self.report(count);
+        }
+    }
+
+    fn report(&mut self, count: usize) {
+        self.callback.call(count);
+        self.reported_count = count;
+        self.reported_at = Instant::now();
+    }
+}
+
+#[derive(Debug)]
+pub struct Poller {
+    search_dir: SearchDir,
+    all_files: HashSet<File>,
+    notify_filters: Arc<Mutex<NotifyFilters>>,
+    scan_budget: Option<usize>,
+    cursor: Option<ScanCursor>,
+    scanned_files: HashSet<File>,
+    //with dedup_hardlinks, the other paths of an inode scanned so far in the current pass and
+    //the first path in name order of every inode seen, starting from the tracked files
+    scanned_links: HashSet<File>,
+    first_links: HashMap<FileId, String>,
+    baseline_loaded: bool,
+    scan_progress: Option<ScanProgress>,
+    //the baseline did not come from the directory, the next poll must scan it
+    force_scan: bool,
+    rename_match: RenameMatch,
+    rename_fallback_reporting: bool,
+    rename_scope: RenameScope,
+    last_correlation_id: u64,
+    change_detector: Option<ChangeDetector>,
+    read_on_change: Option<u64>,
+    content_size_limit: Option<u64>,
+    ignore_atime_only: bool,
+    //every nth poll scans the directory even when has_changed says nothing changed
+    full_rescan_every: Option<u32>,
+    //the kinds of operations computed and reported, the files are tracked either way
+    operations: OperationMask,
+    polls: u32,
+    //how many files the latest poll looked at
+    scanned: usize,
+    //the depth to scan with, may change between polls
+    depth: Option<Arc<Mutex<Option<u8>>>>,
+    //the size of all_files, kept up to date as files are added, changed and removed
+    tracked_bytes: u64,
+    //the directories of the last full scan, to tell a moved directory apart
+    dir_inodes: HashMap<PathBuf, FileId>,
+}
+
+impl Poller {
+    pub fn new(
+        mut search_dir: SearchDir,
+        notify_filters: Arc<Mutex<NotifyFilters>>,
+        scan_budget: Option<usize>,
+        on_scan_progress: Option<Callback<usize>>,
+    ) -> Self {
+        let mut all_files: HashSet<File> = HashSet::new();
+        let mut dir_inodes: HashMap<PathBuf, FileId> = HashMap::new();
+        let mut scan_progress = on_scan_progress.map(ScanProgress::new);
+
+        //load existing files, an incremental scan loads them during its first pass instead
+        if scan_budget.is_none() {
+            all_files = match scan_progress.as_mut() {
+                Some(progress) => Self::load_files(&search_dir, progress),
+                None => search_dir.get_files(),
+            };
+            scan_progress = None;
+
+            //check for directory changes
+            search_dir.sync_metadata();
+            dir_inodes = search_dir.dir_inodes();
+        }
+
+        let tracked_bytes = Self::total_size(&all_files);
+
+        Self {
+            search_dir,
+            all_files,
+            notify_filters,
+            scan_budget,
+            cursor: None,
+            scanned_files: HashSet::new(),
+            scanned_links: HashSet::new(),
+            first_links: HashMap::new(),
+            baseline_loaded: scan_budget.is_none(),
+            scan_progress,
+            force_scan: false,
+            rename_match: RenameMatch::default(),
+            rename_fallback_reporting: false,
+            rename_scope: RenameScope::default(),
+            last_correlation_id: 0,
+            change_detector: None,
+            read_on_change: None,
+            content_size_limit: None,
+            ignore_atime_only: true,
+            full_rescan_every: None,
+            operations: OperationMask::all(),
+            polls: 0,
+            scanned: 0,
+            depth: None,
+            tracked_bytes,
+            dir_inodes,
+        }
+    }
+
+    // diffs against the given files instead of the current directory content
+    pub fn from_baseline(
+        search_dir: SearchDir,
+        notify_filters: Arc<Mutex<NotifyFilters>>,
+        scan_budget: Option<usize>,
+        baseline: HashSet<File>,
+    ) -> Self {
+        Self {
+            search_dir,
+            tracked_bytes: Self::total_size(&baseline),
+            all_files: baseline,
+            notify_filters,
+            scan_budget,
+            cursor: None,
+            scanned_files: HashSet::new(),
+            scanned_links: HashSet::new(),
+            first_links: HashMap::new(),
+            baseline_loaded: true,
+            scan_progress: None,
+            force_scan: true,
+            rename_match: RenameMatch::default(),
+            rename_fallback_reporting: false,
+            rename_scope: RenameScope::default(),
+            last_correlation_id: 0,
+            change_detector: None,
+            read_on_change: None,
+            content_size_limit: None,
+            ignore_atime_only: true,
+            full_rescan_every: None,
+            operations: OperationMask::all(),
+            polls: 0,
+            scanned: 0,
+            depth: None,
+            dir_inodes: HashMap::new(),
+        }
+    }
+
+    pub fn with_rename_match(&mut self, rename_match: RenameMatch) -> &mut Self {
+        self.rename_match = rename_match;
+
+        self
+    }
+
+    pub fn with_rename_fallback_reporting(&mut self, enabled: bool) -> &mut Self {
+        self.rename_fallback_reporting = enabled;
+
+        self
+    }
+
+    pub fn with_rename_scope(&mut self, rename_scope: RenameScope) -> &mut Self {
+        self.rename_scope = rename_scope;
+
+        self
+    }
+
+    pub fn with_change_detector(&mut self, change_detector: Option<ChangeDetector>) -> &mut Self {
+        self.change_detector = change_detector;
+
+        self
+    }
+
+    pub fn with_read_on_change(&mut self, max_size: Option<u64>) -> &mut Self {
+        self.read_on_change = max_size;
+
+        self
+    }
+
+    pub fn with_content_size_limit(&mut self, limit: Option<u64>) -> &mut Self {
+        self.content_size_limit = limit;
+
+        self
+    }
+
+    pub fn with_ignore_atime_only(&mut self, ignore_atime_only: bool) -> &mut Self {
+        self.ignore_atime_only = ignore_atime_only;
+
+        self
+    }
+
+    pub fn with_full_rescan_every(&mut self, polls: Option<u32>) -> &mut Self {
+        self.full_rescan_every = polls.map(|polls| polls.max(1));
+
+        self
+    }
+
+    pub fn with_operations(&mut self, operations: OperationMask) -> &mut Self {
+        self.operations = operations;
+
+        self
+    }
+
+    pub fn with_depth(&mut self, depth: Arc<Mutex<Option<u8>>>) -> &mut Self {
+        self.depth = Some(depth);
+
+        self
+    }
+
+    // a changed depth restarts the scan, files brought in reach are created and the ones out of
+    // reach deleted
+    fn apply_depth(&mut self) {
+        let depth = match &self.depth {
+            Some(depth) => *depth.lock().unwrap(),
+            None => return,
+        };
+
+        if depth == self.search_dir.depth() {
+            return;
+        }
+
+        self.search_dir.set_depth(depth);
+        self.force_scan = true;
+        self.cursor = None;
+        self.scanned_files.clear();
+        self.scanned_links.clear();
+    }
+
+    // takes the directory as it is now for the files seen so far, what changed since the last
+    // poll is never reported; an incremental scan starts over
+    pub fn rebaseline(&mut self) {
+        self.apply_depth();
+
+        self.all_files = self.search_dir.get_files();
+        self.tracked_bytes = Self::total_size(&self.all_files);
+        self.search_dir.sync_metadata();
+        self.dir_inodes = self.search_dir.dir_inodes();
+
+        self.force_scan = false;
+        self.cursor = None;
+        self.scanned_files.clear();
+        self.scanned_links.clear();
+        self.baseline_loaded = true;
+    }
+
+    // the files seen so far
+    pub fn files(&self) -> &HashSet<File> {
+        &self.all_files
+    }
+
+    pub fn search_dir(&self) -> &SearchDir {
+        &self.search_dir
+    }
+
+    pub fn scanned(&self) -> usize {
+        self.scanned
+    }
+
+    // the summed size of the files seen so far
+    pub fn tracked_bytes(&self) -> u64 {
+        self.tracked_bytes
+    }
+
+    fn total_size(files: &HashSet<File>) -> u64 {
+        files.iter().map(File::size).sum()
+    }
+
+    fn load_files(search_dir: &SearchDir, progress: &mut ScanProgress) -> HashSet<File> {
+        let mut result: HashSet<File> = HashSet::new();
+        let mut cursor = search_dir.scan_cursor();
+
+        while !search_dir.scan_step(&mut cursor, SCAN_PROGRESS_STEP, &mut result) {
+            progress.update(result.len());
+        }
+        progress.finish(result.len());
+
+        search_dir.dedup_hardlinks(result)
+    }
+
+    // diffs the directory against the files seen so far, operations are returned in the
+    // order CREATE, CHANGE, DELETE, RENAME
+    pub fn poll(&mut self) -> Vec<OPERATION> {
+        self.scanned = 0;
+        self.apply_depth();
+        if let Some(budget) = self.scan_budget {
+            return self.poll_incremental(budget);
+        }
+
+        let mut result: Vec<OPERATION> = vec![];
+
+        //a modified ignore file can hide or reveal files without touching any directory
+        let ignore_file_changed = self.search_dir.reload_ignore_file();
+
+        //has_changed and has_changed_files miss some changes, a periodic full rescan catches them
+        self.polls = self.polls.wrapping_add(1);
+        let rescan_due = self
+            .full_rescan_every
+            .is_some_and(|every| self.polls.is_multiple_of(every));
+
+        //if there's no change in the directory or its files do not get files
+        if !ignore_file_changed
+            && !self.force_scan
+            && !rescan_due
+            && !self.search_dir.has_changed()
+            && !self.has_changed_files()
+        {
+            return result;
+        }
+        self.force_scan = false;
+        //refreshed ahead of the listing, a directory modified during the scan differs next poll
+        self.search_dir.sync_metadata();
+
+        let rename_match = self.rename_match;
+        let operations = self.operations;
+        let ignore_atime_only = self.ignore_atime_only;
+        let change_detector = self.change_detector.as_ref();
+        let all_files = &mut self.all_files;
+        let tracked_bytes = &mut self.tracked_bytes;
+        let latest_files = self.search_dir.get_files();
+        self.scanned = latest_files.len();
+        let notify_filters = *self.notify_filters.lock().unwrap();
+
+        let mut created_files: HashSet<File> =
+            latest_files.difference(all_files).cloned().collect();
+
+        let mut deleted_files: HashSet<File> =
+            all_files.difference(&latest_files).cloned().collect();
+
+        //without CHANGE the files are refreshed in the snapshot without being compared
+        let report_changes = operations.contains(OperationMask::CHANGE);
+        let mut changed_files: HashSet<ChangedFile> = HashSet::new();
+        for file in latest_files.iter() {
+            if let Some(fe) = all_files.get(file) {
+                // file was changed
+                let changed = report_changes
+                    && Self::is_changed(
+                        change_detector,
+                        fe,
+                        file,
+                        notify_filters,
+                        ignore_atime_only,
+                    );
+                if changed {
+                    changed_files.insert(ChangedFile::new(file.clone(), fe.clone()));
+                }
+
+                if changed || !report_changes {
+                    *tracked_bytes = *tracked_bytes - fe.size() + file.size();
+                    all_files.remove(file);
+                    all_files.insert(file.clone());
+                }
+            }
+        }
+
+        let mut renamed_files: Vec<RenamedFileEntry> = Vec::new();
+        let mut renamed_dirs: Vec<RenamedFileEntry> = Vec::new();
+
+        //without RENAME nothing is paired, a moved file is a CREATE and a DELETE
+        let pair_renames = operations.contains(OperationMask::RENAME);
+
+        //a moved directory is a single rename instead of one for every file in it
+        if pair_renames && rename_match == RenameMatch::Inode {
+            let dir_inodes = self.search_dir.dir_inodes();
+            for (old_dir, new_dir) in Self::moved_dirs(&self.dir_inodes, &dir_inodes) {
+                let moved: Vec<File> = created_files
+                    .iter()
+                    .filter(|f| Path::new(f.name()).starts_with(&new_dir))
+                    .cloned()
+                    .collect();
+
+                for created in moved {
+                    let relative = Path::new(created.name()).strip_prefix(&new_dir).unwrap();
+                    let old_name = old_dir.join(relative);
+
+                    if let Some(deleted) = deleted_files
+                        .iter()
+                        .find(|f| Path::new(f.name()) == old_name)
+                        .cloned()
+                    {
+                        created_files.remove(&created);
+                        deleted_files.remove(&deleted);
+                        *tracked_bytes = *tracked_bytes - deleted.size() + created.size();
+                        all_files.remove(&deleted);
+                        all_files.insert(created);
+                    }
+                }
+
+                renamed_dirs.push(RenamedFileEntry::new(
+                    &new_dir.to_string_lossy(),
+                    &old_dir.to_string_lossy(),
+                ));
+            }
+            self.dir_inodes = dir_inodes;
+        }
+
+        let pairs = match pair_renames {
+            true => Self::pair_renames(&created_files, &deleted_files, rename_match),
+            false => vec![],
+        };
+        for (created, deleted) in pairs {
+            renamed_files.push(RenamedFileEntry::new(created.name(), deleted.name()));
+
+            *tracked_bytes = *tracked_bytes - deleted.size() + created.size();
+            all_files.remove(&deleted);
+            all_files.insert(created);
+        }
+
+        created_files.retain(|f| !renamed_files.iter().any(|v| v.name().eq(f.name())));
+        deleted_files.retain(|f| !renamed_files.iter().any(|v| v.old_name().eq(f.name())));
+
+        //renames out of the scope are dropped, the files are already tracked under their new name
+        if !self.rename_scope.includes_files() {
+            renamed_files.clear();
+        }
+        if self.rename_scope.includes_dirs() {
+            renamed_files.extend(renamed_dirs);
+        }
+
+        //creates and deletes left unpaired in the same poll may still be moves, share an id
+        //between them so the consumer can pair them itself
+        let mut correlation_id: Option<u64> = None;
+        if self.rename_fallback_reporting && !created_files.is_empty() && !deleted_files.is_empty()
+        {
+            self.last_correlation_id += 1;
+            correlation_id = Some(self.last_correlation_id);
+        }
+
+        if !created_files.is_empty() {
+            *tracked_bytes += Self::total_size(&created_files);
+            *all_files = all_files.union(&created_files).cloned().collect();
+
+            // trigger event for added files
+            if operations.contains(OperationMask::CREATE) {
+                result.push(OPERATION::CREATE(Self::correlate(
+                    created_files,
+                    correlation_id,
+                )));
+            }
+        }
+
+        // trigger event for changed files
+        if !changed_files.is_empty() {
+            result.push(OPERATION::CHANGE(Self::read_contents(
+                changed_files,
+                self.read_on_change,
+                self.content_size_limit,
+            )));
+        }
+
+        if !deleted_files.is_empty() {
+            for file in deleted_files.iter() {
+                *tracked_bytes -= file.size();
+                all_files.remove(file);
+            }
+
+            if operations.contains(OperationMask::DELETE) {
+                result.push(OPERATION::DELETE(Self::correlate(
+                    deleted_files,
+                    correlation_id,
+                )));
+            }
+        };
+
+        if !renamed_files.is_empty() {
+            result.push(OPERATION::RENAME(renamed_files.into_iter().collect()));
+        };
+
+        result
+    }
+
+    // tags the reported copies only, the snapshot in all_files stays untagged
+    fn correlate(files: HashSet<File>, correlation_id: Option<u64>) -> HashSet<File> {
+        match correlation_id {
+            Some(id) => files
+                .into_iter()
+                .map(|mut f| {
+                    f.set_correlation_id(id);
+                    f
+                })
+                .collect(),
+            None => files,
+        }
+    }
+
+    // scans at most `budget` entries per call, resuming from the saved cursor; created and
+    // changed files are reported as their part of the tree is scanned, deleted files once the
+    // whole tree was traversed. Renames are not paired in this mode.
+    fn poll_incremental(&mut self, budget: usize) -> Vec<OPERATION> {
+        let mut result: Vec<OPERATION> = vec![];
+
+        let dedup_hardlinks = self.search_dir.dedups_hardlinks();
+        if self.cursor.is_none() {
+            self.search_dir.reload_ignore_file();
+            self.cursor = Some(self.search_dir.scan_cursor());
+
+            if dedup_hardlinks {
+                self.first_links = self
+                    .all_files
+                    .iter()
+                    .filter(|f| matches!(f.id(), FileId::Inode { .. }))
+                    .map(|f| (f.id(), f.name().to_string()))
+                    .collect();
+            }
+        }
+
+        let mut found_files: HashSet<File> = HashSet::new();
+        let completed =
+            self.search_dir
+                .scan_step(self.cursor.as_mut().unwrap(), budget, &mut found_files);
+
+        self.scanned = found_files.len();
+        let mut found_files: Vec<File> = found_files.into_iter().collect();
+        found_files.sort_by(|a, b| a.name().cmp(b.name()));
+
+        let notify_filters = *self.notify_filters.lock().unwrap();
+        let mut created_files: HashSet<File> = HashSet::new();
+        let mut changed_files: HashSet<ChangedFile> = HashSet::new();
+        let report_changes = self.operations.contains(OperationMask::CHANGE);
+        for mut file in found_files {
+            //a later path of an inode is only listed as a hardlink once the pass completes
+            if dedup_hardlinks && matches!(file.id(), FileId::Inode { .. }) {
+                match self.first_links.get(&file.id()) {
+                    Some(first) if first.as_str() < file.name() => {
+                        self.scanned_links.insert(file);
+                        continue;
+                    }
+                    _ => {
+                        self.first_links.insert(file.id(), file.name().to_string());
+                    }
+                }
+            }
+
+            //the hardlinks stay as listed by the last pass until this one completes
+            if let Some(old) = self.all_files.get(&file).filter(|_| dedup_hardlinks) {
+                file.set_hardlinks(old.hardlinks().to_vec());
+            }
+
+            match self.all_files.get(&file) {
+                Some(old)
+                    if report_changes
+                        && Self::is_changed(
+                            self.change_detector.as_ref(),
+                            old,
+                            &file,
+                            notify_filters,
+                            self.ignore_atime_only,
+                        ) =>
+                {
+                    changed_files.insert(ChangedFile::new(file.clone(), old.clone()));
+                }
+                Some(_) => {}
+                None => {
+                    created_files.insert(file.clone());
+                }
+            }
+
+            if let Some(old) = self.all_files.replace(file.clone()) {
+                self.tracked_bytes -= old.size();
+            }
+            self.tracked_bytes += file.size();
+            self.scanned_files.insert(file);
+        }
+
+        let mut deleted_files: HashSet<File> = HashSet::new();
+        if completed {
+            //the same dedup as a full scan, a path whose first link is gone is created now
+            let mut scanned = std::mem::take(&mut self.scanned_files);
+            scanned.extend(std::mem::take(&mut self.scanned_links));
+            let scanned = self.search_dir.dedup_hardlinks(scanned);
+
+            created_files.extend(scanned.difference(&self.all_files).cloned());
+            deleted_files = self.all_files.difference(&scanned).cloned().collect();
+
+            self.all_files = scanned;
+            self.tracked_bytes = Self::total_size(&self.all_files);
+            self.first_links.clear();
+            self.cursor = None;
+        }
+
+        //the first pass only builds the baseline
+        if !self.baseline_loaded {
+            self.baseline_loaded = completed;
+
+            if let Some(progress) = self.scan_progress.as_mut() {
+                match completed {
+                    true => progress.finish(self.all_files.len()),
+                    false => progress.update(self.scanned_files.len()),
+                }
+            }
+
+            if completed {
+                self.scan_progress = None;
+            }
+
+            return result;
+        }
+
+        if !created_files.is_empty() && self.operations.contains(OperationMask::CREATE) {
+            result.push(OPERATION::CREATE(created_files));
+        }
+
+        if !changed_files.is_empty() {
+            result.push(OPERATION::CHANGE(Self::read_contents(
+                changed_files,
+                self.read_on_change,
+                self.content_size_limit,
+            )));
+        }
+
+        if !deleted_files.is_empty() && self.operations.contains(OperationMask::DELETE) {
+            result.push(OPERATION::DELETE(deleted_files));
+        }
+
+        result
+    }
+
+    // attaches the contents of the files not larger than the read on change size, the files over
+    // the content size limit are flagged instead. At most one byte past the size is read, a file
+    // that grew past it since it was listed is treated as if it had been that large
+    fn read_contents(
+        changed_files: HashSet<ChangedFile>,
+        read_on_change: Option<u64>,
+        content_size_limit: Option<u64>,
+    ) -> HashSet<ChangedFile> {
+        let max_size = match read_on_change {
+            Some(max_size) => max_size.min(content_size_limit.unwrap_or(u64::MAX)),
+            None => return changed_files,
+        };
+
+        changed_files
+            .into_iter()
+            .map(|changed| {
+                if content_size_limit.is_some_and(|limit| changed.file().size() > limit) {
+                    return changed.with_content_skipped();
+                }
+
+                if changed.file().size() > max_size {
+                    return changed.with_contents(None);
+                }
+
+                //the file may have grown since it was listed, never read more than allowed
+                let mut contents = vec![];
+                let read = fs::File::open(changed.name()).and_then(|file| {
+                    file.take(max_size.saturating_add(1))
+                        .read_to_end(&mut contents)
+                });
+                match read {
+                    Ok(len) if len as u64 <= max_size => changed.with_contents(Some(contents)),
+                    Ok(len) if content_size_limit.is_some_and(|limit| len as u64 > limit) => {
+                        changed.with_content_skipped()
+                    }
+                    _ => changed.with_contents(None),
+                }
+            })
+            .collect()
+    }
+
+    // a change detector replaces the notify filters entirely
+    // a write into a file changes no directory, the known files are looked at one by one instead
+    // of listing them; without CHANGE there is nothing to look for
+    fn has_changed_files(&self) -> bool {
+        if !self.operations.contains(OperationMask::CHANGE) {
+            return false;
+        }
+
+        let notify_filters = *self.notify_filters.lock().unwrap();
+        self.all_files.iter().any(|file| {
+            self.search_dir.stat(file).is_some_and(|latest| {
+                Self::is_changed(
+                    self.change_detector.as_ref(),
+                    file,
+                    &latest,
+                    notify_filters,
+                    self.ignore_atime_only,
+                )
+            })
+        })
+    }
+
+    fn is_changed(
+        change_detector: Option<&ChangeDetector>,
+        old: &File,
+        new: &File,
+        notify_filters: NotifyFilters,
+        ignore_atime_only: bool,
+    ) -> bool {
+        //a symlink losing, regaining or changing its target is a change whatever the filters
+        if old.is_broken_symlink() != new.is_broken_symlink()
+            || old.symlink_target() != new.symlink_target()
+        {
+            return true;
+        }
+
+        match change_detector {
+            Some(detector) => detector.is_changed(old, new),
+            None => apply_notify_filters(old, new, notify_filters, ignore_atime_only),
+        }
+    }
+
+    // directories gone from their path whose device and inode showed up under a new one, nested
+    // directories that moved along with their parent are left out
+    fn moved_dirs(
+        old: &HashMap<PathBuf, FileId>,
+        new: &HashMap<PathBuf, FileId>,
+    ) -> Vec<(PathBuf, PathBuf)> {
+        let added: HashMap<FileId, &PathBuf> = new
+            .iter()
+            .filter(|(dir, _)| !old.contains_key(*dir))
+            .map(|(dir, id)| (*id, dir))
+            .collect();
+
+        let moves: Vec<(PathBuf, PathBuf)> = old
+            .iter()
+            .filter(|(dir, _)| !new.contains_key(*dir))
+            .filter_map(|(dir, id)| added.get(id).map(|to| (dir.clone(), (*to).clone())))
+            .collect();
+
+        moves
+            .iter()
+            .filter(|(from, _)| {
+                !moves
+                    .iter()
+                    .any(|(other, _)| other != from && from.starts_with(other))
+            })
+            .cloned()
+            .collect()
+    }
+
+    // pairs the created and deleted files that are renames of each other. A file matching several
+    // others is narrowed down to the ones with the same size, modification time and inode (when
+    // known), a pair is kept only when neither file could belong to another pair.
+    fn pair_renames(
+        created_files: &HashSet<File>,
+        deleted_files: &HashSet<File>,
+        rename_match: RenameMatch,
+    ) -> Vec<(File, File)> {
+        let mut pairs: Vec<(File, File)> = vec![];
+        for created in created_files.iter() {
+            let mut candidates: Vec<&File> = deleted_files
+                .iter()
+                .filter(|deleted| Self::is_renamed(deleted, created, rename_match))
+                .collect();
+
+            if candidates.len() > 1 {
+                candidates.retain(|deleted| Self::is_same_file(deleted, created));
+            }
+
+            if let [deleted] = candidates[..] {
+                pairs.push((created.clone(), deleted.clone()));
+            }
+        }
+
+        let claims = |deleted: &File| pairs.iter().filter(|(_, d)| d == deleted).count();
+        pairs
+            .iter()
+            .filter(|(_, deleted)| claims(deleted) == 1)
+            .cloned()
+            .collect()
+    }
+
+    fn is_same_file(deleted: &File, created: &File) -> bool {
+        let same_inode = match (deleted.inode(), created.inode()) {
+            (Some(old), Some(new)) => old == new,
+            _ => true,
+        };
+
+        same_inode
+            && deleted.size() == created.size()
+            && deleted.last_modified() == created.last_modified()
+    }
+
+    // inodes and creation times may be unknown, inodes then fall back to the modification time
+    fn is_renamed(deleted: &File, created: &File, rename_match: RenameMatch) -> bool {
+        let same_created = deleted.created().is_some() && deleted.created() == created.created();
+
+        match rename_match {
+            RenameMatch::Modified => deleted.last_modified() == created.last_modified(),
+            RenameMatch::Created => same_created,
+            RenameMatch::Size => deleted.size() == created.size(),
+            //a freed inode can be reused by a new file right away, the creation time tells them apart
+            RenameMatch::Inode => match (deleted.id(), created.id()) {
+                (old @ FileId::Inode { .. }, new @ FileId::Inode { .. }) => {
+                    old == new && (same_created || deleted.created().is_none())
+                }
+                _ => deleted.last_modified() == created.last_modified(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn a_poll_after_a_scan_without_changes_does_not_scan() {
+        let dir: PathBuf =
+            std::env::temp_dir().join(format!("rwatcher_poll_snapshot_{}", std::process::id()));
+        let nested = dir.join("nested");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&nested).unwrap();
+
+        let mut poller = Poller::new(
+            SearchDir::new(dir.clone(), None, None),
+            Arc::new(Mutex::new(NotifyFilters::DEFAULT)),
+            None,
+            None,
+        );
+        assert!(poller.poll().is_empty());
+        assert_eq!(poller.scanned(), 0);
+
+        thread::sleep(Duration::from_millis(20));
+        fs::write(nested.join("file.txt"), "one").unwrap();
+        assert_eq!(poller.poll().len(), 1);
+        assert_eq!(poller.scanned(), 1);
+
+        //the scan refreshed the snapshot, nothing changed since
+        assert!(poller.poll().is_empty());
+        assert_eq!(poller.scanned(), 0);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_file_growing_past_the_limit_after_the_stat_is_not_read() {
+        let dir: PathBuf =
+            std::env::temp_dir().join(format!("rwatcher_poll_grown_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("log.txt");
+        fs::write(&path, "one").unwrap();
+
+        //listed at 3 bytes, the file grows before it is read
+        let listed = File::from_manifest(&path, std::time::SystemTime::now(), 3);
+        fs::write(&path, "longer than the limit").unwrap();
+        let changed = || HashSet::from([ChangedFile::new(listed.clone(), listed.clone())]);
+
+        let skipped = Poller::read_contents(changed(), Some(1024), Some(8));
+        let skipped = skipped.iter().next().unwrap();
+        assert_eq!(skipped.contents(), None);
+        assert!(skipped.content_skipped());
+
+        let not_read = Poller::read_contents(changed(), Some(8), None);
+        let not_read = not_read.iter().next().unwrap();
+        assert_eq!(not_read.contents(), None);
+        assert!(!not_read.content_skipped());
+
+        //no limit at all
+        let read = Poller::read_contents(changed(), Some(u64::MAX), Some(u64::MAX));
+        let read = read.iter().next().unwrap();
+        assert_eq!(read.contents(), Some(&b"longer than the limit"[..]));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
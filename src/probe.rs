@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::search_dir::{File, SearchDir};
+
+// lists the names in the directory between polls, to catch the files created and deleted before
+// a poll could see them. Best effort: a file living shorter than the interval is still missed,
+// and a renamed one is taken for an ephemeral file under its old name.
+#[derive(Debug)]
+pub struct EphemeralProbe {
+    interval: Duration,
+    //names listed since the last poll that it did not know of
+    seen: HashSet<String>,
+}
+
+impl EphemeralProbe {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval: interval.max(Duration::from_millis(1)),
+            seen: HashSet::new(),
+        }
+    }
+
+    // sleeps for the duration, listing the directory every interval
+    pub fn sleep(&mut self, duration: Duration, search_dir: &SearchDir, files: &HashSet<File>) {
+        let known = Self::known_names(files);
+        let deadline = Instant::now() + duration;
+
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            thread::sleep(self.interval.min(left));
+
+            //only new names pay for the full match, which may sniff the content
+            for name in search_dir.get_names() {
+                if !known.contains(name.as_str())
+                    && !self.seen.contains(&name)
+                    && search_dir.is_match(Path::new(&name))
+                {
+                    self.seen.insert(name);
+                }
+            }
+        }
+    }
+
+    // the names listed since the last call that are missing from the files of the poll since
+    pub fn take_ephemeral(&mut self, files: &HashSet<File>) -> HashSet<String> {
+        let known = Self::known_names(files);
+        let seen = std::mem::take(&mut self.seen);
+
+        seen.into_iter()
+            .filter(|name| !known.contains(name.as_str()))
+            .collect()
+    }
+
+    // hardlinks reported as one file are known under each of their paths
+    fn known_names(files: &HashSet<File>) -> HashSet<&str> {
+        files
+            .iter()
+            .flat_map(|f| std::iter::once(f.name()).chain(f.hardlinks().iter().map(String::as_str)))
+            .collect()
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::poller::Poller;
+use crate::search_dir::{ChangedFile, File, RenamedFileEntry};
+use crate::OPERATION;
+
+// the pollers of every watched directory, the one the watcher was created with first. They are
+// polled together and their operations merged, so each kind is still dispatched once per poll
+pub struct Roots {
+    pollers: Vec<(PathBuf, Poller)>,
+}
+
+impl Roots {
+    pub fn new(pollers: Vec<(PathBuf, Poller)>) -> Self {
+        Self { pollers }
+    }
+
+    // the poller of the directory the watcher was created with
+    pub fn primary(&self) -> &Poller {
+        &self.pollers[0].1
+    }
+
+    pub fn watches(&self, dir: &Path) -> bool {
+        self.pollers.iter().any(|(root, _)| root == dir)
+    }
+
+    // polled from the next poll on, its files as of now are the baseline
+    pub fn add(&mut self, dir: PathBuf, poller: Poller) {
+        self.pollers.push((dir, poller));
+    }
+
+    // the directories other than the primary one that keep rejects are no longer polled, their
+    // files are dropped without any events
+    pub fn retain_extra(&mut self, keep: impl Fn(&Path) -> bool) {
+        let primary = self.pollers[0].0.clone();
+        self.pollers.retain(|(dir, _)| *dir == primary || keep(dir));
+    }
+
+    // the operations of every directory in the order CREATE, CHANGE, DELETE, RENAME; a file
+    // moved between two of them is a delete and a create, never a rename
+    pub fn poll(&mut self) -> Vec<OPERATION> {
+        if let [(_, poller)] = self.pollers.as_mut_slice() {
+            return poller.poll();
+        }
+
+        let mut created: HashSet<File> = HashSet::new();
+        let mut changed: HashSet<ChangedFile> = HashSet::new();
+        let mut deleted: HashSet<File> = HashSet::new();
+        let mut renamed: HashSet<RenamedFileEntry> = HashSet::new();
+        for op in self
+            .pollers
+            .iter_mut()
+            .flat_map(|(_, poller)| poller.poll())
+        {
+            match op {
+                OPERATION::CREATE(files) => created.extend(files),
+                OPERATION::CHANGE(files) => changed.extend(files),
+                OPERATION::DELETE(files) => deleted.extend(files),
+                OPERATION::RENAME(files) => renamed.extend(files),
+                OPERATION::BATCH { .. } | OPERATION::ERROR(_) => {}
+            }
+        }
+
+        let mut result = vec![];
+        if !created.is_empty() {
+            result.push(OPERATION::CREATE(created));
+        }
+        if !changed.is_empty() {
+            result.push(OPERATION::CHANGE(changed));
+        }
+        if !deleted.is_empty() {
+            result.push(OPERATION::DELETE(deleted));
+        }
+        if !renamed.is_empty() {
+            result.push(OPERATION::RENAME(renamed));
+        }
+
+        result
+    }
+
+    pub fn rebaseline(&mut self) {
+        self.pollers
+            .iter_mut()
+            .for_each(|(_, poller)| poller.rebaseline());
+    }
+
+    // the files seen so far in every directory
+    pub fn files(&self) -> HashSet<File> {
+        self.pollers
+            .iter()
+            .flat_map(|(_, poller)| poller.files().iter().cloned())
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pollers
+            .iter()
+            .all(|(_, poller)| poller.files().is_empty())
+    }
+
+    pub fn scanned(&self) -> usize {
+        self.pollers
+            .iter()
+            .map(|(_, poller)| poller.scanned())
+            .sum()
+    }
+
+    pub fn tracked_bytes(&self) -> u64 {
+        self.pollers
+            .iter()
+            .map(|(_, poller)| poller.tracked_bytes())
+            .sum()
+    }
+}
@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use chrono::{Local, NaiveTime, Timelike};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+// the time of day the watcher polls in, from the start included to the end excluded; a start
+// after the end spans midnight and one equal to it the whole day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActiveWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl ActiveWindow {
+    pub fn new(start: NaiveTime, end: NaiveTime) -> Self {
+        Self { start, end }
+    }
+
+    pub fn start(&self) -> NaiveTime {
+        self.start
+    }
+
+    pub fn end(&self) -> NaiveTime {
+        self.end
+    }
+
+    // None while the window is open, otherwise how long until it opens, as of the local time
+    pub fn closed_for(&self) -> Option<Duration> {
+        self.closed_for_at(Local::now().time())
+    }
+
+    fn closed_for_at(&self, now: NaiveTime) -> Option<Duration> {
+        let (start, end, now) = (
+            Self::since_midnight(self.start),
+            Self::since_midnight(self.end),
+            Self::since_midnight(now),
+        );
+
+        let open = match start <= end {
+            true => start == end || (start <= now && now < end),
+            false => now >= start || now < end,
+        };
+
+        match (open, now <= start) {
+            (true, _) => None,
+            (false, true) => Some(start - now),
+            (false, false) => Some(DAY - now + start),
+        }
+    }
+
+    //a leap second counts towards the next second
+    fn since_midnight(time: NaiveTime) -> Duration {
+        Duration::from_secs(time.num_seconds_from_midnight().into())
+            + Duration::from_nanos(time.nanosecond().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, min: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, min, 0).unwrap()
+    }
+
+    #[test]
+    fn closed_for_counts_down_to_the_next_opening() {
+        let business_hours = ActiveWindow::new(time(9, 0), time(17, 0));
+        assert_eq!(business_hours.closed_for_at(time(9, 0)), None);
+        assert_eq!(business_hours.closed_for_at(time(16, 59)), None);
+        assert_eq!(
+            business_hours.closed_for_at(time(8, 30)),
+            Some(Duration::from_secs(30 * 60))
+        );
+        assert_eq!(
+            business_hours.closed_for_at(time(17, 0)),
+            Some(Duration::from_secs(16 * 60 * 60))
+        );
+
+        let overnight = ActiveWindow::new(time(22, 0), time(2, 0));
+        assert_eq!(overnight.closed_for_at(time(23, 0)), None);
+        assert_eq!(overnight.closed_for_at(time(1, 0)), None);
+        assert_eq!(
+            overnight.closed_for_at(time(2, 0)),
+            Some(Duration::from_secs(20 * 60 * 60))
+        );
+
+        let whole_day = ActiveWindow::new(time(6, 0), time(6, 0));
+        assert_eq!(whole_day.closed_for_at(time(5, 0)), None);
+    }
+}
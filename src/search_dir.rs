@@ -1,32 +1,204 @@
-#![recursion_limit = "255"]
-
 use core::panic;
 use regex::Regex;
-use std::collections::HashSet;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::ffi::OsStr;
 use std::fmt::Display;
-use std::fs::{self, Metadata};
-use std::hash::Hash;
+use std::fs::{self, DirEntry, FileType, Metadata};
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::io;
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::callback::Callback;
+use crate::ignore::IgnoreFile;
+use crate::{Entries, NonUtf8, NotifyFilters, WatcherError};
 
-const EMPTY_STRING: &str = "";
 const POINT_CHAR: char = '.';
-const ALL_FILES_FILTER: &str = "*.*";
-const FILTER_SEPARATORS: &[char] = &[';', ','];
-const VALID_FILTER_REGEX_PATH: &str =
-    r"^\*\.\*$|^\*\.([a-zA-Z0-9])+$|^([a-zA-Z0-9])+\.([a-zA-Z0-9])+$";
+pub(crate) const ALL_FILES_FILTER: &str = "*.*";
+pub(crate) const FILTER_SEPARATORS: &[char] = &[';', ','];
+const VALID_FILTER_REGEX_PATH: &str = r"^\*\.\*$|^\*\.([a-zA-Z0-9])+$|^([a-zA-Z0-9]|\[([a-zA-Z0-9](-[a-zA-Z0-9])?)+\])+\.([a-zA-Z0-9])+$";
+
+//compiled on first use and shared by every SearchDir
+static VALID_FILTER_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(VALID_FILTER_REGEX_PATH).unwrap());
 
 #[derive(Debug, Clone)]
 pub struct SearchDir {
     dir_path: PathBuf,
     depth: Option<u8>,
-    extensions: Option<Vec<String>>,
-    file_names: Option<Vec<String>>,
-    include_all_files: bool,
-    last_synced: Option<SystemTime>,
+    entry_filter: EntryFilter,
     meta: Metadata,
+    dir_snapshot: BTreeMap<PathBuf, DirState>,
+    dedup_hardlinks: bool,
+}
+
+// a directory as of a sync, the id is only kept where the inode is known
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DirState {
+    modified: SystemTime,
+    id: Option<FileId>,
+}
+
+// decides which directory entries are part of a scan
+#[derive(Debug, Clone, Default)]
+struct EntryFilter {
+    //sets, so matching a file costs the same however long the filter is; extensions are kept
+    //lowercase and compared ignoring case
+    extensions: Option<HashSet<String>>,
+    file_names: Option<HashSet<String>>,
+    //the names with character classes, i.e. chunk[0-9].bin, tried one by one
+    name_patterns: Vec<NamePattern>,
+    ignore_file: Option<IgnoreFile>,
+    excluded_paths: HashSet<PathBuf>,
+    report_broken_symlinks: bool,
+    track_symlink_targets: bool,
+    non_utf8: NonUtf8,
+    entries: Entries,
+    content_types: Option<Vec<String>>,
+    //whether a file matched the content types, as of its size and modification time
+    content_type_matches: Arc<Mutex<ContentTypeCache>>,
+    metadata_retry: MetadataRetry,
+    //called with the files whose metadata could not be read, retries included
+    on_metadata_error: Option<Callback<(PathBuf, io::Error)>>,
+    read_metadata: MetadataReader,
+    //the directories the last scan could not list, i.e. for lack of permissions
+    unreadable_dirs: Arc<Mutex<BTreeSet<PathBuf>>>,
+}
+
+// how many times a failed metadata read is retried, the backoff doubles after each retry
+#[derive(Debug, Clone, Copy, Default)]
+struct MetadataRetry {
+    attempts: u32,
+    backoff: Duration,
+}
+
+// reads the metadata of a listed entry, DirEntry::metadata unless try_new_with_metadata_reader
+// was given another
+#[derive(Debug, Clone, Copy)]
+struct MetadataReader(fn(&DirEntry) -> io::Result<Metadata>);
+
+impl Default for MetadataReader {
+    fn default() -> Self {
+        Self(DirEntry::metadata)
+    }
+}
+
+type ContentTypeMatch = ((u64, Option<SystemTime>), bool);
+
+// the files looked up since the current scan started and the ones left from the previous scan,
+// dropped when the next scan starts so files that are gone do not stay cached
+#[derive(Debug, Default)]
+struct ContentTypeCache {
+    current: HashMap<PathBuf, ContentTypeMatch>,
+    previous: HashMap<PathBuf, ContentTypeMatch>,
+}
+
+impl ContentTypeCache {
+    fn start_scan(&mut self) {
+        self.previous = std::mem::take(&mut self.current);
+    }
+
+    fn get(&mut self, path: &Path) -> Option<&ContentTypeMatch> {
+        if let Some(found) = self.previous.remove(path) {
+            self.current.insert(path.to_path_buf(), found);
+        }
+
+        self.current.get(path)
+    }
+
+    fn insert(&mut self, path: PathBuf, found: ContentTypeMatch) {
+        self.current.insert(path, found);
+    }
+
+    #[cfg(all(test, feature = "content-type"))]
+    fn len(&self) -> usize {
+        self.current.len() + self.previous.len()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeReason {
+    Modified(PathBuf),
+    Added(PathBuf),
+    Removed(PathBuf),
+}
+
+impl ChangeReason {
+    pub fn path(&self) -> &Path {
+        match self {
+            ChangeReason::Modified(path) => path,
+            ChangeReason::Added(path) => path,
+            ChangeReason::Removed(path) => path,
+        }
+    }
+}
+
+// what differs between two directory trees, see SearchDir::diff_dirs
+#[derive(Debug, Clone, Default)]
+pub struct DirDiff {
+    created: HashSet<File>,
+    changed: HashSet<ChangedFile>,
+    deleted: HashSet<File>,
+}
+
+impl DirDiff {
+    // only in the second directory, named as in it
+    pub fn created(&self) -> &HashSet<File> {
+        &self.created
+    }
+
+    // in both but different, the file of the second directory along with the one of the first
+    pub fn changed(&self) -> &HashSet<ChangedFile> {
+        &self.changed
+    }
+
+    // only in the first directory, named as in it
+    pub fn deleted(&self) -> &HashSet<File> {
+        &self.deleted
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.changed.is_empty() && self.deleted.is_empty()
+    }
+}
+
+// where an incremental scan resumes: directories left to read and entries left to examine
+#[derive(Debug, Default)]
+pub struct ScanCursor {
+    dirs: VecDeque<(PathBuf, u8)>,
+    entries: VecDeque<(DirEntry, u8)>,
+}
+
+// the timestamps of a file, None where unknown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileTimes {
+    pub created: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+    pub accessed: Option<SystemTime>,
+}
+
+// the state of a file as captured by a scan, see File::snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileSnapshot {
+    pub name: String,
+    pub size: u64,
+    pub created: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+    pub accessed: Option<SystemTime>,
+    //only known on unix
+    pub inode: Option<u64>,
+    pub is_dir: bool,
+}
+
+// identifies the file behind a path, a key for maps that follow files through renames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileId {
+    Inode { device: u64, inode: u64 },
+    //changes with a rename
+    Path(u64),
 }
 
 #[derive(Debug, Clone)]
@@ -34,7 +206,15 @@ pub struct File {
     name: String,
     last_modified: Option<SystemTime>,
     last_accessed: Option<SystemTime>,
-    created: SystemTime,
+    created: Option<SystemTime>,
+    size: u64,
+    inode: Option<u64>,
+    device: Option<u64>,
+    hardlinks: Vec<String>,
+    correlation_id: Option<u64>,
+    broken_symlink: bool,
+    symlink_target: Option<Box<Path>>,
+    is_dir: bool,
 }
 
 impl Eq for File {}
@@ -58,6 +238,24 @@ impl Display for File {
 }
 
 impl File {
+    // a file known from elsewhere than a scan, only its modification time and size are known
+    pub fn from_manifest(path: &Path, last_modified: SystemTime, size: u64) -> Self {
+        Self {
+            name: path.to_string_lossy().to_string(),
+            last_modified: Some(last_modified),
+            last_accessed: None,
+            created: None,
+            size,
+            inode: None,
+            device: None,
+            hardlinks: vec![],
+            correlation_id: None,
+            broken_symlink: false,
+            symlink_target: None,
+            is_dir: false,
+        }
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -70,11 +268,193 @@ impl File {
         self.last_accessed
     }
 
-    pub fn created(&self) -> SystemTime {
+    // None when unknown or in the future
+    pub fn modified_duration_ago(&self) -> Option<Duration> {
+        Self::duration_ago(self.last_modified)
+    }
+
+    // None when unknown or in the future
+    pub fn accessed_duration_ago(&self) -> Option<Duration> {
+        Self::duration_ago(self.last_accessed)
+    }
+
+    fn duration_ago(time: Option<SystemTime>) -> Option<Duration> {
+        SystemTime::now().duration_since(time?).ok()
+    }
+
+    // older first, a file with an unknown modification time being the oldest
+    pub fn cmp_by_modified(&self, other: &File) -> Ordering {
+        self.last_modified.cmp(&other.last_modified)
+    }
+
+    pub fn created(&self) -> Option<SystemTime> {
         self.created
     }
+
+    pub fn snapshot(&self) -> FileSnapshot {
+        FileSnapshot {
+            name: self.name.clone(),
+            size: self.size,
+            created: self.created,
+            modified: self.last_modified,
+            accessed: self.last_accessed,
+            inode: self.inode,
+            is_dir: self.is_dir,
+        }
+    }
+
+    pub fn times(&self) -> FileTimes {
+        FileTimes {
+            created: self.created,
+            modified: self.last_modified,
+            accessed: self.last_accessed,
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    // only known on unix
+    pub fn inode(&self) -> Option<u64> {
+        self.inode
+    }
+
+    // the same for a file across renames where its inode is known, a hash of its path otherwise
+    pub fn id(&self) -> FileId {
+        match self.device.zip(self.inode) {
+            Some((device, inode)) => FileId::Inode { device, inode },
+            None => {
+                let mut hasher = DefaultHasher::new();
+                self.name.hash(&mut hasher);
+                FileId::Path(hasher.finish())
+            }
+        }
+    }
+
+    // a directory, only scanned with Entries::DirsOnly or Entries::Both
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    // a symlink whose target does not exist, only scanned with report_broken_symlinks
+    pub fn is_broken_symlink(&self) -> bool {
+        self.broken_symlink
+    }
+
+    // where a symlink points to, only read with track_symlink_targets
+    pub fn symlink_target(&self) -> Option<&Path> {
+        self.symlink_target.as_deref()
+    }
+
+    // the other paths of the same inode when hardlinks are deduplicated, in name order
+    pub fn hardlinks(&self) -> &[String] {
+        &self.hardlinks
+    }
+
+    // shared by the files created and deleted in the same poll that could not be paired as renames
+    pub fn correlation_id(&self) -> Option<u64> {
+        self.correlation_id
+    }
+
+    pub(crate) fn set_correlation_id(&mut self, correlation_id: u64) {
+        self.correlation_id = Some(correlation_id);
+    }
+
+    pub(crate) fn set_hardlinks(&mut self, hardlinks: Vec<String>) {
+        self.hardlinks = hardlinks;
+    }
+
+    pub fn extension(&self) -> Option<&str> {
+        Path::new(&self.name).extension().and_then(|e| e.to_str())
+    }
+
+    pub fn file_name(&self) -> Option<&str> {
+        Path::new(&self.name).file_name().and_then(|n| n.to_str())
+    }
+
+    #[cfg(feature = "binary")]
+    pub(crate) fn to_fields(&self) -> FileFields {
+        let since_epoch = |time: Option<SystemTime>| {
+            let since = time?.duration_since(SystemTime::UNIX_EPOCH).ok()?;
+            Some((since.as_secs(), since.subsec_nanos()))
+        };
+
+        (
+            self.name.clone(),
+            since_epoch(self.last_modified),
+            since_epoch(self.last_accessed),
+            since_epoch(self.created),
+            self.size,
+            self.inode,
+            self.device,
+            self.hardlinks.clone(),
+            self.correlation_id,
+            (self.broken_symlink, self.is_dir),
+            self.symlink_target
+                .as_ref()
+                .map(|target| target.to_string_lossy().to_string()),
+        )
+    }
+
+    #[cfg(feature = "binary")]
+    pub(crate) fn from_fields(fields: FileFields) -> Self {
+        let (
+            name,
+            last_modified,
+            last_accessed,
+            created,
+            size,
+            inode,
+            device,
+            hardlinks,
+            correlation_id,
+            (broken_symlink, is_dir),
+            symlink_target,
+        ) = fields;
+        let time = |since: Option<(u64, u32)>| {
+            since.map(|(secs, nanos)| SystemTime::UNIX_EPOCH + Duration::new(secs, nanos))
+        };
+
+        Self {
+            name,
+            last_modified: time(last_modified),
+            last_accessed: time(last_accessed),
+            created: time(created),
+            size,
+            inode,
+            device,
+            hardlinks,
+            correlation_id,
+            broken_symlink,
+            symlink_target: symlink_target.map(|target| PathBuf::from(target).into_boxed_path()),
+            is_dir,
+        }
+    }
 }
 
+// the fields of a File in the order they are framed by OPERATION::to_bytes, timestamps as the
+// seconds and nanoseconds since the unix epoch. Whether it is a broken symlink and whether it is
+// a directory share a field, bincode encoding tuples of at most 11 fields
+#[cfg(feature = "binary")]
+pub(crate) type FileFields = (
+    String,
+    Option<(u64, u32)>,
+    Option<(u64, u32)>,
+    Option<(u64, u32)>,
+    u64,
+    Option<u64>,
+    Option<u64>,
+    Vec<String>,
+    Option<u64>,
+    (bool, bool),
+    Option<String>,
+);
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct RenamedFileEntry(String, String);
 impl RenamedFileEntry {
@@ -91,44 +471,354 @@ impl RenamedFileEntry {
     }
 }
 
+// a changed file along with its state before the change
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ChangedFile(File, File, Contents);
+
+// what was read of a changed file, kept as small as an Option so change events stay small
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Contents {
+    NotRead,
+    Read(Vec<u8>),
+    //larger than the content size limit
+    Skipped,
+}
+impl ChangedFile {
+    pub fn new(file: File, previous: File) -> Self {
+        Self(file, previous, Contents::NotRead)
+    }
+
+    pub(crate) fn with_contents(mut self, contents: Option<Vec<u8>>) -> Self {
+        self.2 = match contents {
+            Some(contents) => Contents::Read(contents),
+            None => Contents::NotRead,
+        };
+
+        self
+    }
+
+    pub(crate) fn with_content_skipped(mut self) -> Self {
+        self.2 = Contents::Skipped;
+
+        self
+    }
+
+    pub fn file(&self) -> &File {
+        &self.0
+    }
+
+    pub fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    pub fn previous(&self) -> &File {
+        &self.1
+    }
+
+    pub fn previous_size(&self) -> u64 {
+        self.1.size()
+    }
+
+    // read when the change was detected, None unless the watcher reads on change or when the file
+    // was larger than allowed
+    pub fn contents(&self) -> Option<&[u8]> {
+        match &self.2 {
+            Contents::Read(contents) => Some(contents),
+            _ => None,
+        }
+    }
+
+    // the file was larger than the watcher's content size limit, so its contents were not read
+    pub fn content_skipped(&self) -> bool {
+        self.2 == Contents::Skipped
+    }
+
+    // the first data written to a file that was empty, i.e. its writer has started
+    pub fn is_first_write(&self) -> bool {
+        self.1.is_empty() && !self.0.is_empty()
+    }
+
+    // where the appended data starts, None if the file shrank, kept its size or was replaced
+    pub fn append_offset(&self) -> Option<u64> {
+        let replaced = match (self.0.created(), self.1.created()) {
+            (Some(created), Some(previous)) => created != previous,
+            _ => false,
+        };
+
+        match !replaced && self.0.size() > self.1.size() {
+            true => Some(self.1.size()),
+            false => None,
+        }
+    }
+}
+
+impl EntryFilter {
+    // None when the file was deleted since it was listed, or its metadata could not be read even
+    // after the retries
+    fn metadata(&self, entry: &DirEntry) -> Option<Metadata> {
+        let mut backoff = self.metadata_retry.backoff;
+        let mut retries = 0;
+
+        loop {
+            match (self.read_metadata.0)(entry) {
+                Ok(meta) => return Some(meta),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => return None,
+                Err(_) if retries < self.metadata_retry.attempts => {
+                    retries += 1;
+                    thread::sleep(backoff);
+                    backoff = backoff.saturating_mul(2);
+                }
+                Err(error) => {
+                    if let Some(callback) = &self.on_metadata_error {
+                        callback.call((entry.path(), error));
+                    }
+                    return None;
+                }
+            }
+        }
+    }
+
+    // a directory deleted since it was listed is not unreadable
+    fn read_dir(&self, dir: &Path) -> Option<fs::ReadDir> {
+        match fs::read_dir(dir) {
+            Ok(read_dir) => Some(read_dir),
+            Err(error) => {
+                if error.kind() != io::ErrorKind::NotFound {
+                    self.unreadable_dirs
+                        .lock()
+                        .unwrap()
+                        .insert(dir.to_path_buf());
+                }
+                None
+            }
+        }
+    }
+
+    fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        self.excluded_paths.contains(path)
+            || self
+                .ignore_file
+                .as_ref()
+                .is_some_and(|ignore| ignore.is_ignored(path, is_dir))
+    }
+
+    fn is_match(&self, path_buf: &Path, file_type: &FileType) -> bool {
+        if file_type.is_symlink() && !self.report_broken_symlinks && is_broken_symlink(path_buf) {
+            return false;
+        }
+
+        self.matches(path_buf, file_type.is_dir(), file_type.is_file())
+            && (!file_type.is_file() || self.content_type_matches(path_buf))
+    }
+
+    // sniffing reads the file, it is only done once the cheap checks passed and again once the
+    // file was modified
+    fn content_type_matches(&self, path: &Path) -> bool {
+        let content_types = match &self.content_types {
+            Some(content_types) => content_types,
+            None => return true,
+        };
+
+        let meta = match fs::metadata(path) {
+            Ok(meta) => meta,
+            Err(_) => return false,
+        };
+        let version = (meta.len(), meta.modified().ok());
+
+        let mut cache = self.content_type_matches.lock().unwrap();
+        if let Some((cached_version, matched)) = cache.get(path) {
+            if *cached_version == version {
+                return *matched;
+            }
+        }
+
+        let matched =
+            content_type(path).is_some_and(|mime| content_types.iter().any(|t| t == mime));
+        cache.insert(path.to_path_buf(), (version, matched));
+
+        matched
+    }
+
+    // whether a matching entry is reported or only descended into
+    fn reports(&self, is_dir: bool) -> bool {
+        match is_dir {
+            true => self.entries.includes_dirs(),
+            false => self.entries.includes_files(),
+        }
+    }
+
+    fn matches(&self, path_buf: &Path, is_dir: bool, is_file: bool) -> bool {
+        if self.non_utf8 == NonUtf8::Skip && path_buf.to_str().is_none() {
+            return false;
+        }
+
+        if self.is_excluded(path_buf, is_dir) {
+            return false;
+        }
+
+        if !is_file {
+            return true;
+        }
+
+        if self.extensions.is_none() && self.file_names.is_none() {
+            return true;
+        }
+
+        //a file matches either one of the extensions or one of the names
+        //skipped paths are valid UTF-8 by now, only Lossy ever replaces anything
+        let extension_match = self.extensions.as_ref().is_some_and(|exts| {
+            let file_ext = path_buf.extension().map(OsStr::to_string_lossy);
+            file_ext.is_some_and(|extension| exts.contains(&extension.to_ascii_lowercase()))
+        });
+
+        let file_name = path_buf.file_name().map(OsStr::to_string_lossy);
+        let name_match = file_name.is_some_and(|name| {
+            self.file_names
+                .as_ref()
+                .is_some_and(|names| names.contains(name.as_ref()))
+                || self.name_patterns.iter().any(|p| p.is_match(&name))
+        });
+
+        extension_match || name_match
+    }
+}
+
+// a file name with bracket character classes, each class matching a single character out of
+// its ranges and literals, i.e. [0-9], [abc] or [a-fx]; translated to a regex
+#[derive(Debug, Clone)]
+struct NamePattern {
+    regex: Regex,
+}
+
+impl NamePattern {
+    // None for an unclosed or empty class or a range going backwards, i.e. [9-0]
+    fn parse(glob: &str) -> Option<Self> {
+        let mut pattern = String::from("^");
+        let mut chars = glob.chars();
+        while let Some(c) = chars.next() {
+            if c != '[' {
+                pattern.push_str(&regex::escape(&c.to_string()));
+                continue;
+            }
+
+            let mut class = String::new();
+            loop {
+                match chars.next()? {
+                    ']' => break,
+                    '-' => class.push('-'),
+                    c => class.push_str(&regex::escape(&c.to_string())),
+                }
+            }
+            if class.is_empty() {
+                return None;
+            }
+            pattern.push_str(&format!("[{}]", class));
+        }
+        pattern.push('$');
+
+        Regex::new(&pattern).ok().map(|regex| Self { regex })
+    }
+
+    fn is_match(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+}
+
 impl SearchDir {
     pub fn new(dir_path: PathBuf, depth: Option<u8>, filter: Option<String>) -> Self {
-        let path = Path::new(&dir_path);
-        if !path.exists() || !path.is_dir() {
-            panic!("Directory '{:?}' does not exist", dir_path.clone());
-        }
+        Self::new_with_filter_separators(dir_path, depth, filter, FILTER_SEPARATORS)
+    }
+
+    // whether new_with_filter_separators accepts the filter rather than panicking
+    pub(crate) fn is_valid_filter(filter: &str, filter_separators: &[char]) -> bool {
+        let entries: Vec<&str> = filter.split(filter_separators).collect();
+
+        filter.is_empty()
+            || entries.contains(&ALL_FILES_FILTER)
+            || entries.into_iter().all(Self::is_valid_filter_entry)
+    }
+
+    fn is_valid_filter_entry(entry: &str) -> bool {
+        VALID_FILTER_REGEX.is_match(entry)
+            && (!entry.contains('[') || NamePattern::parse(entry).is_some())
+    }
+
+    // the filter is split on the given separators instead of ';' and ','; panics where
+    // try_new_with_filter_separators errors
+    pub fn new_with_filter_separators(
+        dir_path: PathBuf,
+        depth: Option<u8>,
+        filter: Option<String>,
+        filter_separators: &[char],
+    ) -> Self {
+        Self::try_new_with_filter_separators(dir_path, depth, filter, filter_separators)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    // the directory must be readable and the filter valid, a directory removed meanwhile is an
+    // error rather than a panic
+    pub fn try_new_with_filter_separators(
+        dir_path: PathBuf,
+        depth: Option<u8>,
+        filter: Option<String>,
+        filter_separators: &[char],
+    ) -> Result<Self, WatcherError> {
+        Self::try_new_with_metadata_reader(
+            dir_path,
+            depth,
+            filter,
+            filter_separators,
+            DirEntry::metadata,
+        )
+    }
 
-        let mut file_names: Option<Vec<String>> = None;
-        let mut extensions: Option<Vec<String>> = None;
-        let mut include_all_files = false;
+    // reads the metadata of the listed entries with read_metadata instead of DirEntry::metadata
+    fn try_new_with_metadata_reader(
+        dir_path: PathBuf,
+        depth: Option<u8>,
+        filter: Option<String>,
+        filter_separators: &[char],
+        read_metadata: fn(&DirEntry) -> io::Result<Metadata>,
+    ) -> Result<Self, WatcherError> {
+        WatcherError::check(&dir_path)?;
+        WatcherError::check_filter(&dir_path, filter.as_deref(), filter_separators)?;
+        let meta = dir_path
+            .metadata()
+            .map_err(|_| WatcherError::NotFound(dir_path.clone()))?;
+
+        let mut file_names: Option<HashSet<String>> = None;
+        let mut extensions: Option<HashSet<String>> = None;
+        let mut name_patterns = vec![];
 
         if let Some(file) = filter {
             if !file.is_empty() {
                 let split_extensions: Vec<String> = file
-                    .split(FILTER_SEPARATORS)
+                    .split(filter_separators)
                     .map(|f| f.to_string())
                     .collect();
-                include_all_files = split_extensions
+                let include_all_files = split_extensions
                     .clone()
                     .into_iter()
                     .any(|e| e.eq(ALL_FILES_FILTER));
 
                 if !include_all_files {
-                    let filter_regex = Regex::new(VALID_FILTER_REGEX_PATH).unwrap();
-                    let mut exs: Vec<String> = vec![];
-                    let mut files: Vec<String> = vec![];
+                    let mut exs: HashSet<String> = HashSet::new();
+                    let mut files: HashSet<String> = HashSet::new();
 
                     for elem in split_extensions {
-                        if !filter_regex.is_match(elem.as_str()) {
-                            panic!("The filter should contain valid file extensions separated by {:?}! i.e: *.*, *.ext, file_name.ext", FILTER_SEPARATORS);
-                        }
+                        let pattern = match elem.contains('[') {
+                            true => NamePattern::parse(&elem),
+                            false => None,
+                        };
 
                         //if we have entry like *.ext
                         if elem.starts_with("*") {
                             let splits: Vec<&str> = elem.split(POINT_CHAR).collect();
-                            exs.push(splits[1].to_string());
+                            exs.insert(splits[1].to_ascii_lowercase());
+                        } else if let Some(pattern) = pattern {
+                            name_patterns.push(pattern);
                         } else {
-                            files.push(elem.to_string());
+                            files.insert(elem.to_string());
                         }
                     }
 
@@ -138,71 +828,526 @@ impl SearchDir {
             }
         }
 
-        Self {
+        let mut result = Self {
             dir_path: dir_path.clone(),
             depth,
-            meta: path.metadata().unwrap(),
-            extensions,
-            file_names,
-            include_all_files,
-            last_synced: None,
-        }
-    }
+            meta,
+            entry_filter: EntryFilter {
+                extensions,
+                file_names,
+                name_patterns,
+                read_metadata: MetadataReader(read_metadata),
+                ..Default::default()
+            },
+            dir_snapshot: BTreeMap::new(),
+            dedup_hardlinks: false,
+        };
+        result.dir_snapshot = result.get_dirs();
 
-    pub fn metadata(&self) -> &Metadata {
-        &self.meta
+        Ok(result)
     }
 
-    pub fn sync_metadata(&mut self) {
-        self.meta = fs::metadata(self.dir_path.as_path()).unwrap();
+    pub fn with_ignore_file(&mut self, path: PathBuf) -> &mut Self {
+        self.entry_filter.ignore_file = Some(IgnoreFile::load(&self.dir_path, path));
+        self.dir_snapshot = self.get_dirs();
+
+        self
     }
 
-    pub fn last_modified(&self) -> Result<SystemTime, io::Error> {
-        self.meta.modified()
+    // the path is never reported, whatever the filter says
+    pub fn with_excluded_path(&mut self, path: PathBuf) -> &mut Self {
+        self.entry_filter.excluded_paths.insert(path);
+        self.dir_snapshot = self.get_dirs();
+
+        self
     }
 
-    pub fn has_changed(&self) -> bool {
-        self.last_modified().unwrap()
-            != fs::metadata(self.dir_path.as_path())
-                .unwrap()
-                .modified()
-                .unwrap()
+    pub fn depth(&self) -> Option<u8> {
+        self.depth
     }
 
-    pub fn get_files(&self) -> HashSet<File> {
-        let mut result: HashSet<File> = HashSet::new();
+    // files now in or out of reach are found by the next scan
+    pub fn set_depth(&mut self, depth: Option<u8>) {
+        self.depth = depth;
+        self.dir_snapshot = self.get_dirs();
+    }
 
-        let rec_limit: u8 = match self.depth {
-            Some(value) => value,
-            _ => u8::MAX - 1,
-        };
+    // paths sharing an inode are reported as one file, not applied by scan_step
+    pub fn with_dedup_hardlinks(&mut self, dedup_hardlinks: bool) -> &mut Self {
+        self.dedup_hardlinks = dedup_hardlinks;
 
-        Self::get_files_internal(
-            &self.dir_path,
-            rec_limit + 1,
-            &self.extensions,
-            &self.file_names,
-            &mut result,
-        );
+        self
+    }
 
-        result
+    pub fn dedups_hardlinks(&self) -> bool {
+        self.dedup_hardlinks
     }
 
-    pub fn get_all_files(dir_path: &str) -> HashSet<File> {
-        let path = Self::validate_dir_path(dir_path);
+    // a failed metadata read is retried `attempts` times, waiting `backoff` and twice as long
+    // after each retry, before the file is skipped
+    pub fn with_metadata_retry(&mut self, attempts: u32, backoff: Duration) -> &mut Self {
+        self.entry_filter.metadata_retry = MetadataRetry { attempts, backoff };
 
-        let mut result: HashSet<File> = HashSet::new();
-        let rec_limit: u8 = u8::MAX - 1;
+        self
+    }
 
-        Self::get_files_internal(&path, rec_limit + 1, &None, &None, &mut result);
+    pub(crate) fn with_on_metadata_error(
+        &mut self,
+        callback: Callback<(PathBuf, io::Error)>,
+    ) -> &mut Self {
+        self.entry_filter.on_metadata_error = Some(callback);
 
-        result
+        self
     }
 
-    fn validate_dir_path(dir_path: &str) -> PathBuf {
-        if dir_path.is_empty() {
-            panic!("The directory path cannot be empty!")
-        }
+    // shares the directories the scans could not list, see unreadable_dirs
+    pub(crate) fn with_unreadable_dirs(
+        &mut self,
+        unreadable_dirs: Arc<Mutex<BTreeSet<PathBuf>>>,
+    ) -> &mut Self {
+        self.entry_filter.unreadable_dirs = unreadable_dirs;
+
+        self
+    }
+
+    // the directories the last scan could not list, their files are missing from it; an
+    // incremental scan lists the ones of its current pass
+    pub fn unreadable_dirs(&self) -> Vec<PathBuf> {
+        self.entry_filter
+            .unreadable_dirs
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    // a new scan starts over, the ones of other directories sharing the set are kept
+    // forgets what the previous scan found unreadable and the content types of the files it did
+    // not come across
+    fn start_scan(&self) {
+        self.entry_filter
+            .unreadable_dirs
+            .lock()
+            .unwrap()
+            .retain(|dir| !dir.starts_with(&self.dir_path));
+        self.entry_filter
+            .content_type_matches
+            .lock()
+            .unwrap()
+            .start_scan();
+    }
+
+    // symlinks whose target does not exist are reported instead of skipped, see
+    // File::is_broken_symlink
+    pub fn with_report_broken_symlinks(&mut self, report_broken_symlinks: bool) -> &mut Self {
+        self.entry_filter.report_broken_symlinks = report_broken_symlinks;
+
+        self
+    }
+
+    // only the files whose leading bytes are of one of the MIME types are scanned, on top of
+    // the filter
+    #[cfg(feature = "content-type")]
+    pub fn with_content_type_filter(&mut self, content_types: &[String]) -> &mut Self {
+        self.entry_filter.content_types = Some(content_types.to_vec());
+
+        self
+    }
+
+    // the target of every symlink is read, see File::symlink_target
+    pub fn with_track_symlink_targets(&mut self, track_symlink_targets: bool) -> &mut Self {
+        self.entry_filter.track_symlink_targets = track_symlink_targets;
+
+        self
+    }
+
+    pub fn with_non_utf8(&mut self, non_utf8: NonUtf8) -> &mut Self {
+        self.entry_filter.non_utf8 = non_utf8;
+
+        self
+    }
+
+    // which entries a scan reports, it descends into the directories either way
+    pub fn with_entries(&mut self, entries: Entries) -> &mut Self {
+        self.entry_filter.entries = entries;
+
+        self
+    }
+
+    pub fn reload_ignore_file(&mut self) -> bool {
+        match self.entry_filter.ignore_file.as_mut() {
+            Some(ignore_file) => ignore_file.reload_if_changed(),
+            None => false,
+        }
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.meta
+    }
+
+    pub fn sync_metadata(&mut self) {
+        //a removed root keeps its last metadata, the snapshot then lacks it
+        if let Ok(meta) = fs::metadata(self.dir_path.as_path()) {
+            self.meta = meta;
+        }
+        self.dir_snapshot = self.get_dirs();
+    }
+
+    pub fn last_modified(&self) -> Result<SystemTime, io::Error> {
+        self.meta.modified()
+    }
+
+    pub fn has_changed(&self) -> bool {
+        self.change_reason().is_some()
+    }
+
+    //the first directory (root first, then nested ones in path order) that differs from the last sync
+    pub fn change_reason(&self) -> Option<ChangeReason> {
+        let current = self.get_dirs();
+
+        for (dir, state) in current.iter() {
+            match self.dir_snapshot.get(dir) {
+                Some(old) if old.modified != state.modified => {
+                    return Some(ChangeReason::Modified(dir.clone()))
+                }
+                None => return Some(ChangeReason::Added(dir.clone())),
+                _ => {}
+            }
+        }
+
+        self.dir_snapshot
+            .keys()
+            .find(|dir| !current.contains_key(*dir))
+            .map(|dir| ChangeReason::Removed(dir.clone()))
+    }
+
+    // the device and inode of every watched directory as of the last sync_metadata, empty where
+    // inodes are unknown
+    pub fn dir_inodes(&self) -> HashMap<PathBuf, FileId> {
+        self.dir_snapshot
+            .iter()
+            .filter_map(|(dir, state)| Some((dir.clone(), state.id?)))
+            .collect()
+    }
+
+    // the file as it is now, read from its path without listing its directory; None once it is
+    // gone. Symlinks are not followed, as in a scan
+    pub fn stat(&self, file: &File) -> Option<File> {
+        let path = Path::new(file.name());
+        let meta = fs::symlink_metadata(path).ok()?;
+
+        Some(Self::file_of(
+            path,
+            &meta,
+            meta.file_type().is_symlink(),
+            &self.entry_filter,
+        ))
+    }
+
+    pub fn get_files(&self) -> HashSet<File> {
+        let mut result: HashSet<File> = HashSet::new();
+        self.start_scan();
+
+        Self::get_files_internal(
+            &self.dir_path,
+            self.recursion_limit(),
+            &self.entry_filter,
+            &mut result,
+        );
+
+        self.dedup_hardlinks(result)
+    }
+
+    // the summed size of the matched files, scans the directory
+    pub fn total_size(&self) -> u64 {
+        self.get_files().iter().map(File::size).sum()
+    }
+
+    // the paths of the files in the directory from its listings alone, without their metadata;
+    // the filter is applied but not the content type filter
+    pub fn get_names(&self) -> HashSet<String> {
+        let mut result: HashSet<String> = HashSet::new();
+
+        Self::get_names_internal(
+            &self.dir_path,
+            self.recursion_limit(),
+            &self.entry_filter,
+            &mut result,
+        );
+
+        result
+    }
+
+    // keeps the first path in name order of every inode, the others become its hardlinks
+    pub fn dedup_hardlinks(&self, files: HashSet<File>) -> HashSet<File> {
+        if !self.dedup_hardlinks {
+            return files;
+        }
+
+        let mut files: Vec<File> = files.into_iter().collect();
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut result: Vec<File> = vec![];
+        let mut by_inode: HashMap<(u64, u64), usize> = HashMap::new();
+        for mut file in files {
+            match file.device.zip(file.inode) {
+                Some(id) => match by_inode.get(&id) {
+                    Some(&index) => result[index].hardlinks.push(file.name),
+                    None => {
+                        //listed anew, whatever an earlier dedup listed
+                        file.hardlinks.clear();
+                        by_inode.insert(id, result.len());
+                        result.push(file);
+                    }
+                },
+                None => result.push(file),
+            }
+        }
+
+        result.into_iter().collect()
+    }
+
+    pub fn scan_cursor(&self) -> ScanCursor {
+        self.start_scan();
+
+        ScanCursor {
+            dirs: VecDeque::from([(self.dir_path.clone(), self.recursion_limit())]),
+            entries: VecDeque::new(),
+        }
+    }
+
+    // examines at most `budget` entries (directory reads included) starting from the cursor and
+    // adds the matching files to `result`, returns true once the whole tree has been traversed
+    pub fn scan_step(
+        &self,
+        cursor: &mut ScanCursor,
+        budget: usize,
+        result: &mut HashSet<File>,
+    ) -> bool {
+        let mut examined: usize = 0;
+
+        while examined < budget {
+            examined += 1;
+
+            let (entry, depth) = match cursor.entries.pop_front() {
+                Some(next) => next,
+                None => match cursor.dirs.pop_front() {
+                    Some((dir, depth)) => {
+                        if let Some(read_dir) = self.entry_filter.read_dir(&dir) {
+                            cursor
+                                .entries
+                                .extend(read_dir.flatten().map(|entry| (entry, depth)));
+                        }
+
+                        continue;
+                    }
+                    None => break,
+                },
+            };
+
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(_) => continue,
+            };
+
+            if !self.entry_filter.is_match(&entry.path(), &file_type) {
+                continue;
+            }
+
+            if self.entry_filter.reports(file_type.is_dir()) {
+                if let Some(file) = Self::to_file(&entry, &self.entry_filter) {
+                    result.insert(file);
+                }
+            }
+
+            if file_type.is_dir() && depth > 1 {
+                cursor.dirs.push_back((entry.path(), depth - 1));
+            }
+        }
+
+        cursor.entries.is_empty() && cursor.dirs.is_empty()
+    }
+
+    // compares the files of `b` to the ones of `a` at the same relative path, as if `a` changed
+    // into `b`; like rsync's quick check a file is changed when its size or modification time
+    // differs, i.e. NotifyFilters::DEFAULT. Renames are not paired. Fails when either tree
+    // cannot be listed.
+    pub fn diff_dirs(
+        a: &Path,
+        b: &Path,
+        filter: Option<String>,
+        depth: Option<u8>,
+    ) -> Result<DirDiff, WatcherError> {
+        let old_dir = Self::try_new_with_filter_separators(
+            a.to_path_buf(),
+            depth,
+            filter.clone(),
+            FILTER_SEPARATORS,
+        )?;
+        let new_dir = Self::try_new_with_filter_separators(
+            b.to_path_buf(),
+            depth,
+            filter,
+            FILTER_SEPARATORS,
+        )?;
+
+        //file names are lossy, a root that isn't valid utf-8 is stripped the same way
+        let relative = |root: &Path, files: HashSet<File>| -> HashMap<PathBuf, File> {
+            let root = PathBuf::from(root.to_string_lossy().to_string());
+            files
+                .into_iter()
+                .map(|f| {
+                    let name = Path::new(f.name());
+                    let path = name.strip_prefix(&root).unwrap_or(name).to_path_buf();
+                    (path, f)
+                })
+                .collect()
+        };
+        let mut old = relative(a, old_dir.get_files());
+        let new = relative(b, new_dir.get_files());
+
+        let mut result = DirDiff::default();
+        for (path, file) in new {
+            match old.remove(&path) {
+                Some(previous) => {
+                    if apply_notify_filters(&previous, &file, NotifyFilters::DEFAULT, false) {
+                        result.changed.insert(ChangedFile::new(file, previous));
+                    }
+                }
+                None => {
+                    result.created.insert(file);
+                }
+            }
+        }
+        result.deleted = old.into_values().collect();
+
+        Ok(result)
+    }
+
+    pub fn get_all_files(dir_path: &str) -> HashSet<File> {
+        let path = Self::validate_dir_path(dir_path);
+
+        let mut result: HashSet<File> = HashSet::new();
+        let rec_limit: u8 = u8::MAX - 1;
+
+        Self::get_files_internal(&path, rec_limit + 1, &EntryFilter::default(), &mut result);
+
+        result
+    }
+
+    // whether the path would be reported by a scan, without scanning; a path that does not exist
+    // is taken for a file
+    pub fn is_match(&self, path: &Path) -> bool {
+        let relative = match path.strip_prefix(&self.dir_path) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative,
+            _ => return false,
+        };
+
+        if relative.components().count() > usize::from(self.recursion_limit()) {
+            return false;
+        }
+
+        //the directories on the way are filtered as well
+        let excluded_parent = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| *dir != self.dir_path)
+            .any(|dir| self.entry_filter.is_excluded(dir, true));
+        if excluded_parent {
+            return false;
+        }
+
+        match fs::symlink_metadata(path) {
+            Ok(meta) => self.entry_filter.is_match(path, &meta.file_type()),
+            Err(_) => self.entry_filter.matches(path, false, true),
+        }
+    }
+
+    fn recursion_limit(&self) -> u8 {
+        match self.depth {
+            Some(value) => value.saturating_add(1),
+            _ => u8::MAX,
+        }
+    }
+
+    fn get_names_internal(
+        dir: &Path,
+        depth: u8,
+        entry_filter: &EntryFilter,
+        result: &mut HashSet<String>,
+    ) {
+        if depth == 0 {
+            return;
+        }
+
+        if let Ok(read_dir) = fs::read_dir(dir) {
+            for dir_entry in read_dir.flatten() {
+                let path = dir_entry.path();
+                let is_dir = dir_entry.file_type().is_ok_and(|t| t.is_dir());
+                if !entry_filter.matches(&path, is_dir, !is_dir) {
+                    continue;
+                }
+
+                if entry_filter.reports(is_dir) {
+                    result.insert(path.to_string_lossy().to_string());
+                }
+
+                if is_dir {
+                    Self::get_names_internal(&path, depth - 1, entry_filter, result);
+                }
+            }
+        }
+    }
+
+    fn get_dirs(&self) -> BTreeMap<PathBuf, DirState> {
+        let mut result: BTreeMap<PathBuf, DirState> = BTreeMap::new();
+
+        Self::get_dirs_internal(
+            &self.dir_path,
+            self.recursion_limit(),
+            &self.entry_filter,
+            &mut result,
+        );
+
+        result
+    }
+
+    fn get_dirs_internal(
+        dir: &Path,
+        depth: u8,
+        entry_filter: &EntryFilter,
+        result: &mut BTreeMap<PathBuf, DirState>,
+    ) {
+        if depth == 0 {
+            return;
+        }
+
+        if let Ok(meta) = fs::metadata(dir) {
+            if let Ok(modified) = meta.modified() {
+                let id = Self::device(&meta)
+                    .zip(Self::inode(&meta))
+                    .map(|(device, inode)| FileId::Inode { device, inode });
+                result.insert(dir.to_path_buf(), DirState { modified, id });
+            }
+        }
+
+        if let Ok(read_dir) = fs::read_dir(dir) {
+            for dir_entry in read_dir.flatten() {
+                let path = dir_entry.path();
+                if dir_entry.file_type().map(|t| t.is_dir()).unwrap_or(false)
+                    && !entry_filter.is_excluded(&path, true)
+                {
+                    Self::get_dirs_internal(&path, depth - 1, entry_filter, result);
+                }
+            }
+        }
+    }
+
+    fn validate_dir_path(dir_path: &str) -> PathBuf {
+        if dir_path.is_empty() {
+            panic!("The directory path cannot be empty!")
+        }
 
         let path = Path::new(dir_path);
         if !path.exists() || !path.is_dir() {
@@ -213,60 +1358,818 @@ impl SearchDir {
     }
 
     fn get_files_internal(
-        dir: &PathBuf,
+        dir: &Path,
         depth: u8,
-        extensions: &Option<Vec<String>>,
-        file_names: &Option<Vec<String>>,
+        entry_filter: &EntryFilter,
         result: &mut HashSet<File>,
     ) {
         if depth == 0 {
             return;
         }
 
-        if let Ok(read_dir) = fs::read_dir(dir) {
-            for dir_entry in read_dir.filter(|f| {
-                let entry = f.as_ref().unwrap();
-                let path_buf = entry.path().clone();
-                let file_type = entry.file_type().unwrap();
+        if let Some(read_dir) = entry_filter.read_dir(dir) {
+            //an entry that cannot be read is skipped, not the whole scan
+            for entry in read_dir.flatten() {
+                let file_type = match entry.file_type() {
+                    Ok(file_type) => file_type,
+                    Err(_) => continue,
+                };
 
-                if !file_type.is_file() {
-                    return true;
+                if !entry_filter.is_match(&entry.path(), &file_type) {
+                    continue;
                 }
 
-                if let Some(exts) = extensions {
-                    let file_ext = path_buf.as_path().extension().and_then(OsStr::to_str);
-                    if let Some(extension) = file_ext {
-                        return exts.iter().any(|e| e.contains(extension));
+                if entry_filter.reports(file_type.is_dir()) {
+                    if let Some(file) = Self::to_file(&entry, entry_filter) {
+                        result.insert(file);
                     }
                 }
 
-                if let Some(names) = file_names {
-                    let file_name = path_buf.file_name().unwrap().to_str().unwrap().to_string();
-                    return names.contains(&file_name);
+                if file_type.is_dir() {
+                    Self::get_files_internal(&entry.path(), depth - 1, entry_filter, result);
                 }
+            }
+        }
+    }
 
-                true
-            }) {
-                let file = dir_entry.unwrap();
-
-                if file.file_type().unwrap().is_dir() {
-                    Self::get_files_internal(
-                        &file.path(),
-                        depth - 1,
-                        extensions,
-                        file_names,
-                        result,
-                    );
-                } else {
-                    let meta = file.metadata().unwrap();
-                    result.insert(File {
-                        name: String::from(file.path().to_str().unwrap()),
-                        created: meta.created().unwrap(),
-                        last_modified: Some(meta.modified().unwrap()),
-                        last_accessed: Some(meta.accessed().unwrap()),
-                    });
-                }
+    #[cfg(unix)]
+    fn inode(meta: &Metadata) -> Option<u64> {
+        Some(std::os::unix::fs::MetadataExt::ino(meta))
+    }
+
+    #[cfg(not(unix))]
+    fn inode(_meta: &Metadata) -> Option<u64> {
+        None
+    }
+
+    #[cfg(unix)]
+    fn device(meta: &Metadata) -> Option<u64> {
+        Some(std::os::unix::fs::MetadataExt::dev(meta))
+    }
+
+    #[cfg(not(unix))]
+    fn device(_meta: &Metadata) -> Option<u64> {
+        None
+    }
+
+    // None when the entry vanished before its metadata could be read
+    fn to_file(entry: &DirEntry, entry_filter: &EntryFilter) -> Option<File> {
+        let meta = entry_filter.metadata(entry)?;
+        let is_symlink = entry.file_type().is_ok_and(|t| t.is_symlink());
+
+        Some(Self::file_of(
+            &entry.path(),
+            &meta,
+            is_symlink,
+            entry_filter,
+        ))
+    }
+
+    fn file_of(path: &Path, meta: &Metadata, is_symlink: bool, entry_filter: &EntryFilter) -> File {
+        File {
+            name: path.to_string_lossy().to_string(),
+            created: meta.created().ok(),
+            last_modified: meta.modified().ok(),
+            last_accessed: meta.accessed().ok(),
+            size: meta.len(),
+            inode: Self::inode(meta),
+            device: Self::device(meta),
+            hardlinks: vec![],
+            correlation_id: None,
+            broken_symlink: is_symlink && is_broken_symlink(path),
+            symlink_target: match is_symlink && entry_filter.track_symlink_targets {
+                true => fs::read_link(path).ok().map(PathBuf::into_boxed_path),
+                false => None,
+            },
+            is_dir: meta.is_dir(),
+        }
+    }
+}
+
+// the notify filters decide which differences between two snapshots of a file are a change
+pub(crate) fn apply_notify_filters(
+    old: &File,
+    new: &File,
+    notify_filters: NotifyFilters,
+    ignore_atime_only: bool,
+) -> bool {
+    let last_write = notify_filters.contains(NotifyFilters::LastWrite)
+        && old.last_modified() != new.last_modified();
+
+    if last_write {
+        return last_write;
+    }
+
+    //reading a file updates its access time, an access without a write may not count
+    let last_access = notify_filters.contains(NotifyFilters::LastAccess)
+        && known_and_different(old.last_accessed(), new.last_accessed())
+        && !(ignore_atime_only && old.last_modified() == new.last_modified());
+
+    if last_access {
+        return last_access;
+    }
+
+    let creation_time = notify_filters.contains(NotifyFilters::CreationTime)
+        && known_and_different(old.created(), new.created());
+
+    if creation_time {
+        return creation_time;
+    }
+
+    notify_filters.contains(NotifyFilters::Size) && old.size() != new.size()
+}
+
+// files from a manifest lack some timestamps, a missing value is not a change
+fn known_and_different(old: Option<SystemTime>, new: Option<SystemTime>) -> bool {
+    match (old, new) {
+        (Some(old), Some(new)) => old != new,
+        _ => false,
+    }
+}
+
+// the MIME type told by the leading bytes of the file
+#[cfg(feature = "content-type")]
+fn content_type(path: &Path) -> Option<&'static str> {
+    infer::get_from_path(path)
+        .ok()
+        .flatten()
+        .map(|kind| kind.mime_type())
+}
+
+#[cfg(not(feature = "content-type"))]
+fn content_type(_path: &Path) -> Option<&'static str> {
+    None
+}
+
+// DirEntry::metadata does not follow symlinks, fs::metadata does and fails on a missing target
+fn is_broken_symlink(path: &Path) -> bool {
+    fs::metadata(path).is_err()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rwatcher_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn change_reason_names_nested_directory() {
+        let root = test_dir("change_reason");
+        let nested = root.join("level1").join("level2");
+        fs::create_dir_all(&nested).unwrap();
+
+        let mut search_dir = SearchDir::new(root.clone(), None, None);
+        search_dir.sync_metadata();
+        assert_eq!(search_dir.change_reason(), None);
+
+        thread::sleep(Duration::from_millis(20));
+        fs::write(nested.join("file.txt"), "content").unwrap();
+
+        assert!(search_dir.has_changed());
+        assert_eq!(
+            search_dir.change_reason(),
+            Some(ChangeReason::Modified(nested.clone()))
+        );
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dir_inodes_are_those_of_the_last_sync() {
+        let root = test_dir("dir_inodes");
+        let before = root.join("before");
+        fs::create_dir_all(&before).unwrap();
+
+        let mut search_dir = SearchDir::new(root.clone(), None, None);
+        search_dir.sync_metadata();
+        let dir_inodes = search_dir.dir_inodes();
+        let id = dir_inodes[&before];
+        assert!(matches!(id, FileId::Inode { .. }));
+
+        //not read again until the next sync
+        let after = root.join("after");
+        fs::rename(&before, &after).unwrap();
+        assert_eq!(search_dir.dir_inodes(), dir_inodes);
+
+        search_dir.sync_metadata();
+        let dir_inodes = search_dir.dir_inodes();
+        assert_eq!(dir_inodes.get(&after), Some(&id));
+        assert!(!dir_inodes.contains_key(&before));
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    static METADATA_READS: AtomicUsize = AtomicUsize::new(0);
+
+    //fails the first two reads like a busy filesystem would
+    fn flaky_metadata(entry: &DirEntry) -> io::Result<Metadata> {
+        match METADATA_READS.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => Err(io::Error::other("resource busy")),
+            _ => entry.metadata(),
+        }
+    }
+
+    #[test]
+    fn failed_metadata_reads_are_retried() {
+        let dir = test_dir("metadata_retry");
+        fs::write(dir.join("a.txt"), "one").unwrap();
+
+        let failed: Arc<Mutex<Vec<PathBuf>>> = Arc::new(Mutex::new(vec![]));
+        let reported = Arc::clone(&failed);
+        let mut search_dir = SearchDir::try_new_with_metadata_reader(
+            dir.clone(),
+            None,
+            None,
+            FILTER_SEPARATORS,
+            flaky_metadata,
+        )
+        .unwrap();
+        search_dir.with_on_metadata_error(Callback::new(Box::new(move |(path, _)| {
+            reported.lock().unwrap().push(path);
+        })));
+
+        search_dir.with_metadata_retry(2, Duration::from_millis(1));
+        assert_eq!(search_dir.get_files().len(), 1);
+        assert_eq!(METADATA_READS.load(Ordering::SeqCst), 3);
+        assert!(failed.lock().unwrap().is_empty());
+
+        //one retry is not enough, the file is skipped and the error reported
+        METADATA_READS.store(0, Ordering::SeqCst);
+        search_dir.with_metadata_retry(1, Duration::from_millis(1));
+        assert!(search_dir.get_files().is_empty());
+        assert_eq!(*failed.lock().unwrap(), vec![dir.join("a.txt")]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn ignore_file_excludes_subdirectory() {
+        let root = test_dir("ignore_file");
+        let app = root.join("build").join("out").join("app.txt");
+        let main = root.join("src").join("main.txt");
+        fs::create_dir_all(app.parent().unwrap()).unwrap();
+        fs::create_dir_all(main.parent().unwrap()).unwrap();
+        fs::write(&app, "").unwrap();
+        fs::write(&main, "").unwrap();
+        fs::write(root.join(".watchignore"), "# generated\nbuild/\n").unwrap();
+
+        let mut search_dir = SearchDir::new(root.clone(), None, Some("*.txt".to_string()));
+        search_dir.with_ignore_file(root.join(".watchignore"));
+
+        let files: Vec<PathBuf> = search_dir
+            .get_files()
+            .iter()
+            .map(|f| f.name().into())
+            .collect();
+        assert_eq!(files, vec![main.clone()]);
+        assert!(!search_dir.reload_ignore_file());
+
+        thread::sleep(Duration::from_millis(20));
+        fs::write(root.join(".watchignore"), "src/main.txt\n").unwrap();
+
+        assert!(search_dir.reload_ignore_file());
+        let files: Vec<PathBuf> = search_dir
+            .get_files()
+            .iter()
+            .map(|f| f.name().into())
+            .collect();
+        assert_eq!(files, vec![app]);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn scan_step_examines_at_most_budget_entries() {
+        let root = test_dir("scan_step");
+        for d in 0..5 {
+            let dir = root.join(format!("dir{}", d));
+            fs::create_dir_all(&dir).unwrap();
+            for f in 0..20 {
+                fs::write(dir.join(format!("file{}.txt", f)), "").unwrap();
             }
         }
+
+        let search_dir = SearchDir::new(root.clone(), None, None);
+        let mut cursor = search_dir.scan_cursor();
+        let mut files: HashSet<File> = HashSet::new();
+
+        // 1 root read + 5 directories + 5 directory reads + 100 files
+        let mut steps = 0;
+        while !search_dir.scan_step(&mut cursor, 10, &mut files) {
+            steps += 1;
+            assert!(files.len() <= steps * 10);
+        }
+
+        assert_eq!(steps + 1, 12);
+        assert_eq!(files, search_dir.get_files());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn file_times_group_the_individual_timestamps() {
+        let dir = test_dir("file_times");
+        fs::write(dir.join("a.txt"), "one").unwrap();
+
+        let files = SearchDir::new(dir.clone(), None, None).get_files();
+        let file = files.iter().next().unwrap();
+        let times = file.times();
+
+        assert_eq!(times.created, file.created());
+        assert_eq!(times.modified, file.last_modified());
+        assert_eq!(times.accessed, file.last_accessed());
+        assert!(times.modified.is_some());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_names_are_skipped_or_matched_lossily() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = test_dir("non_utf8");
+        fs::File::create(dir.join("valid.txt")).unwrap();
+        fs::File::create(dir.join(OsStr::from_bytes(b"invalid\xff.txt"))).unwrap();
+
+        let names = |non_utf8: NonUtf8| {
+            let mut search_dir = SearchDir::new(dir.clone(), None, Some("*.txt".to_string()));
+            search_dir.with_non_utf8(non_utf8);
+
+            let mut names: Vec<String> = search_dir
+                .get_files()
+                .iter()
+                .map(|f| f.file_name().unwrap().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(names(NonUtf8::Skip), vec!["valid.txt"]);
+        assert_eq!(
+            names(NonUtf8::Lossy),
+            vec!["invalid\u{FFFD}.txt", "valid.txt"]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn snapshot_mirrors_the_accessors() {
+        let dir = test_dir("snapshot");
+        fs::write(dir.join("file.txt"), "content").unwrap();
+
+        let search_dir = SearchDir::new(dir.clone(), None, None);
+        let file = search_dir.get_files().into_iter().next().unwrap();
+        let snapshot = file.snapshot();
+
+        assert_eq!(snapshot.name, file.name());
+        assert_eq!(snapshot.size, file.size());
+        assert_eq!(snapshot.created, file.created());
+        assert_eq!(snapshot.modified, file.last_modified());
+        assert_eq!(snapshot.accessed, file.last_accessed());
+        assert_eq!(snapshot.inode, file.inode());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_id_survives_a_rename() {
+        let dir = test_dir("file_id");
+        fs::write(dir.join("old.txt"), "one").unwrap();
+        fs::write(dir.join("other.txt"), "two").unwrap();
+
+        let search_dir = SearchDir::new(dir.clone(), None, None);
+        let id_of = |name: &str| {
+            let path = dir.join(name);
+            let files = search_dir.get_files();
+            files
+                .iter()
+                .find(|f| Path::new(f.name()) == path)
+                .map(File::id)
+                .unwrap()
+        };
+
+        let before = id_of("old.txt");
+        assert!(matches!(before, FileId::Inode { .. }));
+        assert_ne!(before, id_of("other.txt"));
+
+        fs::rename(dir.join("old.txt"), dir.join("new.txt")).unwrap();
+        assert_eq!(id_of("new.txt"), before);
+
+        //without an inode the path is all there is
+        let manifest = File::from_manifest(&dir.join("new.txt"), SystemTime::now(), 3);
+        assert!(matches!(manifest.id(), FileId::Path(_)));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn file_extension_and_file_name_parse_the_path() {
+        let file = |name: &str| File {
+            name: name.to_string(),
+            last_modified: None,
+            last_accessed: None,
+            created: None,
+            size: 0,
+            inode: None,
+            device: None,
+            hardlinks: vec![],
+            correlation_id: None,
+            broken_symlink: false,
+            symlink_target: None,
+            is_dir: false,
+        };
+
+        let plain = file("/logs/app.log");
+        assert_eq!(plain.extension(), Some("log"));
+        assert_eq!(plain.file_name(), Some("app.log"));
+
+        let multi_dot = file("/backups/db.dump.tar.gz");
+        assert_eq!(multi_dot.extension(), Some("gz"));
+        assert_eq!(multi_dot.file_name(), Some("db.dump.tar.gz"));
+
+        let extensionless = file("/src/Makefile");
+        assert_eq!(extensionless.extension(), None);
+        assert_eq!(extensionless.file_name(), Some("Makefile"));
+
+        let hidden = file("/home/.bashrc");
+        assert_eq!(hidden.extension(), None);
+        assert_eq!(hidden.file_name(), Some(".bashrc"));
+
+        let dir_like = file("/data/reports/");
+        assert_eq!(dir_like.extension(), None);
+        assert_eq!(dir_like.file_name(), Some("reports"));
+
+        let root = file("/");
+        assert_eq!(root.extension(), None);
+        assert_eq!(root.file_name(), None);
+    }
+
+    #[test]
+    fn filter_validation_regex_is_shared_by_every_thread() {
+        //the regex compiled by whichever thread gets there first validates for all of them
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                thread::spawn(|| {
+                    for _ in 0..50 {
+                        assert!(SearchDir::is_valid_filter(
+                            "*.txt;*.log;notes.md",
+                            FILTER_SEPARATORS
+                        ));
+                        assert!(!SearchDir::is_valid_filter(
+                            "not a filter!",
+                            FILTER_SEPARATORS
+                        ));
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn duration_ago_of_a_fresh_file_is_small() {
+        let dir = test_dir("duration_ago");
+        fs::write(dir.join("fresh.txt"), "one").unwrap();
+
+        let entry = fs::read_dir(&dir).unwrap().next().unwrap().unwrap();
+        let file = SearchDir::to_file(&entry, &EntryFilter::default()).unwrap();
+
+        assert!(file.modified_duration_ago().unwrap() < Duration::from_secs(5));
+        assert!(file.accessed_duration_ago().unwrap() < Duration::from_secs(5));
+
+        let future = File::from_manifest(
+            &dir.join("future.txt"),
+            SystemTime::now() + Duration::from_secs(3600),
+            0,
+        );
+        assert_eq!(future.modified_duration_ago(), None);
+        assert_eq!(future.accessed_duration_ago(), None);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn filter_separators_only_change_how_the_filter_is_split() {
+        let dir = test_dir("filter_separators");
+
+        let parsed = |filter: &str, separators: &[char]| {
+            let search_dir = SearchDir::new_with_filter_separators(
+                dir.clone(),
+                None,
+                Some(filter.to_string()),
+                separators,
+            );
+            search_dir.entry_filter.extensions
+        };
+
+        let expected = parsed("*.txt;*.log,*.md", FILTER_SEPARATORS);
+        assert_eq!(
+            expected,
+            Some(HashSet::from([
+                "txt".to_string(),
+                "log".to_string(),
+                "md".to_string()
+            ]))
+        );
+        assert_eq!(parsed("*.txt *.log *.md", &[' ']), expected);
+        assert_eq!(parsed("*.txt|*.log|*.md", &['|']), expected);
+        assert_eq!(parsed("*.txt|*.log *.md", &['|', ' ']), expected);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn diff_dirs_compares_the_files_at_the_same_relative_path() {
+        let (source, backup) = (test_dir("diff_source"), test_dir("diff_backup"));
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        for dir in [&source, &backup] {
+            fs::create_dir_all(dir.join("nested")).unwrap();
+            let same = fs::File::create(dir.join("same.txt")).unwrap();
+            same.set_modified(modified).unwrap();
+            fs::write(dir.join("nested").join("changed.txt"), "one").unwrap();
+        }
+        fs::write(source.join("nested").join("changed.txt"), "longer").unwrap();
+        fs::write(source.join("only_in_source.txt"), "").unwrap();
+        fs::write(backup.join("nested").join("only_in_backup.txt"), "").unwrap();
+        fs::write(backup.join("only_in_backup.log"), "").unwrap();
+
+        let names = |files: Vec<&str>| -> Vec<String> {
+            let mut names: Vec<String> = files.into_iter().map(str::to_string).collect();
+            names.sort();
+            names
+        };
+
+        let diff = SearchDir::diff_dirs(&source, &backup, Some("*.txt".to_string()), None).unwrap();
+        assert_eq!(
+            names(diff.created().iter().map(File::name).collect()),
+            vec![backup
+                .join("nested")
+                .join("only_in_backup.txt")
+                .to_string_lossy()
+                .to_string()]
+        );
+        assert_eq!(
+            names(diff.changed().iter().map(ChangedFile::name).collect()),
+            vec![backup
+                .join("nested")
+                .join("changed.txt")
+                .to_string_lossy()
+                .to_string()]
+        );
+        assert_eq!(
+            names(diff.deleted().iter().map(File::name).collect()),
+            vec![source
+                .join("only_in_source.txt")
+                .to_string_lossy()
+                .to_string()]
+        );
+
+        //a tree compared to itself is the same
+        assert!(SearchDir::diff_dirs(&source, &source, None, None)
+            .unwrap()
+            .is_empty());
+
+        //a missing tree is an error rather than a panic
+        let missing = source.join("missing");
+        assert!(matches!(
+            SearchDir::diff_dirs(&source, &missing, None, None),
+            Err(WatcherError::NotFound(path)) if path == missing
+        ));
+
+        //so is an invalid filter
+        assert!(matches!(
+            SearchDir::diff_dirs(&source, &backup, Some("not a filter!".to_string()), None),
+            Err(WatcherError::InvalidFilter(path, _)) if path == source
+        ));
+
+        let _ = fs::remove_dir_all(&source);
+        let _ = fs::remove_dir_all(&backup);
+    }
+
+    #[test]
+    fn long_filters_match_exact_extensions_and_names() {
+        let dir = test_dir("long_filter");
+        for i in 0..2000 {
+            let nested = dir.join(format!("dir{}", i % 20));
+            fs::create_dir_all(&nested).unwrap();
+            fs::File::create(nested.join(format!("file{}.ext{}", i, i % 1000))).unwrap();
+        }
+        fs::File::create(dir.join("named.txt")).unwrap();
+
+        //500 extensions, every other one of the files, and a name
+        let mut filter: Vec<String> = (0..500).map(|i| format!("*.ext{}", i * 2)).collect();
+        filter.push("named.txt".to_string());
+        let search_dir = SearchDir::new(dir.clone(), None, Some(filter.join(";")));
+
+        let files = search_dir.get_files();
+        assert_eq!(files.len(), 1001);
+        assert!(files.iter().any(|f| f.name().ends_with("named.txt")));
+
+        //an extension matches whole, not as a part of a longer one
+        assert!(search_dir.is_match(&dir.join("file.ext10")));
+        assert!(!search_dir.is_match(&dir.join("file.ext")));
+        assert!(!search_dir.is_match(&dir.join("file.ext10x")));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn extensions_match_exactly_ignoring_case() {
+        let dir = test_dir("extension_case");
+        for name in ["report.txt", "report.txt.bak", "index.ts", "INDEX.TXT"] {
+            fs::File::create(dir.join(name)).unwrap();
+        }
+
+        let search_dir = SearchDir::new(dir.clone(), None, Some(String::from("*.txt;*.TS")));
+        assert!(search_dir.is_match(&dir.join("report.txt")));
+        assert!(!search_dir.is_match(&dir.join("report.txt.bak")));
+        assert!(search_dir.is_match(&dir.join("index.ts")));
+        assert!(search_dir.is_match(&dir.join("INDEX.TXT")));
+        assert_eq!(search_dir.get_files().len(), 3);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn most_recent_is_the_latest_modified_file() {
+        let at = |secs: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let changed = |name: &str, modified: Option<SystemTime>| {
+            let mut file = File::from_manifest(Path::new(name), at(0), 1);
+            file.last_modified = modified;
+            ChangedFile::new(file.clone(), file)
+        };
+
+        let args = crate::events::OnChangedEventArgs::new(HashSet::from([
+            changed("a.txt", Some(at(200))),
+            changed("b.txt", Some(at(300))),
+            changed("c.txt", None),
+            changed("d.txt", Some(at(100))),
+        ]));
+        assert_eq!(args.most_recent().map(File::name), Some("b.txt"));
+
+        //an unknown modification time is older than any known one
+        let args = crate::events::OnChangedEventArgs::new(HashSet::from([
+            changed("a.txt", None),
+            changed("b.txt", Some(at(0))),
+        ]));
+        assert_eq!(args.most_recent().map(File::name), Some("b.txt"));
+        assert_eq!(
+            crate::events::OnChangedEventArgs::new(HashSet::new()).most_recent(),
+            None
+        );
+    }
+
+    #[test]
+    fn character_classes_match_a_single_character_of_the_name() {
+        let dir = test_dir("character_classes");
+        for name in ["chunk3.bin", "chunkA.bin", "chunk12.bin"] {
+            fs::File::create(dir.join(name)).unwrap();
+        }
+
+        let search_dir = SearchDir::new(dir.clone(), None, Some(String::from("chunk[0-9].bin")));
+        assert!(search_dir.is_match(&dir.join("chunk3.bin")));
+        assert!(!search_dir.is_match(&dir.join("chunkA.bin")));
+        assert!(!search_dir.is_match(&dir.join("chunk12.bin")));
+        assert_eq!(search_dir.get_files().len(), 1);
+
+        //a range and a literal in the same class
+        let filter = String::from("chunk[A-Cx].bin");
+        let search_dir = SearchDir::new(dir.clone(), None, Some(filter));
+        assert!(search_dir.is_match(&dir.join("chunkA.bin")));
+        assert!(search_dir.is_match(&dir.join("chunkx.bin")));
+        assert!(!search_dir.is_match(&dir.join("chunkD.bin")));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    #[should_panic]
+    fn character_classes_going_backwards_are_rejected() {
+        let dir = test_dir("backwards_class");
+        SearchDir::new(dir, None, Some(String::from("chunk[9-0].bin")));
+    }
+
+    #[test]
+    fn try_new_errors_on_a_missing_directory_or_an_invalid_filter() {
+        let dir = test_dir("try_new_search_dir");
+        let missing = dir.join("missing");
+
+        assert!(matches!(
+            SearchDir::try_new_with_filter_separators(missing.clone(), None, None, FILTER_SEPARATORS),
+            Err(WatcherError::NotFound(path)) if path == missing
+        ));
+        assert!(matches!(
+            SearchDir::try_new_with_filter_separators(
+                dir.clone(),
+                None,
+                Some(String::from("chunk[9-0].bin")),
+                FILTER_SEPARATORS
+            ),
+            Err(WatcherError::InvalidFilter(..))
+        ));
+        assert!(SearchDir::try_new_with_filter_separators(
+            dir.clone(),
+            None,
+            Some(String::from("*.txt")),
+            FILTER_SEPARATORS
+        )
+        .is_ok());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "content-type")]
+    #[test]
+    fn content_type_filter_matches_by_the_leading_bytes() {
+        let dir = test_dir("content_type");
+        let png = [
+            0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0x0D,
+        ];
+        fs::write(dir.join("image.dat"), png).unwrap();
+        fs::write(dir.join("notes.dat"), "not an image").unwrap();
+
+        let mut search_dir = SearchDir::new(dir.clone(), None, Some("*.dat".to_string()));
+        search_dir.with_content_type_filter(&["image/png".to_string()]);
+
+        let names = |files: HashSet<File>| -> Vec<String> {
+            files.iter().map(|f| f.name.clone()).collect()
+        };
+        let image = dir.join("image.dat").to_str().unwrap().to_string();
+        assert_eq!(names(search_dir.get_files()), [image]);
+        assert!(search_dir.is_match(&dir.join("image.dat")));
+        assert!(!search_dir.is_match(&dir.join("notes.dat")));
+
+        //the cached result is dropped once the file changes
+        fs::write(dir.join("notes.dat"), png).unwrap();
+        assert_eq!(search_dir.get_files().len(), 2);
+
+        //and forgotten once a whole scan went by without the file
+        fs::remove_file(dir.join("notes.dat")).unwrap();
+        assert_eq!(search_dir.get_files().len(), 1);
+        assert_eq!(search_dir.get_files().len(), 1);
+        let cache = search_dir.entry_filter.content_type_matches.lock().unwrap();
+        assert_eq!(cache.len(), 1);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn is_match_applies_the_filter_without_scanning() {
+        let dir = test_dir("is_match");
+        fs::create_dir_all(dir.join("build")).unwrap();
+        fs::write(dir.join(".watchignore"), "build/\n*.tmp\n").unwrap();
+
+        //extension
+        let search_dir = SearchDir::new(dir.clone(), None, Some(String::from("*.txt;*.log")));
+        assert!(search_dir.is_match(&dir.join("a.txt")));
+        assert!(search_dir.is_match(&dir.join("nested").join("b.log")));
+        assert!(!search_dir.is_match(&dir.join("a.pdf")));
+        assert!(!search_dir.is_match(Path::new("/elsewhere/a.txt")));
+        assert!(!search_dir.is_match(&dir));
+
+        //exact name, alone and along with extensions
+        let search_dir = SearchDir::new(dir.clone(), None, Some(String::from("notes.md")));
+        assert!(search_dir.is_match(&dir.join("notes.md")));
+        assert!(search_dir.is_match(&dir.join("nested").join("notes.md")));
+        assert!(!search_dir.is_match(&dir.join("other.md")));
+
+        let search_dir = SearchDir::new(dir.clone(), None, Some(String::from("*.txt;notes.md")));
+        assert!(search_dir.is_match(&dir.join("a.txt")));
+        assert!(search_dir.is_match(&dir.join("notes.md")));
+        assert!(!search_dir.is_match(&dir.join("other.md")));
+
+        //every file
+        let search_dir = SearchDir::new(dir.clone(), Some(1), Some(String::from("*.*")));
+        assert!(search_dir.is_match(&dir.join("a.pdf")));
+        assert!(search_dir.is_match(&dir.join("sub").join("a.pdf")));
+        assert!(!search_dir.is_match(&dir.join("sub").join("deeper").join("a.pdf")));
+
+        //ignore file globs
+        let mut search_dir = SearchDir::new(dir.clone(), None, None);
+        search_dir.with_ignore_file(dir.join(".watchignore"));
+        assert!(search_dir.is_match(&dir.join("a.log")));
+        assert!(!search_dir.is_match(&dir.join("a.tmp")));
+        assert!(!search_dir.is_match(&dir.join("sub").join("a.tmp")));
+        assert!(!search_dir.is_match(&dir.join("build")));
+        assert!(!search_dir.is_match(&dir.join("build").join("out.log")));
+
+        let _ = fs::remove_dir_all(&dir);
     }
 }
@@ -0,0 +1,139 @@
+use std::time::Duration;
+
+// how many of the latest scans the percentiles are computed over
+const SCAN_DURATION_SAMPLES: usize = 128;
+
+// the durations of the latest scans, kept in a fixed size ring buffer
+#[derive(Debug, Clone, Copy)]
+pub struct ScanDurations {
+    samples: [Duration; SCAN_DURATION_SAMPLES],
+    len: usize,
+    next: usize,
+}
+
+impl Default for ScanDurations {
+    fn default() -> Self {
+        Self {
+            samples: [Duration::ZERO; SCAN_DURATION_SAMPLES],
+            len: 0,
+            next: 0,
+        }
+    }
+}
+
+impl ScanDurations {
+    pub fn record(&mut self, duration: Duration) {
+        self.samples[self.next] = duration;
+        self.next = (self.next + 1) % SCAN_DURATION_SAMPLES;
+        self.len = (self.len + 1).min(SCAN_DURATION_SAMPLES);
+    }
+
+    // nearest rank percentile, None until a scan was recorded
+    pub fn percentile(&self, percentile: u8) -> Option<Duration> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let mut sorted = self.samples;
+        let sorted = &mut sorted[..self.len];
+        sorted.sort_unstable();
+
+        let rank = (usize::from(percentile.min(100)) * self.len).div_ceil(100);
+
+        Some(sorted[rank.max(1) - 1])
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.samples[..self.len].iter().max().copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WatcherStats {
+    scan_durations: ScanDurations,
+    scans: u64,
+    coalesced_events: u64,
+    tracked_bytes: u64,
+    dropped_events: u64,
+}
+
+impl WatcherStats {
+    pub fn record_scan(&mut self, duration: Duration) {
+        self.scan_durations.record(duration);
+        self.scans += 1;
+    }
+
+    // the polls since the watcher started, the baseline scan left out
+    pub fn scans(&self) -> u64 {
+        self.scans
+    }
+
+    pub fn scan_duration_p50(&self) -> Option<Duration> {
+        self.scan_durations.percentile(50)
+    }
+
+    pub fn scan_duration_p99(&self) -> Option<Duration> {
+        self.scan_durations.percentile(99)
+    }
+
+    pub fn scan_duration_max(&self) -> Option<Duration> {
+        self.scan_durations.max()
+    }
+
+    pub fn record_coalesced(&mut self) {
+        self.coalesced_events += 1;
+    }
+
+    // operations merged into a held back one because of the max event rate
+    pub fn coalesced_events(&self) -> u64 {
+        self.coalesced_events
+    }
+
+    pub fn record_dropped_events(&mut self, count: u64) {
+        self.dropped_events += count;
+    }
+
+    // events a bounded receiver had no room for, see FileWatcher::events_rx_bounded
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped_events
+    }
+
+    pub fn set_tracked_bytes(&mut self, tracked_bytes: u64) {
+        self.tracked_bytes = tracked_bytes;
+    }
+
+    // the summed size of the watched files as of the latest poll
+    pub fn tracked_bytes(&self) -> u64 {
+        self.tracked_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_duration_percentiles_use_the_latest_samples() {
+        let mut stats = WatcherStats::default();
+        assert_eq!(stats.scan_duration_p50(), None);
+        assert_eq!(stats.scan_duration_max(), None);
+
+        //recorded out of order, percentiles must not depend on it
+        for ms in (1..=100).rev() {
+            stats.record_scan(Duration::from_millis(ms));
+        }
+
+        assert_eq!(stats.scan_duration_p50(), Some(Duration::from_millis(50)));
+        assert_eq!(stats.scan_duration_p99(), Some(Duration::from_millis(99)));
+        assert_eq!(stats.scan_duration_max(), Some(Duration::from_millis(100)));
+
+        //older samples fall out of the buffer
+        for _ in 0..SCAN_DURATION_SAMPLES {
+            stats.record_scan(Duration::from_millis(7));
+        }
+
+        assert_eq!(stats.scan_duration_p50(), Some(Duration::from_millis(7)));
+        assert_eq!(stats.scan_duration_p99(), Some(Duration::from_millis(7)));
+        assert_eq!(stats.scan_duration_max(), Some(Duration::from_millis(7)));
+    }
+}
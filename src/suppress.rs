@@ -0,0 +1,151 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::events::Named;
+use crate::search_dir::RenamedFileEntry;
+use crate::OPERATION;
+
+#[derive(Debug)]
+struct Suppression {
+    id: u64,
+    path: PathBuf,
+    descendants: bool,
+    //None while a guard holds it
+    until: Option<Instant>,
+}
+
+impl Suppression {
+    fn covers(&self, name: &str) -> bool {
+        let path = Path::new(name);
+
+        match self.descendants {
+            true => path.starts_with(&self.path),
+            false => path == self.path,
+        }
+    }
+}
+
+// the paths whose events the events thread drops, shared with the watcher that adds them
+#[derive(Debug, Clone, Default)]
+pub struct Suppressions {
+    entries: Arc<Mutex<Vec<Suppression>>>,
+    last_id: Arc<Mutex<u64>>,
+}
+
+impl Suppressions {
+    pub fn add(&self, path: PathBuf, descendants: bool, until: Option<Instant>) -> u64 {
+        let mut last_id = self.last_id.lock().unwrap();
+        *last_id += 1;
+
+        self.entries.lock().unwrap().push(Suppression {
+            id: *last_id,
+            path,
+            descendants,
+            until,
+        });
+
+        *last_id
+    }
+
+    fn release(&self, id: u64, until: Instant) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
+            entry.until = Some(until);
+        }
+    }
+
+    // the operation without the files of the suppressed paths, None when none are left
+    pub fn filter(&self, op: OPERATION) -> Option<OPERATION> {
+        let mut entries = self.entries.lock().unwrap();
+
+        let now = Instant::now();
+        entries.retain(|e| e.until.is_none_or(|until| until > now));
+        if entries.is_empty() {
+            return Some(op);
+        }
+
+        let suppressed = |name: &str| entries.iter().any(|e| e.covers(name));
+        let renamed = |files: HashSet<RenamedFileEntry>| -> HashSet<RenamedFileEntry> {
+            files
+                .into_iter()
+                .filter(|f| !suppressed(f.name()) && !suppressed(f.old_name()))
+                .collect()
+        };
+
+        let op = match op {
+            OPERATION::CREATE(files) => OPERATION::CREATE(Self::retain(files, &suppressed)),
+            OPERATION::CHANGE(files) => OPERATION::CHANGE(Self::retain(files, &suppressed)),
+            OPERATION::DELETE(files) => OPERATION::DELETE(Self::retain(files, &suppressed)),
+            OPERATION::RENAME(files) => OPERATION::RENAME(renamed(files)),
+            OPERATION::BATCH {
+                created,
+                changed,
+                deleted,
+                renamed: renamed_files,
+            } => OPERATION::BATCH {
+                created: Self::retain(created, &suppressed),
+                changed: Self::retain(changed, &suppressed),
+                deleted: Self::retain(deleted, &suppressed),
+                renamed: renamed(renamed_files),
+            },
+            OPERATION::ERROR(error) => return Some(OPERATION::ERROR(error)),
+        };
+
+        let is_empty = match &op {
+            OPERATION::CREATE(files) | OPERATION::DELETE(files) => files.is_empty(),
+            OPERATION::CHANGE(files) => files.is_empty(),
+            OPERATION::RENAME(files) => files.is_empty(),
+            OPERATION::BATCH {
+                created,
+                changed,
+                deleted,
+                renamed,
+            } => {
+                created.is_empty() && changed.is_empty() && deleted.is_empty() && renamed.is_empty()
+            }
+            OPERATION::ERROR(_) => false,
+        };
+
+        (!is_empty).then_some(op)
+    }
+
+    fn retain<T: Named + Eq + Hash>(
+        files: HashSet<T>,
+        suppressed: &impl Fn(&str) -> bool,
+    ) -> HashSet<T> {
+        files
+            .into_iter()
+            .filter(|f| !suppressed(f.name()))
+            .collect()
+    }
+}
+
+// suppresses a path while it lives; once dropped the path stays suppressed for a grace period, so
+// the poll that sees the writes made under the guard is dropped as well
+#[must_use = "the path is no longer suppressed once the guard is dropped"]
+#[derive(Debug)]
+pub struct SuppressionGuard {
+    suppressions: Suppressions,
+    id: u64,
+    grace: Duration,
+}
+
+impl SuppressionGuard {
+    pub(crate) fn new(suppressions: Suppressions, id: u64, grace: Duration) -> Self {
+        Self {
+            suppressions,
+            id,
+            grace,
+        }
+    }
+}
+
+impl Drop for SuppressionGuard {
+    fn drop(&mut self) {
+        self.suppressions
+            .release(self.id, Instant::now() + self.grace);
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::mem;
+use std::time::{Duration, Instant};
+
+use crate::OPERATION;
+
+// a token bucket allowing `rate` operations per second, operations over the rate are held back
+// and merged into the held back operation of the same kind, unless a later held back operation
+// touches the same paths and merging would reorder them
+#[derive(Debug)]
+pub struct EventThrottle {
+    rate: f64,
+    tokens: f64,
+    refilled_at: Instant,
+    held: Vec<OPERATION>,
+}
+
+impl EventThrottle {
+    pub fn new(rate: u32) -> Self {
+        let rate = f64::from(rate.max(1));
+
+        Self {
+            rate,
+            tokens: rate,
+            refilled_at: Instant::now(),
+            held: vec![],
+        }
+    }
+
+    // holds the operation back until release(), true when it was merged into a held back one
+    pub fn push(&mut self, op: OPERATION) -> bool {
+        let paths = op.paths();
+        let last = self.held.iter().rposition(|held| {
+            mem::discriminant(held) == mem::discriminant(&op) || !held.paths().is_disjoint(&paths)
+        });
+
+        let op = match last {
+            Some(index) => match Self::merge(&mut self.held[index], op) {
+                Some(op) => op,
+                None => return true,
+            },
+            None => op,
+        };
+
+        self.held.push(op);
+        false
+    }
+
+    // the held back operations a token is available for, in the order they were held
+    pub fn release(&mut self) -> Vec<OPERATION> {
+        let now = Instant::now();
+        let refill = now.duration_since(self.refilled_at).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.rate);
+        self.refilled_at = now;
+
+        let count = (self.tokens as usize).min(self.held.len());
+        self.tokens -= count as f64;
+
+        self.held.drain(..count).collect()
+    }
+
+    // every held back operation, whatever the rate
+    pub fn drain(&mut self) -> Vec<OPERATION> {
+        std::mem::take(&mut self.held)
+    }
+
+    // how long until the next held back operation can be released, None when none is held
+    pub fn next_release_in(&self) -> Option<Duration> {
+        if self.held.is_empty() {
+            return None;
+        }
+
+        let missing = (1.0 - self.tokens).max(0.0);
+        let refilled_in = Duration::from_secs_f64(missing / self.rate);
+
+        Some(refilled_in.saturating_sub(self.refilled_at.elapsed()))
+    }
+
+    // merges operations of the same kind, gives the operation back otherwise. Errors are never merged.
+    fn merge(held: &mut OPERATION, op: OPERATION) -> Option<OPERATION> {
+        match (held, op) {
+            (OPERATION::CREATE(held), OPERATION::CREATE(files)) => Self::replace_all(held, files),
+            (OPERATION::CHANGE(held), OPERATION::CHANGE(files)) => Self::replace_all(held, files),
+            (OPERATION::DELETE(held), OPERATION::DELETE(files)) => Self::replace_all(held, files),
+            (OPERATION::RENAME(held), OPERATION::RENAME(files)) => Self::replace_all(held, files),
+            (
+                OPERATION::BATCH {
+                    created,
+                    changed,
+                    deleted,
+                    renamed,
+                },
+                OPERATION::BATCH {
+                    created: new_created,
+                    changed: new_changed,
+                    deleted: new_deleted,
+                    renamed: new_renamed,
+                },
+            ) => {
+                Self::replace_all(created, new_created);
+                Self::replace_all(changed, new_changed);
+                Self::replace_all(deleted, new_deleted);
+                Self::replace_all(renamed, new_renamed);
+            }
+            (_, op) => return Some(op),
+        }
+
+        None
+    }
+
+    // the latest state of a file wins
+    fn replace_all<T: Eq + Hash>(held: &mut HashSet<T>, files: HashSet<T>) {
+        for file in files {
+            held.replace(file);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::search_dir::File;
+
+    fn files(names: &[&str]) -> HashSet<File> {
+        names
+            .iter()
+            .map(|name| File::from_manifest(Path::new(name), SystemTime::now(), 0))
+            .collect()
+    }
+
+    #[test]
+    fn held_operations_are_merged_by_kind_in_first_held_order() {
+        let mut throttle = EventThrottle::new(1);
+        assert!(!throttle.push(OPERATION::CREATE(files(&["a"]))));
+        assert!(!throttle.push(OPERATION::DELETE(files(&["b"]))));
+        assert!(throttle.push(OPERATION::CREATE(files(&["c"]))));
+
+        assert_eq!(
+            throttle.release(),
+            vec![OPERATION::CREATE(files(&["a", "c"]))]
+        );
+        assert!(throttle.next_release_in().unwrap() > Duration::from_millis(500));
+        assert_eq!(throttle.drain(), vec![OPERATION::DELETE(files(&["b"]))]);
+        assert_eq!(throttle.next_release_in(), None);
+    }
+
+    #[test]
+    fn operations_are_not_merged_across_a_later_one_on_the_same_path() {
+        let mut throttle = EventThrottle::new(1);
+        assert!(!throttle.push(OPERATION::CREATE(files(&["a"]))));
+        assert!(!throttle.push(OPERATION::DELETE(files(&["a"]))));
+        assert!(!throttle.push(OPERATION::CREATE(files(&["a"]))));
+        assert!(throttle.push(OPERATION::CREATE(files(&["b"]))));
+
+        assert_eq!(
+            throttle.drain(),
+            vec![
+                OPERATION::CREATE(files(&["a"])),
+                OPERATION::DELETE(files(&["a"])),
+                OPERATION::CREATE(files(&["a", "b"])),
+            ]
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+
+use crate::OPERATION;
+
+// holds operations until the next boundary of wall clock aligned ticks, i.e. the next whole
+// second for a tick of a second, and releases them together as a single BATCH, or as a single
+// operation of each kind without combined events; an operation on a path already in the batch
+// starts a new one so the operations of a file keep their order
+#[derive(Debug)]
+pub struct EventTick {
+    tick: Duration,
+    combined: bool,
+    //the operations of each batch, oldest first
+    held: Vec<Vec<OPERATION>>,
+    //every path of the last batch
+    held_paths: HashSet<String>,
+    //the boundary the held operations are released at, None when none are held
+    due: Option<SystemTime>,
+}
+
+impl EventTick {
+    pub fn new(tick: Duration, combined: bool) -> Self {
+        Self {
+            tick: tick.max(Duration::from_millis(1)),
+            combined,
+            held: vec![],
+            held_paths: HashSet::new(),
+            due: None,
+        }
+    }
+
+    pub fn push(&mut self, op: OPERATION) {
+        if self.due.is_none() {
+            self.due = Some(Self::next_boundary(SystemTime::now(), self.tick));
+        }
+
+        let paths: HashSet<String> = op.paths().into_iter().map(str::to_string).collect();
+        if self.held.is_empty() || !self.held_paths.is_disjoint(&paths) {
+            self.held.push(vec![]);
+            self.held_paths.clear();
+        }
+        self.held_paths.extend(paths);
+        let held = self.held.last_mut().unwrap();
+
+        //a batch is taken apart to be combined with the rest of the tick
+        match op {
+            OPERATION::BATCH {
+                created,
+                changed,
+                deleted,
+                renamed,
+            } => held.extend([
+                OPERATION::CREATE(created),
+                OPERATION::CHANGE(changed),
+                OPERATION::DELETE(deleted),
+                OPERATION::RENAME(renamed),
+            ]),
+            op => held.push(op),
+        }
+    }
+
+    // the held operations once their boundary passed, errors apart from the batch
+    pub fn release(&mut self) -> Vec<OPERATION> {
+        match self.due {
+            Some(due) if due <= SystemTime::now() => self.drain(),
+            _ => vec![],
+        }
+    }
+
+    // every held operation, whether the boundary passed or not
+    pub fn drain(&mut self) -> Vec<OPERATION> {
+        self.due = None;
+        self.held_paths.clear();
+
+        let released = std::mem::take(&mut self.held)
+            .into_iter()
+            .flat_map(OPERATION::combine);
+        match self.combined {
+            true => released.collect(),
+            false => released.flat_map(Self::split).collect(),
+        }
+    }
+
+    // the kinds of a batch as operations of their own, in the order the callbacks are called
+    fn split(op: OPERATION) -> Vec<OPERATION> {
+        match op {
+            OPERATION::BATCH {
+                created,
+                changed,
+                deleted,
+                renamed,
+            } => {
+                let mut result = vec![];
+                if !created.is_empty() {
+                    result.push(OPERATION::CREATE(created));
+                }
+                if !changed.is_empty() {
+                    result.push(OPERATION::CHANGE(changed));
+                }
+                if !deleted.is_empty() {
+                    result.push(OPERATION::DELETE(deleted));
+                }
+                if !renamed.is_empty() {
+                    result.push(OPERATION::RENAME(renamed));
+                }
+
+                result
+            }
+            op => vec![op],
+        }
+    }
+
+    // how long until the held operations are released, None when none are held
+    pub fn next_release_in(&self) -> Option<Duration> {
+        let due = self.due?;
+
+        Some(
+            due.duration_since(SystemTime::now())
+                .unwrap_or(Duration::ZERO),
+        )
+    }
+
+    fn next_boundary(now: SystemTime, tick: Duration) -> SystemTime {
+        let since_epoch = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let ticks = since_epoch.as_nanos() / tick.as_nanos() + 1;
+
+        SystemTime::UNIX_EPOCH + Duration::from_nanos((ticks * tick.as_nanos()) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::search_dir::File;
+
+    #[test]
+    fn boundaries_are_aligned_to_the_tick() {
+        let second = Duration::from_secs(1);
+        let at = |millis: u64| SystemTime::UNIX_EPOCH + Duration::from_millis(millis);
+
+        assert_eq!(EventTick::next_boundary(at(1_250), second), at(2_000));
+        assert_eq!(EventTick::next_boundary(at(1_999), second), at(2_000));
+        //an operation right at a boundary waits for the next one
+        assert_eq!(EventTick::next_boundary(at(2_000), second), at(3_000));
+        assert_eq!(
+            EventTick::next_boundary(at(1_250), Duration::from_millis(100)),
+            at(1_300)
+        );
+    }
+
+    #[test]
+    fn operations_on_the_same_path_are_released_in_order() {
+        let file = |name: &str| File::from_manifest(Path::new(name), SystemTime::UNIX_EPOCH, 0);
+        let mut tick = EventTick::new(Duration::from_secs(60), true);
+        tick.push(OPERATION::CREATE(HashSet::from([file("a.txt")])));
+        tick.push(OPERATION::CREATE(HashSet::from([file("b.txt")])));
+        tick.push(OPERATION::DELETE(HashSet::from([file("a.txt")])));
+
+        let released: Vec<(usize, usize)> = tick
+            .drain()
+            .iter()
+            .map(|op| match op {
+                OPERATION::BATCH {
+                    created, deleted, ..
+                } => (created.len(), deleted.len()),
+                op => panic!("expected a batch, got {:?}", op),
+            })
+            .collect();
+        //the delete of a.txt cannot join the batch holding its create
+        assert_eq!(released, vec![(2, 0), (0, 1)]);
+        assert!(tick.drain().is_empty());
+    }
+
+    #[test]
+    fn without_combined_events_a_batch_is_released_by_kind() {
+        let file = |name: &str| File::from_manifest(Path::new(name), SystemTime::UNIX_EPOCH, 0);
+        let mut tick = EventTick::new(Duration::from_secs(60), false);
+        tick.push(OPERATION::CREATE(HashSet::from([file("a.txt")])));
+        tick.push(OPERATION::CREATE(HashSet::from([file("b.txt")])));
+        tick.push(OPERATION::DELETE(HashSet::from([file("c.txt")])));
+        tick.push(OPERATION::DELETE(HashSet::from([file("a.txt")])));
+
+        let released: Vec<(&str, usize)> = tick
+            .drain()
+            .iter()
+            .map(|op| match op {
+                OPERATION::CREATE(files) => ("created", files.len()),
+                OPERATION::DELETE(files) => ("deleted", files.len()),
+                op => panic!("expected a create or a delete, got {:?}", op),
+            })
+            .collect();
+        assert_eq!(
+            released,
+            vec![("created", 2), ("deleted", 1), ("deleted", 1)]
+        );
+    }
+}
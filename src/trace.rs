@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use crate::OPERATION;
+
+// the span of one poll, the dispatch of its operations are child spans. Without the `tracing`
+// feature it records nothing.
+#[derive(Debug, Clone)]
+pub struct PollSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl PollSpan {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::debug_span!(
+                "poll",
+                scanned = tracing::field::Empty,
+                duration_us = tracing::field::Empty
+            ),
+        }
+    }
+
+    // operations merged across polls by the max event rate belong to none of them
+    pub fn none() -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::Span::none(),
+        }
+    }
+
+    pub fn in_scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+
+        f()
+    }
+
+    // a trace event for every file of the operations and a debug summary of the poll
+    pub fn record(&self, scanned: usize, duration: Duration, operations: &[OPERATION]) {
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("scanned", scanned as u64);
+            self.span.record("duration_us", duration.as_micros() as u64);
+
+            let _entered = self.span.enter();
+            for event in operations
+                .iter()
+                .flat_map(crate::WatchEvent::from_operation)
+            {
+                tracing::trace!(?event, "file");
+            }
+            tracing::debug!(scanned, operations = operations.len(), "poll");
+        }
+
+        #[cfg(not(feature = "tracing"))]
+        let _ = (scanned, duration, operations);
+    }
+
+    // entered until the guard is dropped
+    pub fn dispatch(&self, operation: &OPERATION) -> Dispatching {
+        #[cfg(not(feature = "tracing"))]
+        let _ = operation;
+
+        Dispatching {
+            #[cfg(feature = "tracing")]
+            _entered: tracing::debug_span!(parent: &self.span, "dispatch", kind = operation.kind().as_str())
+                .entered(),
+        }
+    }
+}
+
+pub struct Dispatching {
+    #[cfg(feature = "tracing")]
+    _entered: tracing::span::EnteredSpan,
+}
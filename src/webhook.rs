@@ -0,0 +1,216 @@
+use std::collections::HashSet;
+use std::fmt::Error;
+use std::io;
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::thread;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::callback::Sink;
+use crate::search_dir::{ChangedFile, File, RenamedFileEntry};
+use crate::{EventKind, OPERATION};
+
+//the payloads waiting to be posted, more are reported as errors and dropped
+const WEBHOOK_QUEUE: usize = 256;
+const WEBHOOK_ATTEMPTS: u32 = 3;
+//doubled after every failed attempt
+const WEBHOOK_BACKOFF: Duration = Duration::from_millis(100);
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// the endpoint of a webhook, http or https
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookUrl(String);
+
+impl WebhookUrl {
+    pub fn parse(url: &str) -> io::Result<Self> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("'{}' is not an http:// or https:// webhook url!", url),
+            )
+        };
+
+        let request_url = ureq::post(url).request_url().map_err(|_| invalid())?;
+        match request_url.scheme() {
+            "http" | "https" => Ok(Self(url.to_string())),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+// the JSON posted for an operation, one per kind of operation
+#[derive(Debug, Serialize)]
+struct Payload {
+    kind: &'static str,
+    files: Vec<PayloadFile>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum PayloadFile {
+    File {
+        name: String,
+        size: u64,
+    },
+    Changed {
+        name: String,
+        size: u64,
+        previous_size: u64,
+    },
+    Renamed {
+        name: String,
+        old_name: String,
+    },
+}
+
+// posts the operations as JSON from a thread of its own, so a slow endpoint only fills the queue
+// and never holds up the events thread
+pub struct Webhook {
+    queue: SyncSender<Payload>,
+    sink: Option<Sink>,
+}
+
+impl Webhook {
+    // the posting thread ends once the webhook is dropped and the queue is drained. The posts
+    // that failed every retry, and the operations dropped because too many posts were waiting,
+    // are reported to the sink's on_error
+    pub fn spawn(url: WebhookUrl, sink: Option<Sink>) -> Self {
+        let (queue, payloads) = sync_channel::<Payload>(WEBHOOK_QUEUE);
+
+        let agent = ureq::AgentBuilder::new().timeout(WEBHOOK_TIMEOUT).build();
+        let thread_sink = sink.clone();
+        thread::spawn(move || {
+            for payload in payloads {
+                if !Self::post_with_retry(&agent, &url, &payload) {
+                    Self::report_error(&thread_sink);
+                }
+            }
+        });
+
+        Self { queue, sink }
+    }
+
+    // a batch is posted as one payload per kind of operation in it
+    pub fn send(&self, op: &OPERATION) {
+        for payload in Self::payloads(op) {
+            if self.queue.try_send(payload).is_err() {
+                Self::report_error(&self.sink);
+            }
+        }
+    }
+
+    fn report_error(sink: &Option<Sink>) {
+        if let Some(sink) = sink {
+            sink.send(&OPERATION::ERROR(Error));
+        }
+    }
+
+    // whether the payload was posted, a status outside 2xx fails the attempt like a lost connection
+    fn post_with_retry(agent: &ureq::Agent, url: &WebhookUrl, payload: &Payload) -> bool {
+        let mut backoff = WEBHOOK_BACKOFF;
+        let mut attempt = 1;
+
+        loop {
+            match agent.post(&url.0).send_json(payload) {
+                Ok(_) => return true,
+                Err(_) if attempt < WEBHOOK_ATTEMPTS => {
+                    thread::sleep(backoff);
+                    backoff = backoff.saturating_mul(2);
+                    attempt += 1;
+                }
+                Err(_) => return false,
+            }
+        }
+    }
+
+    fn payloads(op: &OPERATION) -> Vec<Payload> {
+        let payload = |kind: EventKind, files: Vec<PayloadFile>| Payload {
+            kind: kind.as_str(),
+            files,
+        };
+        let files = |files: &HashSet<File>| -> Vec<PayloadFile> {
+            let mut files: Vec<&File> = files.iter().collect();
+            files.sort_by(|a, b| a.name().cmp(b.name()));
+            files
+                .iter()
+                .map(|f| PayloadFile::File {
+                    name: f.name().to_string(),
+                    size: f.size(),
+                })
+                .collect()
+        };
+        let changed = |files: &HashSet<ChangedFile>| -> Vec<PayloadFile> {
+            let mut files: Vec<&ChangedFile> = files.iter().collect();
+            files.sort_by(|a, b| a.name().cmp(b.name()));
+            files
+                .iter()
+                .map(|f| PayloadFile::Changed {
+                    name: f.name().to_string(),
+                    size: f.file().size(),
+                    previous_size: f.previous_size(),
+                })
+                .collect()
+        };
+        let renamed = |files: &HashSet<RenamedFileEntry>| -> Vec<PayloadFile> {
+            let mut files: Vec<&RenamedFileEntry> = files.iter().collect();
+            files.sort_by(|a, b| a.name().cmp(b.name()));
+            files
+                .iter()
+                .map(|f| PayloadFile::Renamed {
+                    name: f.name().to_string(),
+                    old_name: f.old_name().to_string(),
+                })
+                .collect()
+        };
+
+        match op {
+            OPERATION::CREATE(created) => vec![payload(EventKind::Created, files(created))],
+            OPERATION::CHANGE(data) => vec![payload(EventKind::Changed, changed(data))],
+            OPERATION::DELETE(deleted) => vec![payload(EventKind::Deleted, files(deleted))],
+            OPERATION::RENAME(data) => vec![payload(EventKind::Renamed, renamed(data))],
+            OPERATION::BATCH {
+                created,
+                changed: changes,
+                deleted,
+                renamed: renames,
+            } => [
+                (EventKind::Created, files(created)),
+                (EventKind::Changed, changed(changes)),
+                (EventKind::Deleted, files(deleted)),
+                (EventKind::Renamed, renamed(renames)),
+            ]
+            .into_iter()
+            .filter(|(_, files)| !files.is_empty())
+            .map(|(kind, files)| payload(kind, files))
+            .collect(),
+            OPERATION::ERROR(_) => vec![payload(EventKind::Error, vec![])],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_must_be_http_or_https() {
+        assert!(WebhookUrl::parse("http://localhost").is_ok());
+        assert!(WebhookUrl::parse("https://127.0.0.1:8443/hooks/files").is_ok());
+
+        assert!(WebhookUrl::parse("ftp://localhost").is_err());
+        assert!(WebhookUrl::parse("localhost/hooks").is_err());
+        assert!(WebhookUrl::parse("http://localhost:port").is_err());
+    }
+
+    #[test]
+    fn payloads_are_serialized_as_json() {
+        let renamed = HashSet::from([RenamedFileEntry::new("b\"c.txt", "a\\b.txt")]);
+        let payloads = Webhook::payloads(&OPERATION::RENAME(renamed));
+
+        assert_eq!(
+            ureq::serde_json::to_string(&payloads[0]).unwrap(),
+            r#"{"kind":"renamed","files":[{"name":"b\"c.txt","old_name":"a\\b.txt"}]}"#
+        );
+    }
+}